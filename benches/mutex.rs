@@ -0,0 +1,136 @@
+//! Compares the mutexes with `futures::lock::Mutex` and `tokio::sync::Mutex`.
+//!
+//! Run with `cargo bench --bench mutex`. Every mutex is driven by the same
+//! `futures` executors, so the numbers differ only by the lock itself.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use criterion::measurement::WallTime;
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+use futures::executor::{block_on, LocalPool, ThreadPool};
+use futures::future::join_all;
+use futures::task::{LocalSpawnExt, SpawnExt};
+use futures_test::future::FutureTestExt;
+
+use futures_mutex::{sync, unsync, WakePolicy};
+
+const TASKS: u64 = 16;
+
+const POLICIES: [WakePolicy; 3] = [WakePolicy::Handoff, WakePolicy::WakeOne, WakePolicy::WakeAll];
+
+/// Locks and unlocks in a loop, with nobody else around.
+macro_rules! uncontended {
+    ($group:expr, $name:expr, $mutex:expr) => {{
+        let mutex = $mutex;
+        $group.bench_function($name, |b| {
+            b.iter_custom(|iters| {
+                let start = Instant::now();
+                block_on(async {
+                    for _ in 0..iters {
+                        drop(mutex.lock().await);
+                    }
+                });
+                start.elapsed()
+            })
+        });
+    }};
+}
+
+/// Two tasks on one thread, each holding the lock across a yield, so that
+/// the lock changes hands on every acquisition.
+macro_rules! ping_pong {
+    ($group:expr, $name:expr, $mutex:expr) => {{
+        $group.bench_function($name, |b| {
+            b.iter_custom(|iters| {
+                let mutex = Rc::new($mutex);
+                let mut pool = LocalPool::new();
+                for _ in 0..2 {
+                    let mutex = mutex.clone();
+                    let task = async move {
+                        for _ in 0..iters {
+                            let guard = mutex.lock().await;
+                            async {}.pending_once().await;
+                            drop(guard);
+                        }
+                    };
+                    pool.spawner().spawn_local(task).unwrap();
+                }
+                let start = Instant::now();
+                pool.run();
+                start.elapsed()
+            })
+        });
+    }};
+}
+
+/// Many tasks on a thread pool, contending with short critical sections.
+macro_rules! contention {
+    ($group:expr, $pool:expr, $name:expr, $mutex:expr) => {{
+        $group.bench_function($name, |b| {
+            b.iter_custom(|iters| {
+                let mutex = Arc::new($mutex);
+                let handles = (0..TASKS)
+                    .map(|_| {
+                        let mutex = mutex.clone();
+                        let task = async move {
+                            for _ in 0..iters {
+                                drop(mutex.lock().await);
+                            }
+                        };
+                        $pool.spawn_with_handle(task).unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                let start = Instant::now();
+                block_on(join_all(handles));
+                start.elapsed()
+            })
+        });
+    }};
+}
+
+fn with_locks<'a>(c: &'a mut Criterion, name: &str, locks: u64) -> BenchmarkGroup<'a, WallTime> {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(locks));
+    group
+}
+
+fn bench_uncontended(c: &mut Criterion) {
+    let mut group = with_locks(c, "uncontended", 1);
+    uncontended!(group, "futures::lock::Mutex", futures::lock::Mutex::new(0));
+    uncontended!(group, "tokio::sync::Mutex", tokio::sync::Mutex::new(0));
+    uncontended!(group, "unsync::Mutex", unsync::Mutex::new(0));
+    uncontended!(group, "sync::Mutex", sync::Mutex::new(0));
+    group.finish();
+}
+
+fn bench_ping_pong(c: &mut Criterion) {
+    let mut group = with_locks(c, "ping-pong", 2);
+    ping_pong!(group, "futures::lock::Mutex", futures::lock::Mutex::new(0));
+    ping_pong!(group, "tokio::sync::Mutex", tokio::sync::Mutex::new(0));
+    for &policy in &POLICIES {
+        let name = format!("unsync::Mutex/{:?}", policy);
+        ping_pong!(group, &name, unsync::Mutex::with_policy(0, policy));
+    }
+    for &policy in &POLICIES {
+        let name = format!("sync::Mutex/{:?}", policy);
+        ping_pong!(group, &name, sync::Mutex::with_policy(0, policy));
+    }
+    group.finish();
+}
+
+fn bench_contention(c: &mut Criterion) {
+    let pool = ThreadPool::new().unwrap();
+    let mut group = with_locks(c, "contention", TASKS);
+    contention!(group, pool, "futures::lock::Mutex", futures::lock::Mutex::new(0));
+    contention!(group, pool, "tokio::sync::Mutex", tokio::sync::Mutex::new(0));
+    for &policy in &POLICIES {
+        let name = format!("sync::Mutex/{:?}", policy);
+        contention!(group, pool, &name, sync::Mutex::with_policy(0, policy));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_uncontended, bench_ping_pong, bench_contention);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use futures::executor::{block_on, ThreadPool};
+use futures::future::join_all;
+use futures::task::SpawnExt;
+use rand::prelude::*;
+
+use futures_mutex::sync::Mutex;
+use futures_test::future::FutureTestExt;
+
+async fn jitter() {
+    let num = thread_rng().gen_range(0..10);
+    for _ in 0..num {
+        async {}.pending_once().await;
+    }
+}
+
+fn main() {
+    let pool = ThreadPool::new().unwrap();
+    let resources = (0..5_i32).map(|i| Arc::new(Mutex::new(i))).collect::<Vec<_>>();
+    let mut handles = Vec::new();
+    for i in 0..5 {
+        let (res0, res1) = if i == 4 {
+            (resources[0].clone(), resources[4].clone())
+        } else {
+            (resources[i].clone(), resources[i + 1].clone())
+        };
+        let handle = pool.spawn_with_handle(async move {
+            for _ in 0..100 {
+                let lock0 = res0.lock().await.unwrap();
+                jitter().await;
+                eprintln!("Thread {}: acquired {}", i, *lock0);
+
+                let lock1 = res1.lock().await.unwrap();
+                jitter().await;
+                eprintln!("Thread {}: acquired {}", i, *lock1);
+
+                drop(lock1);
+                jitter().await;
+                drop(lock0);
+                jitter().await;
+            }
+            println!("Thread {}: done!", i);
+        }).unwrap();
+        handles.push(handle);
+    }
+    block_on(join_all(handles));
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::prelude::*;
+
+use futures_mutex::sync::Mutex;
+
+async fn jitter() {
+    let millis = thread_rng().gen_range(0..3);
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+}
+
+#[tokio::main]
+async fn main() {
+    let resources = (0..5_i32).map(|i| Arc::new(Mutex::new(i))).collect::<Vec<_>>();
+    let mut handles = Vec::new();
+    for i in 0..5 {
+        let (res0, res1) = if i == 4 {
+            (resources[0].clone(), resources[4].clone())
+        } else {
+            (resources[i].clone(), resources[i + 1].clone())
+        };
+        let handle = tokio::spawn(async move {
+            for _ in 0..100 {
+                let lock0 = res0.lock().await.unwrap();
+                jitter().await;
+                eprintln!("Task {}: acquired {}", i, *lock0);
+
+                let lock1 = res1.lock().await.unwrap();
+                jitter().await;
+                eprintln!("Task {}: acquired {}", i, *lock1);
+
+                drop(lock1);
+                jitter().await;
+                drop(lock0);
+                jitter().await;
+            }
+            println!("Task {}: done!", i);
+        });
+        handles.push(handle);
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
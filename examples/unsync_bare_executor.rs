@@ -0,0 +1,79 @@
+//! The mutex needs nothing from the executor but a waker:
+//! here it runs on a minimal run-queue executor built on `std` alone.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use futures_mutex::unsync::Mutex;
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<StdMutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.lock().unwrap().push_back(self.id);
+    }
+}
+
+fn run(tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>) {
+    let queue = Arc::new(StdMutex::new((0..tasks.len()).collect::<VecDeque<_>>()));
+    let mut tasks = tasks.into_iter().map(Some).collect::<Vec<_>>();
+    loop {
+        let id = match queue.lock().unwrap().pop_front() {
+            Some(id) => id,
+            None => break,
+        };
+        if let Some(task) = &mut tasks[id] {
+            let queue = queue.clone();
+            let waker = Waker::from(Arc::new(TaskWaker { id, queue }));
+            if task.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                tasks[id] = None;
+            }
+        }
+    }
+}
+
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn main() {
+    let mutex = Rc::new(Mutex::new(Vec::new()));
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let tasks = (0..3)
+        .map(|i| {
+            let mutex = mutex.clone();
+            let log = log.clone();
+            Box::pin(async move {
+                for j in 0..3 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    YieldNow(false).await;
+                    guard.push((i, j));
+                    log.borrow_mut().push(format!("task {} step {}", i, j));
+                }
+            }) as Pin<Box<dyn Future<Output = ()>>>
+        })
+        .collect();
+    run(tasks);
+    for line in log.borrow().iter() {
+        println!("{}", line);
+    }
+    assert_eq!(mutex.try_lock().unwrap().len(), 9);
+}
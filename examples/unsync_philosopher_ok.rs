@@ -1,22 +1,22 @@
-#![feature(async_await, await_macro, pin, futures_api)]
-
 use std::rc::Rc;
 
-use futures::prelude::*;
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
 use rand::prelude::*;
 
-use tokio::runtime::current_thread::{Runtime, spawn};
 use futures_mutex::unsync::Mutex;
 use futures_test::future::FutureTestExt;
 
 async fn jitter() {
-    let num = thread_rng().gen_range(0, 10);
+    let num = thread_rng().gen_range(0..10);
     for _ in 0..num {
-        await!(async {}.pending_once());
+        async {}.pending_once().await;
     }
 }
 
-async fn main2() {
+fn main() {
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
     let resources = (0..5_i32).map(|i| Rc::new(Mutex::new(i))).collect::<Vec<_>>();
     for i in 0..5 {
         let (res0, res1) = if i == 4 {
@@ -24,32 +24,23 @@ async fn main2() {
         } else {
             (resources[i].clone(), resources[i + 1].clone())
         };
-        spawn(async move {
+        spawner.spawn_local(async move {
             for _ in 0..100 {
-                let lock0 = await!(res0.lock()).unwrap();
-                await!(jitter());
+                let lock0 = res0.lock().await.unwrap();
+                jitter().await;
                 eprintln!("Thread {}: acquired {}", i, *lock0);
 
-                let lock1 = await!(res1.lock()).unwrap();
-                await!(jitter());
+                let lock1 = res1.lock().await.unwrap();
+                jitter().await;
                 eprintln!("Thread {}: acquired {}", i, *lock1);
 
                 drop(lock1);
-                await!(jitter());
+                jitter().await;
                 drop(lock0);
-                await!(jitter());
+                jitter().await;
             }
             println!("Thread {}: done!", i);
-            Ok(())
-        }.boxed().compat());
+        }).unwrap();
     }
-}
-
-fn main() {
-    let mut rt = Runtime::new().unwrap();
-    rt.spawn(async {
-        await!(main2());
-        Ok(())
-    }.boxed().compat());
-    rt.run().unwrap();
+    pool.run();
 }
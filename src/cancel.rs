@@ -0,0 +1,97 @@
+//! A token for abandoning lock waits, such as on shutdown.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+
+use crate::wait_queue::{WaitKey, WaitQueue};
+
+/// Tells tasks to stop waiting.
+///
+/// Clones share their state: once one of them is cancelled, every
+/// `cancelled` future resolves, and acquisitions started with
+/// `Mutex::lock_with_cancel` fail with `LockError::Cancelled`.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    waiters: WaitQueue,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wakes every task waiting for the token. Cancelling twice does nothing.
+    pub fn cancel(&self) {
+        let mut state = self.state();
+        if !state.cancelled {
+            state.cancelled = true;
+            let wakers = state.waiters.notify_all();
+            drop(state);
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state().cancelled
+    }
+
+    /// Resolves once the token is cancelled.
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            token: self,
+            key: WaitKey::default(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Leaves the token's queue when dropped, so an abandoned wait leaves
+/// no waker behind.
+#[derive(Debug)]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+    key: WaitKey,
+}
+
+impl<'a> Future for Cancelled<'a> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = &mut *self;
+        let mut state = this.token.state();
+        if state.cancelled {
+            // Everyone has been woken, so there is no wakeup to pass on.
+            let _ = state.waiters.cancel(&mut this.key);
+            return Poll::Ready(());
+        }
+        state.waiters.register(&mut this.key, cx);
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for Cancelled<'a> {
+    fn drop(&mut self) {
+        let _ = self.token.state().waiters.cancel(&mut self.key);
+    }
+}
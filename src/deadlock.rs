@@ -0,0 +1,111 @@
+//! Wait-for graph tracking behind the `deadlock_detection` feature.
+//!
+//! Tasks have no identity of their own, so a task is identified by its
+//! waker. Mutexes and queued waiters are identified by their addresses.
+//! When a task is about to wait for a mutex that is held, directly or
+//! through other waiting tasks, by the task itself, the poll panics.
+//!
+//! `try_lock` has no waker to go by, so a lock it takes is recorded as
+//! held by an unknown task, and cycles through that task go unreported.
+
+#[cfg(feature = "deadlock_detection")]
+mod imp {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::task::Waker;
+
+    type TaskId = (usize, usize);
+
+    #[derive(Default)]
+    struct Graph {
+        holders: HashMap<usize, TaskId>,
+        waits: HashMap<(usize, usize), TaskId>,
+    }
+
+    fn graph() -> MutexGuard<'static, Graph> {
+        static GRAPH: OnceLock<Mutex<Graph>> = OnceLock::new();
+        GRAPH
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn task_id(waker: &Waker) -> TaskId {
+        (waker.data() as usize, waker.vtable() as *const _ as usize)
+    }
+
+    impl Graph {
+        fn reaches(&self, mutex: usize, task: TaskId) -> bool {
+            let mut visited = HashSet::new();
+            let mut stack = vec![mutex];
+            while let Some(mutex) = stack.pop() {
+                if !visited.insert(mutex) {
+                    continue;
+                }
+                let holder = match self.holders.get(&mutex) {
+                    Some(&holder) => holder,
+                    None => continue,
+                };
+                if holder == task {
+                    return true;
+                }
+                stack.extend(
+                    self.waits
+                        .iter()
+                        .filter(|&(_, &waiter)| waiter == holder)
+                        .map(|(&(mutex, _), _)| mutex),
+                );
+            }
+            false
+        }
+    }
+
+    pub(crate) fn wait(mutex: usize, key: usize, waker: &Waker) {
+        let task = task_id(waker);
+        let mut graph = graph();
+        graph.waits.insert((mutex, key), task);
+        if graph.reaches(mutex, task) {
+            graph.waits.remove(&(mutex, key));
+            drop(graph);
+            panic!("deadlock detected: the task is waiting for a mutex it holds");
+        }
+    }
+
+    pub(crate) fn cancel(mutex: usize, key: usize) {
+        graph().waits.remove(&(mutex, key));
+    }
+
+    pub(crate) fn acquired(mutex: usize, key: Option<usize>, waker: Option<&Waker>) {
+        let mut graph = graph();
+        if let Some(key) = key {
+            graph.waits.remove(&(mutex, key));
+        }
+        match waker {
+            Some(waker) => graph.holders.insert(mutex, task_id(waker)),
+            None => graph.holders.remove(&mutex),
+        };
+    }
+
+    pub(crate) fn released(mutex: usize, next: Option<&Waker>) {
+        acquired(mutex, None, next);
+    }
+}
+
+#[cfg(not(feature = "deadlock_detection"))]
+mod imp {
+    use core::task::Waker;
+
+    #[inline]
+    pub(crate) fn wait(_mutex: usize, _key: usize, _waker: &Waker) {}
+
+    #[inline]
+    pub(crate) fn cancel(_mutex: usize, _key: usize) {}
+
+    #[inline]
+    pub(crate) fn acquired(_mutex: usize, _key: Option<usize>, _waker: Option<&Waker>) {}
+
+    #[inline]
+    pub(crate) fn released(_mutex: usize, _next: Option<&Waker>) {}
+}
+
+pub(crate) use self::imp::{acquired, cancel, released, wait};
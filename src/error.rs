@@ -0,0 +1,125 @@
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::poison::PoisonError;
+use crate::timeout::Elapsed;
+
+/// Why an acquisition through `lock_checked` and friends failed.
+///
+/// Like `PoisonError`, a poisoned lock still hands out the guard.
+pub enum LockError<G> {
+    Poisoned(PoisonError<G>),
+    /// The cancellation future given to `lock_cancellable` completed first.
+    Cancelled,
+    /// The timeout given to `lock_checked_for` passed first.
+    TimedOut,
+    /// As many tasks as the mutex allows were waiting already.
+    QueueFull,
+}
+
+impl<G> From<PoisonError<G>> for LockError<G> {
+    fn from(err: PoisonError<G>) -> Self {
+        LockError::Poisoned(err)
+    }
+}
+
+impl<G> From<Elapsed> for LockError<G> {
+    fn from(_: Elapsed) -> Self {
+        LockError::TimedOut
+    }
+}
+
+impl<G> fmt::Debug for LockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::Poisoned(..) => f.pad("Poisoned(..)"),
+            LockError::Cancelled => f.pad("Cancelled"),
+            LockError::TimedOut => f.pad("TimedOut"),
+            LockError::QueueFull => f.pad("QueueFull"),
+        }
+    }
+}
+
+impl<G> fmt::Display for LockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::Poisoned(err) => fmt::Display::fmt(err, f),
+            LockError::Cancelled => f.write_str("lock acquisition cancelled"),
+            LockError::TimedOut => f.write_str("lock acquisition timed out"),
+            LockError::QueueFull => f.write_str("too many tasks waiting for the lock"),
+        }
+    }
+}
+
+impl<G> Error for LockError<G> {}
+
+/// Wraps an acquisition to report all its failures as `LockError`.
+///
+/// If `stop` completes before the lock is acquired, the acquisition is
+/// dropped and fails with `Cancelled` or `TimedOut`, depending on how
+/// it was created.
+#[derive(Debug)]
+pub struct LockChecked<F, D> {
+    /// `None` once the acquisition has been given up.
+    inner: Option<F>,
+    stop: D,
+    timed_out: bool,
+}
+
+impl<F, D> LockChecked<F, D> {
+    pub(crate) fn new(inner: F, stop: D, timed_out: bool) -> Self {
+        Self {
+            inner: Some(inner),
+            stop,
+            timed_out,
+        }
+    }
+}
+
+impl<F, D> Future for LockChecked<F, D>
+where
+    F: sealed::CheckedAcquire,
+    D: Future<Output = ()>,
+{
+    type Output = Result<F::Guard, LockError<F::Guard>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Both futures are pinned along with `self`.
+        let inner = unsafe { self.as_mut().map_unchecked_mut(|this| &mut this.inner) };
+        let acquire = inner
+            .as_pin_mut()
+            .expect("LockChecked polled after cancellation");
+        if let Poll::Ready(result) = acquire.poll_checked(cx) {
+            return Poll::Ready(result);
+        }
+
+        let timed_out = self.timed_out;
+        let stop = unsafe { self.as_mut().map_unchecked_mut(|this| &mut this.stop) };
+        if stop.poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        // Leave the queue right away rather than when `self` is dropped.
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }.set(None);
+        if timed_out {
+            Poll::Ready(Err(LockError::TimedOut))
+        } else {
+            Poll::Ready(Err(LockError::Cancelled))
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    /// An acquisition that `LockChecked` can drive. Unlike its `Future`
+    /// implementation, it may fail with more than poisoning.
+    pub trait CheckedAcquire {
+        type Guard;
+        fn poll_checked(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<Self::Guard, LockError<Self::Guard>>>;
+    }
+}
@@ -0,0 +1,80 @@
+//! Eventual fairness for unfair mutexes; see `MutexBuilder::fair_after`.
+
+#[cfg(feature = "std")]
+mod imp {
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    #[derive(Debug)]
+    pub(crate) struct Fairness {
+        timeout: Option<Duration>,
+        /// Since when the waiters have gone without the lock.
+        since: Mutex<Option<Instant>>,
+    }
+
+    impl Fairness {
+        pub(crate) const fn new(timeout: Option<Duration>) -> Self {
+            Self {
+                timeout,
+                since: Mutex::new(None),
+            }
+        }
+
+        /// Whether a release that leaves waiters behind should hand the
+        /// lock over, because they have waited long enough.
+        pub(crate) fn overdue(&self) -> bool {
+            let timeout = match self.timeout {
+                Some(timeout) => timeout,
+                None => return false,
+            };
+            let mut since = self.since();
+            match *since {
+                Some(start) if start.elapsed() >= timeout => {
+                    *since = None;
+                    true
+                }
+                Some(_) => false,
+                None => {
+                    *since = Some(Instant::now());
+                    false
+                }
+            }
+        }
+
+        /// Records that a task got the lock after waiting for it.
+        pub(crate) fn served(&self) {
+            if self.timeout.is_some() {
+                *self.since() = None;
+            }
+        }
+
+        fn since(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+            self.since.lock().unwrap_or_else(|err| err.into_inner())
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    use core::time::Duration;
+
+    #[derive(Debug)]
+    pub(crate) struct Fairness;
+
+    impl Fairness {
+        #[inline]
+        pub(crate) const fn new(_timeout: Option<Duration>) -> Self {
+            Fairness
+        }
+
+        #[inline]
+        pub(crate) fn overdue(&self) -> bool {
+            false
+        }
+
+        #[inline]
+        pub(crate) fn served(&self) {}
+    }
+}
+
+pub(crate) use self::imp::Fairness;
@@ -0,0 +1,221 @@
+//! Mutexes that must be acquired in a fixed order.
+//!
+//! Every `OrderedMutex` has a rank, and a task may only wait for a mutex
+//! ranked higher than every `OrderedMutex` it already holds. Two tasks
+//! that follow this order cannot deadlock on each other, so giving the
+//! locks ranks documents the order, and debug builds check it: a task
+//! that is about to wait out of order panics. Release builds do not
+//! track anything.
+//!
+//! As in deadlock detection, tasks are identified by their wakers.
+
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::LockResult;
+use std::task::{Context, Poll};
+
+use futures_core::future::FusedFuture;
+
+use crate::poison;
+use crate::sync::{Mutex, MutexAcquire, MutexGuard};
+
+#[cfg(debug_assertions)]
+mod imp {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, MutexGuard, OnceLock};
+    use std::task::Waker;
+
+    type TaskId = (usize, usize);
+
+    /// The ranks of the mutexes each task holds.
+    fn held() -> MutexGuard<'static, HashMap<TaskId, Vec<u32>>> {
+        static HELD: OnceLock<Mutex<HashMap<TaskId, Vec<u32>>>> = OnceLock::new();
+        HELD.get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn task_id(waker: &Waker) -> TaskId {
+        (waker.data() as usize, waker.vtable() as *const _ as usize)
+    }
+
+    pub(super) fn check(rank: u32, waker: &Waker) {
+        let held = held();
+        let highest = held
+            .get(&task_id(waker))
+            .and_then(|ranks| ranks.iter().copied().max());
+        if let Some(highest) = highest.filter(|&highest| highest >= rank) {
+            drop(held);
+            panic!(
+                "lock order violation: waiting for a mutex of rank {} while holding one of rank {}",
+                rank, highest
+            );
+        }
+    }
+
+    /// Records that a task holds a mutex, until dropped.
+    #[derive(Debug)]
+    pub(super) struct Hold {
+        task: TaskId,
+        rank: u32,
+    }
+
+    impl Hold {
+        pub(super) fn new(rank: u32, waker: &Waker) -> Self {
+            let task = task_id(waker);
+            held().entry(task).or_default().push(rank);
+            Self { task, rank }
+        }
+    }
+
+    impl Drop for Hold {
+        fn drop(&mut self) {
+            let mut held = held();
+            if let Some(ranks) = held.get_mut(&self.task) {
+                if let Some(pos) = ranks.iter().position(|&rank| rank == self.rank) {
+                    ranks.swap_remove(pos);
+                }
+                if ranks.is_empty() {
+                    held.remove(&self.task);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+mod imp {
+    use std::task::Waker;
+
+    #[inline]
+    pub(super) fn check(_rank: u32, _waker: &Waker) {}
+
+    #[derive(Debug)]
+    pub(super) struct Hold;
+
+    impl Hold {
+        #[inline]
+        pub(super) fn new(_rank: u32, _waker: &Waker) -> Self {
+            Hold
+        }
+    }
+}
+
+use self::imp::Hold;
+
+/// A `sync::Mutex` with a rank in the lock order.
+pub struct OrderedMutex<T: ?Sized> {
+    rank: u32,
+    inner: Mutex<T>,
+}
+
+impl<T> OrderedMutex<T> {
+    pub fn new(rank: u32, inner: T) -> Self {
+        Self {
+            rank,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> OrderedMutex<T> {
+    pub fn rank(&self) -> u32 {
+        self.rank
+    }
+
+    /// Like `sync::Mutex::lock`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, the future panics if the task polling it holds an
+    /// `OrderedMutex` ranked the same or higher.
+    #[track_caller]
+    pub fn lock(&self) -> OrderedMutexAcquire<'_, T> {
+        OrderedMutexAcquire {
+            rank: self.rank,
+            inner: self.inner.lock(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OrderedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OrderedMutex")
+            .field("rank", &self.rank)
+            .field("inner", &&self.inner)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct OrderedMutexAcquire<'a, T: ?Sized + 'a> {
+    rank: u32,
+    inner: MutexAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for OrderedMutexAcquire<'a, T> {
+    type Output = LockResult<OrderedMutexGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        imp::check(this.rank, cx.waker());
+        match Pin::new(&mut this.inner).poll(cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                OrderedMutexGuard {
+                    inner: guard,
+                    _hold: Hold::new(this.rank, cx.waker()),
+                }
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> FusedFuture for OrderedMutexAcquire<'a, T> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_terminated()
+    }
+}
+
+pub struct OrderedMutexGuard<'a, T: ?Sized + 'a> {
+    inner: MutexGuard<'a, T>,
+    _hold: Hold,
+}
+
+impl<'a, T: ?Sized + 'a> Deref for OrderedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for OrderedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for OrderedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for OrderedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
@@ -0,0 +1,214 @@
+//! Lock event hooks behind the `instrument` feature.
+//!
+//! Install a hook with [`set_hook`] to observe every mutex in the process,
+//! e.g. to forward contention into a tracing or metrics system, or with
+//! `Mutex::set_event_hook` to observe a single mutex. Mutexes are
+//! identified by their addresses, and tasks by their wakers.
+//!
+//! [`set_long_hold_threshold`] additionally flags locks that were held for
+//! too long, which is a common source of latency with async locks.
+
+#[cfg(feature = "instrument")]
+mod imp {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
+    use std::task::Waker;
+    use std::time::{Duration, Instant};
+
+    type Hook = Arc<dyn Fn(&LockEvent) + Send + Sync>;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LockEvent {
+        /// The address of the mutex.
+        pub mutex: usize,
+        /// The data pointer of the waker of the task involved, which
+        /// identifies the task with most executors. `None` for events not
+        /// tied to a poll, such as releases.
+        pub task: Option<usize>,
+        pub kind: LockEventKind,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum LockEventKind {
+        /// A task started acquiring the lock.
+        Requested,
+        /// The lock was held, so the task started waiting for it.
+        WaitStarted,
+        /// The task gave up waiting.
+        WaitCancelled { waited: Duration },
+        /// A release woke the waiting task, to take the lock or to try
+        /// again, depending on the mutex's `WakePolicy`.
+        Woken,
+        /// The task acquired the lock; `waited` is `None` if it did not wait.
+        Acquired { waited: Option<Duration> },
+        /// The lock was released; `held` is `None` if the acquisition was
+        /// not observed, as with `try_lock`.
+        Released { held: Option<Duration> },
+        /// Follows `Released` if the lock was held for longer than the
+        /// threshold set with `set_long_hold_threshold`.
+        HeldTooLong { held: Duration },
+    }
+
+    static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+    static LONG_HOLD_THRESHOLD: RwLock<Option<Duration>> = RwLock::new(None);
+
+    /// Registers a hook called on every lock event, replacing the previous one.
+    ///
+    /// The hook runs synchronously within the polls and drops that cause
+    /// the events, so it should be cheap.
+    pub fn set_hook(hook: impl Fn(&LockEvent) + Send + Sync + 'static) {
+        *HOOK.write().unwrap_or_else(|err| err.into_inner()) = Some(Arc::new(hook));
+    }
+
+    /// Unregisters the hook, if any.
+    pub fn take_hook() {
+        HOOK.write().unwrap_or_else(|err| err.into_inner()).take();
+    }
+
+    /// Sets how long a lock may be held before its release is followed by
+    /// a `HeldTooLong` event. `None`, the default, disables the events.
+    pub fn set_long_hold_threshold(threshold: Option<Duration>) {
+        *LONG_HOLD_THRESHOLD
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = threshold;
+    }
+
+    /// The hook of a single mutex, called after the global one.
+    #[derive(Debug)]
+    pub(crate) struct Instrument {
+        hook: RwLock<Option<fn(&LockEvent)>>,
+    }
+
+    fn task(waker: Option<&Waker>) -> Option<usize> {
+        waker.map(|waker| waker.data() as usize)
+    }
+
+    fn waiting() -> MutexGuard<'static, HashMap<(usize, usize), Instant>> {
+        static WAITING: OnceLock<Mutex<HashMap<(usize, usize), Instant>>> = OnceLock::new();
+        WAITING
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn holding() -> MutexGuard<'static, HashMap<usize, Instant>> {
+        static HOLDING: OnceLock<Mutex<HashMap<usize, Instant>>> = OnceLock::new();
+        HOLDING
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    impl Instrument {
+        pub(crate) const fn new() -> Self {
+            Self {
+                hook: RwLock::new(None),
+            }
+        }
+
+        pub(crate) fn set_hook(&self, hook: Option<fn(&LockEvent)>) {
+            *self.hook.write().unwrap_or_else(|err| err.into_inner()) = hook;
+        }
+
+        fn emit(&self, mutex: usize, task: Option<usize>, kind: LockEventKind) {
+            let event = LockEvent { mutex, task, kind };
+            let hook = HOOK.read().unwrap_or_else(|err| err.into_inner()).clone();
+            // Called without holding any lock, so that the hooks may use mutexes.
+            if let Some(hook) = hook {
+                hook(&event);
+            }
+            let local = *self.hook.read().unwrap_or_else(|err| err.into_inner());
+            if let Some(local) = local {
+                local(&event);
+            }
+        }
+
+        pub(crate) fn requested(&self, mutex: usize, key: Option<usize>, waker: &Waker) {
+            let waiting = key.is_some_and(|key| waiting().contains_key(&(mutex, key)));
+            if !waiting {
+                self.emit(mutex, task(Some(waker)), LockEventKind::Requested);
+            }
+        }
+
+        pub(crate) fn wait_started(&self, mutex: usize, key: usize, waker: &Waker) {
+            let mut started = false;
+            waiting().entry((mutex, key)).or_insert_with(|| {
+                started = true;
+                Instant::now()
+            });
+            if started {
+                self.emit(mutex, task(Some(waker)), LockEventKind::WaitStarted);
+            }
+        }
+
+        pub(crate) fn cancelled(&self, mutex: usize, key: usize) {
+            let since = waiting().remove(&(mutex, key));
+            if let Some(since) = since {
+                let waited = since.elapsed();
+                self.emit(mutex, None, LockEventKind::WaitCancelled { waited });
+            }
+        }
+
+        pub(crate) fn woken(&self, mutex: usize, waker: &Waker) {
+            self.emit(mutex, task(Some(waker)), LockEventKind::Woken);
+        }
+
+        pub(crate) fn acquired(&self, mutex: usize, key: Option<usize>, waker: Option<&Waker>) {
+            let since = key.and_then(|key| waiting().remove(&(mutex, key)));
+            let waited = since.map(|since| since.elapsed());
+            holding().insert(mutex, Instant::now());
+            self.emit(mutex, task(waker), LockEventKind::Acquired { waited });
+        }
+
+        pub(crate) fn released(&self, mutex: usize) {
+            let since = holding().remove(&mutex);
+            let held = since.map(|since| since.elapsed());
+            self.emit(mutex, None, LockEventKind::Released { held });
+            let threshold = *LONG_HOLD_THRESHOLD
+                .read()
+                .unwrap_or_else(|err| err.into_inner());
+            if let (Some(held), Some(threshold)) = (held, threshold) {
+                if held > threshold {
+                    self.emit(mutex, None, LockEventKind::HeldTooLong { held });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "instrument"))]
+mod imp {
+    use core::task::Waker;
+
+    #[derive(Debug)]
+    pub(crate) struct Instrument;
+
+    impl Instrument {
+        pub(crate) const fn new() -> Self {
+            Instrument
+        }
+
+        #[inline]
+        pub(crate) fn requested(&self, _mutex: usize, _key: Option<usize>, _waker: &Waker) {}
+
+        #[inline]
+        pub(crate) fn wait_started(&self, _mutex: usize, _key: usize, _waker: &Waker) {}
+
+        #[inline]
+        pub(crate) fn cancelled(&self, _mutex: usize, _key: usize) {}
+
+        #[inline]
+        pub(crate) fn woken(&self, _mutex: usize, _waker: &Waker) {}
+
+        #[inline]
+        pub(crate) fn acquired(&self, _mutex: usize, _key: Option<usize>, _waker: Option<&Waker>) {}
+
+        #[inline]
+        pub(crate) fn released(&self, _mutex: usize) {}
+    }
+}
+
+#[cfg(feature = "instrument")]
+pub use self::imp::{set_hook, set_long_hold_threshold, take_hook, LockEvent, LockEventKind};
+
+pub(crate) use self::imp::Instrument;
@@ -1,3 +1,48 @@
-#![feature(arbitrary_self_types, pin, futures_api)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+#[macro_use]
+mod loom;
+
+#[cfg(feature = "std")]
+pub mod graph;
+#[cfg(feature = "std")]
+pub mod sync;
 pub mod unsync;
+
+#[cfg(feature = "std")]
+mod blocking;
+#[cfg(feature = "std")]
+mod cancel;
+mod deadlock;
+mod error;
+mod fairness;
+#[cfg(feature = "instrument")]
+pub mod instrument;
+#[cfg(not(feature = "instrument"))]
+mod instrument;
+mod poison;
+mod small_deque;
+mod stats;
+mod timeout;
+mod tracking;
+mod wait_queue;
+mod waiter;
+mod wake_batch;
+mod wake_policy;
+
+#[cfg(feature = "std")]
+pub use crate::cancel::{CancellationToken, Cancelled};
+pub use crate::error::{LockChecked, LockError};
+pub use crate::poison::{LockResult, LockResultExt, PoisonError, TryLockError, TryLockResult};
+#[cfg(feature = "stats")]
+pub use crate::stats::MutexStats;
+pub use crate::timeout::{Elapsed, Timeout, TryLockTimeout};
+pub use crate::wait_queue::{WaitKey, WaitQueue};
+#[cfg(feature = "std")]
+pub use crate::wake_batch::WakeBatch;
+pub use crate::wake_policy::WakePolicy;
+#[cfg(feature = "compat")]
+pub use futures_util::compat::Compat;
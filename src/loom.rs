@@ -0,0 +1,24 @@
+//! The atomics and locks behind the `sync` primitives.
+//!
+//! Building with `--cfg loom` swaps them for the model-checked versions
+//! from the `loom` crate, which `tests/loom.rs` runs the primitives under.
+
+#[cfg(loom)]
+pub(crate) use ::loom::{hint, sync};
+
+#[cfg(not(loom))]
+pub(crate) use std::{hint, sync};
+
+/// Defines a `const fn`, which is not `const` under loom, as loom's
+/// atomics and locks cannot be created in constant contexts.
+macro_rules! loom_const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $name:ident $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])*
+        $vis const fn $name $($rest)*
+
+        #[cfg(loom)]
+        $(#[$attr])*
+        $vis fn $name $($rest)*
+    };
+}
@@ -0,0 +1,169 @@
+use core::ops::DerefMut;
+
+#[cfg(feature = "std")]
+pub use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// Whether the thread is unwinding from a panic.
+///
+/// Without `std` this cannot be told, so mutexes are never poisoned.
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn panicking() -> bool {
+    std::thread::panicking()
+}
+
+#[cfg(not(feature = "std"))]
+#[inline]
+pub(crate) fn panicking() -> bool {
+    false
+}
+
+/// Recovery from poisoning, for the results of locking a mutex.
+pub trait LockResultExt<G> {
+    /// Returns the guard. If the mutex was poisoned, `repair` first runs
+    /// on the data, and then the poison is cleared.
+    fn recover_with<F>(self, repair: F) -> G
+    where
+        G: DerefMut,
+        F: FnOnce(&mut G::Target);
+}
+
+impl<G: sealed::ClearPoison> LockResultExt<G> for LockResult<G> {
+    fn recover_with<F>(self, repair: F) -> G
+    where
+        G: DerefMut,
+        F: FnOnce(&mut G::Target),
+    {
+        match self {
+            Ok(guard) => guard,
+            Err(err) => {
+                let mut guard = err.into_inner();
+                repair(&mut guard);
+                G::clear_poison(&guard);
+                guard
+            }
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    /// A guard through which its mutex can be unpoisoned.
+    pub trait ClearPoison {
+        fn clear_poison(guard: &Self);
+    }
+}
+
+pub(crate) fn map_result<T, U, F>(result: LockResult<T>, f: F) -> LockResult<U>
+where
+    F: FnOnce(T) -> U,
+{
+    match result {
+        Ok(t) => Ok(f(t)),
+        Err(err) => Err(PoisonError::new(f(err.into_inner()))),
+    }
+}
+
+pub(crate) fn zip<T, U>(a: LockResult<T>, b: LockResult<U>) -> LockResult<(T, U)> {
+    let poisoned = a.is_err() || b.is_err();
+    let a = a.unwrap_or_else(PoisonError::into_inner);
+    let b = b.unwrap_or_else(PoisonError::into_inner);
+    if poisoned {
+        Err(PoisonError::new((a, b)))
+    } else {
+        Ok((a, b))
+    }
+}
+
+pub(crate) fn map_try_result<T, U, F>(result: TryLockResult<T>, f: F) -> TryLockResult<U>
+where
+    F: FnOnce(T) -> U,
+{
+    match result {
+        Ok(t) => Ok(f(t)),
+        Err(TryLockError::Poisoned(err)) => Err(PoisonError::new(f(err.into_inner())).into()),
+        Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+    }
+}
+
+/// Stand-ins for the poisoning types of `std::sync`, with the same API.
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use core::error::Error;
+    use core::fmt;
+
+    pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+    pub type TryLockResult<G> = Result<G, TryLockError<G>>;
+
+    pub struct PoisonError<T> {
+        guard: T,
+    }
+
+    impl<T> PoisonError<T> {
+        pub fn new(guard: T) -> Self {
+            Self { guard }
+        }
+
+        pub fn into_inner(self) -> T {
+            self.guard
+        }
+
+        pub fn get_ref(&self) -> &T {
+            &self.guard
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+
+    impl<T> fmt::Debug for PoisonError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("PoisonError").finish_non_exhaustive()
+        }
+    }
+
+    impl<T> fmt::Display for PoisonError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("poisoned lock: another task failed inside")
+        }
+    }
+
+    impl<T> Error for PoisonError<T> {}
+
+    pub enum TryLockError<T> {
+        Poisoned(PoisonError<T>),
+        WouldBlock,
+    }
+
+    impl<T> From<PoisonError<T>> for TryLockError<T> {
+        fn from(err: PoisonError<T>) -> Self {
+            TryLockError::Poisoned(err)
+        }
+    }
+
+    impl<T> fmt::Debug for TryLockError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                TryLockError::Poisoned(err) => f.debug_tuple("Poisoned").field(err).finish(),
+                TryLockError::WouldBlock => f.write_str("WouldBlock"),
+            }
+        }
+    }
+
+    impl<T> fmt::Display for TryLockError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                TryLockError::Poisoned(err) => fmt::Display::fmt(err, f),
+                TryLockError::WouldBlock => {
+                    f.write_str("try_lock failed because the operation would block")
+                }
+            }
+        }
+    }
+
+    impl<T> Error for TryLockError<T> {}
+}
@@ -0,0 +1,89 @@
+use alloc::collections::vec_deque::{self, VecDeque};
+use core::array;
+use core::iter::{Chain, Flatten};
+
+/// A FIFO sequence that stores its first `N` elements inline,
+/// and only allocates once more elements are pushed.
+///
+/// The elements are `inline[..len]` followed by `spill`.
+/// `spill` is empty unless the inline part is full.
+#[derive(Debug)]
+pub(crate) struct SmallDeque<T, const N: usize> {
+    inline: [Option<T>; N],
+    len: usize,
+    spill: VecDeque<T>,
+}
+
+impl<T, const N: usize> SmallDeque<T, N> {
+    const NONE: Option<T> = None;
+
+    pub(crate) const fn new() -> Self {
+        Self {
+            inline: [Self::NONE; N],
+            len: 0,
+            spill: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len + self.spill.len()
+    }
+
+    pub(crate) fn front(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub(crate) fn push_back(&mut self, value: T) {
+        if self.len < N {
+            self.inline[self.len] = Some(value);
+            self.len += 1;
+        } else {
+            self.spill.push_back(value);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.len {
+            return self.spill.remove(index - self.len);
+        }
+        let value = self.inline[index].take();
+        self.inline[index..self.len].rotate_left(1);
+        match self.spill.pop_front() {
+            Some(next) => self.inline[self.len - 1] = Some(next),
+            None => self.len -= 1,
+        }
+        value
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
+        self.inline[..self.len].iter().flatten().chain(&self.spill)
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.inline[..self.len]
+            .iter_mut()
+            .flatten()
+            .chain(&mut self.spill)
+    }
+}
+
+impl<T, const N: usize> Default for SmallDeque<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for SmallDeque<T, N> {
+    type Item = T;
+    type IntoIter = Chain<Flatten<array::IntoIter<Option<T>, N>>, vec_deque::IntoIter<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self.inline)
+            .flatten()
+            .chain(self.spill)
+    }
+}
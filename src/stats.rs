@@ -0,0 +1,108 @@
+//! Per-mutex contention statistics behind the `stats` feature.
+
+#[cfg(feature = "stats")]
+mod imp {
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    /// Contention statistics of a mutex, as returned by `Mutex::stats`.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct MutexStats {
+        /// How many times the lock was acquired, including by `try_lock`.
+        pub acquisitions: u64,
+        /// How many of the acquisitions had to queue up first.
+        pub contended: u64,
+        /// The time the contended acquisitions spent queued, in total.
+        pub total_wait: Duration,
+        /// The most tasks queued up at once.
+        pub max_queue_depth: usize,
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct Stats {
+        inner: Mutex<Inner>,
+    }
+
+    #[derive(Debug)]
+    struct Inner {
+        stats: MutexStats,
+        /// When each queued waiter started waiting, by key.
+        waiting: Vec<(usize, Instant)>,
+    }
+
+    impl Stats {
+        pub(crate) const fn new() -> Self {
+            Self {
+                inner: Mutex::new(Inner {
+                    stats: MutexStats {
+                        acquisitions: 0,
+                        contended: 0,
+                        total_wait: Duration::ZERO,
+                        max_queue_depth: 0,
+                    },
+                    waiting: Vec::new(),
+                }),
+            }
+        }
+
+        pub(crate) fn get(&self) -> MutexStats {
+            self.inner().stats
+        }
+
+        pub(crate) fn wait_started(&self, key: usize, queue_depth: usize) {
+            let mut inner = self.inner();
+            if !inner.waiting.iter().any(|&(k, _)| k == key) {
+                inner.waiting.push((key, Instant::now()));
+            }
+            let max = &mut inner.stats.max_queue_depth;
+            *max = (*max).max(queue_depth);
+        }
+
+        pub(crate) fn cancelled(&self, key: usize) {
+            let mut inner = self.inner();
+            inner.waiting.retain(|&(k, _)| k != key);
+        }
+
+        pub(crate) fn acquired(&self, key: Option<usize>) {
+            let mut inner = self.inner();
+            inner.stats.acquisitions += 1;
+            let pos = key.and_then(|key| inner.waiting.iter().position(|&(k, _)| k == key));
+            if let Some(pos) = pos {
+                let (_, since) = inner.waiting.swap_remove(pos);
+                inner.stats.contended += 1;
+                inner.stats.total_wait += since.elapsed();
+            }
+        }
+
+        fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+            self.inner.lock().unwrap_or_else(|err| err.into_inner())
+        }
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+mod imp {
+    #[derive(Debug)]
+    pub(crate) struct Stats;
+
+    impl Stats {
+        #[inline]
+        pub(crate) const fn new() -> Self {
+            Stats
+        }
+
+        #[inline]
+        pub(crate) fn wait_started(&self, _key: usize, _queue_depth: usize) {}
+
+        #[inline]
+        pub(crate) fn cancelled(&self, _key: usize) {}
+
+        #[inline]
+        pub(crate) fn acquired(&self, _key: Option<usize>) {}
+    }
+}
+
+#[cfg(feature = "stats")]
+pub use self::imp::MutexStats;
+
+pub(crate) use self::imp::Stats;
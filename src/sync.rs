@@ -0,0 +1,40 @@
+pub mod oneshot;
+
+#[cfg(feature = "io")]
+mod io;
+mod lazy;
+mod multi;
+mod mutex;
+mod mutex_map;
+mod pin_mutex;
+mod rwlock;
+mod semaphore;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "sink")]
+mod sink;
+mod sharded;
+mod unpoisoned;
+
+#[cfg(feature = "io")]
+pub use self::io::MutexIo;
+pub use self::lazy::{LazyMutex, LazyMutexAcquire};
+pub use self::multi::{lock_all, lock_both, select_lock, LockAll, LockBoth, SelectLock};
+pub use self::mutex::{
+    MappedMutexGuard, Mutex, MutexAcquire, MutexBuilder, MutexGuard, MutexHold, MutexIter,
+    MutexLockAndThen, MutexLockIter, MutexLockStream, MutexLockWhen, OwnedMutexAcquire,
+    OwnedMutexGuard, WaitToken,
+};
+pub use self::mutex_map::{MutexMap, MutexMapAcquire, MutexMapGuard};
+pub use self::pin_mutex::{PinMutex, PinMutexAcquire, PinMutexGuard};
+pub use self::rwlock::{
+    MappedRwLockReadGuard, MappedRwLockWriteGuard, OwnedRwLockReadAcquire, OwnedRwLockReadGuard,
+    OwnedRwLockWriteAcquire, OwnedRwLockWriteGuard, RwLock, RwLockBuilder, RwLockReadAcquire,
+    RwLockReadGuard, RwLockUpgradableReadAcquire, RwLockUpgradableReadGuard, RwLockUpgrade,
+    RwLockWriteAcquire, RwLockWriteGuard,
+};
+pub use self::semaphore::{Semaphore, SemaphoreAcquire, SemaphorePermit};
+#[cfg(feature = "sink")]
+pub use self::sink::MutexSink;
+pub use self::sharded::ShardedMutex;
+pub use self::unpoisoned::{UnpoisonedMutex, UnpoisonedMutexAcquire};
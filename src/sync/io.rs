@@ -0,0 +1,102 @@
+use std::fmt;
+use std::future::Future;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+use crate::poison::PoisonError;
+
+/// An IO object that reads and writes through a mutex; see `Mutex::as_io`.
+pub struct MutexIo<'a, T: ?Sized + 'a> {
+    acquire: MutexAcquire<'a, T>,
+    guard: Option<MutexGuard<'a, T>>,
+}
+
+impl<'a, T: ?Sized + Unpin + 'a> MutexIo<'a, T> {
+    pub(super) fn new(mutex: &'a Mutex<T>) -> Self {
+        Self {
+            acquire: mutex.lock(),
+            guard: None,
+        }
+    }
+
+    /// Runs one operation with the lock held, keeping the lock until the
+    /// operation completes.
+    fn with_lock<R>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        op: impl FnOnce(Pin<&mut T>, &mut Context<'_>) -> Poll<R>,
+    ) -> Poll<R> {
+        let this = self.get_mut();
+        if this.guard.is_none() {
+            match Pin::new(&mut this.acquire).poll(cx) {
+                Poll::Ready(result) => {
+                    this.guard = Some(result.unwrap_or_else(PoisonError::into_inner));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let result = op(Pin::new(&mut **this.guard.as_mut().unwrap()), cx);
+        // The IO object only wakes the task that got `Pending`, so another
+        // task must not use it in the meantime.
+        if result.is_ready() {
+            this.guard = None;
+        }
+        result
+    }
+}
+
+impl<'a, T: ?Sized + AsyncRead + Unpin + 'a> AsyncRead for MutexIo<'a, T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.with_lock(cx, |io, cx| io.poll_read(cx, buf))
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.with_lock(cx, |io, cx| io.poll_read_vectored(cx, bufs))
+    }
+}
+
+impl<'a, T: ?Sized + AsyncWrite + Unpin + 'a> AsyncWrite for MutexIo<'a, T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.with_lock(cx, |io, cx| io.poll_write(cx, buf))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.with_lock(cx, |io, cx| io.poll_write_vectored(cx, bufs))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.with_lock(cx, |io, cx| io.poll_flush(cx))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.with_lock(cx, |io, cx| io.poll_close(cx))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> fmt::Debug for MutexIo<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexIo")
+            .field("locked", &self.guard.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,176 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{LockResult, PoisonError};
+use std::task::{Context, Poll};
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+use crate::poison;
+
+fn address<T: ?Sized>(mutex: &Mutex<T>) -> usize {
+    mutex as *const Mutex<T> as *const u8 as usize
+}
+
+/// Locks two mutexes, always in the order of their addresses.
+///
+/// As long as every task that needs both locks goes through this function,
+/// they cannot deadlock on each other.
+///
+/// # Panics
+///
+/// Panics if both arguments refer to the same mutex.
+#[track_caller]
+pub fn lock_both<'a, A: ?Sized, B: ?Sized>(
+    a: &'a Mutex<A>,
+    b: &'a Mutex<B>,
+) -> LockBoth<'a, A, B> {
+    assert_ne!(address(a), address(b), "lock_both: cannot lock a mutex twice");
+    LockBoth {
+        a_first: address(a) < address(b),
+        acquire_a: a.lock(),
+        acquire_b: b.lock(),
+        a: None,
+        b: None,
+    }
+}
+
+/// Locks all the mutexes, always in the order of their addresses.
+///
+/// The guards are returned in the order of the arguments.
+///
+/// # Panics
+///
+/// Panics if the same mutex appears twice.
+pub fn lock_all<'a, T: ?Sized>(mutexes: &[&'a Mutex<T>]) -> LockAll<'a, T> {
+    let mut order = (0..mutexes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| address(mutexes[i]));
+    for pair in order.windows(2) {
+        assert_ne!(
+            address(mutexes[pair[0]]),
+            address(mutexes[pair[1]]),
+            "lock_all: cannot lock a mutex twice"
+        );
+    }
+    LockAll {
+        mutexes: mutexes.to_vec(),
+        order,
+        next: 0,
+        current: None,
+        guards: mutexes.iter().map(|_| None).collect(),
+        poisoned: false,
+    }
+}
+
+/// Acquires whichever of the mutexes becomes available first,
+/// resolving to its index and guard.
+///
+/// Every mutex is queued for at the same time. Once one is acquired, the
+/// other acquisitions are dropped, passing on any lock handed over to them
+/// meanwhile. Mutexes that become available at once are preferred in the
+/// order of the arguments.
+///
+/// # Panics
+///
+/// Panics if there are no mutexes.
+pub fn select_lock<'a, T: ?Sized>(mutexes: &[&'a Mutex<T>]) -> SelectLock<'a, T> {
+    assert!(!mutexes.is_empty(), "select_lock: no mutexes to lock");
+    SelectLock {
+        acquires: mutexes.iter().map(|mutex| mutex.lock()).collect(),
+    }
+}
+
+#[derive(Debug)]
+pub struct LockBoth<'a, A: ?Sized + 'a, B: ?Sized + 'a> {
+    a_first: bool,
+    acquire_a: MutexAcquire<'a, A>,
+    acquire_b: MutexAcquire<'a, B>,
+    a: Option<LockResult<MutexGuard<'a, A>>>,
+    b: Option<LockResult<MutexGuard<'a, B>>>,
+}
+
+impl<'a, A: ?Sized + 'a, B: ?Sized + 'a> Future for LockBoth<'a, A, B> {
+    type Output = LockResult<(MutexGuard<'a, A>, MutexGuard<'a, B>)>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.a_first && !poll_into(&mut this.acquire_a, &mut this.a, cx) {
+            return Poll::Pending;
+        }
+        if !poll_into(&mut this.acquire_b, &mut this.b, cx)
+            || !poll_into(&mut this.acquire_a, &mut this.a, cx)
+        {
+            return Poll::Pending;
+        }
+
+        let a = this.a.take().unwrap();
+        let b = this.b.take().unwrap();
+        Poll::Ready(poison::zip(a, b))
+    }
+}
+
+fn poll_into<'a, T: ?Sized>(
+    acquire: &mut MutexAcquire<'a, T>,
+    result: &mut Option<LockResult<MutexGuard<'a, T>>>,
+    cx: &mut Context<'_>,
+) -> bool {
+    if result.is_none() {
+        match Pin::new(acquire).poll(cx) {
+            Poll::Ready(guard) => *result = Some(guard),
+            Poll::Pending => return false,
+        }
+    }
+    true
+}
+
+#[derive(Debug)]
+pub struct LockAll<'a, T: ?Sized + 'a> {
+    mutexes: Vec<&'a Mutex<T>>,
+    order: Vec<usize>,
+    next: usize,
+    current: Option<MutexAcquire<'a, T>>,
+    guards: Vec<Option<MutexGuard<'a, T>>>,
+    poisoned: bool,
+}
+
+impl<'a, T: ?Sized + 'a> Future for LockAll<'a, T> {
+    type Output = LockResult<Vec<MutexGuard<'a, T>>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        while let Some(&index) = this.order.get(this.next) {
+            let mutex = this.mutexes[index];
+            let acquire = this.current.get_or_insert_with(|| mutex.lock());
+            let result = match Pin::new(acquire).poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            this.current = None;
+            this.next += 1;
+            this.poisoned |= result.is_err();
+            this.guards[index] = Some(result.unwrap_or_else(PoisonError::into_inner));
+        }
+
+        let guards = this.guards.drain(..).map(Option::unwrap).collect();
+        if this.poisoned {
+            Poll::Ready(Err(PoisonError::new(guards)))
+        } else {
+            Poll::Ready(Ok(guards))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SelectLock<'a, T: ?Sized + 'a> {
+    acquires: Vec<MutexAcquire<'a, T>>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for SelectLock<'a, T> {
+    type Output = LockResult<(usize, MutexGuard<'a, T>)>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        for (index, acquire) in this.acquires.iter_mut().enumerate() {
+            if let Poll::Ready(result) = Pin::new(acquire).poll(cx) {
+                this.acquires.clear();
+                return Poll::Ready(poison::map_result(result, |guard| (index, guard)));
+            }
+        }
+        Poll::Pending
+    }
+}
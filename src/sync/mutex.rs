@@ -0,0 +1,1874 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::{self, Future};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "debug-tracking")]
+use std::panic::Location;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, LockResult, PoisonError, TryLockError, TryLockResult};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures_core::future::FusedFuture;
+use futures_core::Stream;
+#[cfg(feature = "compat")]
+use futures_util::compat::Compat;
+
+#[cfg(feature = "io")]
+use super::io::MutexIo;
+use super::multi;
+#[cfg(feature = "sink")]
+use super::sink::MutexSink;
+use crate::blocking;
+use crate::cancel::{CancellationToken, Cancelled};
+use crate::deadlock;
+use crate::error::sealed::CheckedAcquire;
+use crate::error::{LockChecked, LockError};
+use crate::fairness::Fairness;
+use crate::instrument::Instrument;
+#[cfg(feature = "instrument")]
+use crate::instrument::LockEvent;
+use crate::loom::hint;
+use crate::loom::sync::atomic::{AtomicUsize, Ordering};
+use crate::loom::sync;
+use crate::poison::{self, sealed::ClearPoison};
+#[cfg(feature = "stats")]
+use crate::stats::MutexStats;
+use crate::stats::Stats;
+use crate::timeout::{Timeout, TryLockTimeout};
+use crate::tracking::{AcquiredAt, Caller, WaitingAt};
+use crate::wake_batch;
+use crate::wake_policy::WakePolicy;
+use crate::waiter::WaitQueue;
+
+/// A mutual exclusion primitive whose acquisition is a future.
+///
+/// Pinning is not structural: every guard hands out `&mut T`, which would
+/// allow moving the data out from under a `Pin`. To protect a `!Unpin` value
+/// such as a future or a stream, use a `PinMutex`, or store it as
+/// `Pin<Box<T>>` and poll it through `guard.as_mut()`.
+///
+/// `T` may be unsized. The data is the last field, so a pointer to a mutex
+/// coerces along with its contents: `Arc<Mutex<S>>` becomes
+/// `Arc<Mutex<dyn Trait>>` for any `S: Trait`, and `Box<Mutex<[u8; 4]>>`
+/// becomes `Box<Mutex<[u8]>>`.
+pub struct Mutex<T: ?Sized> {
+    raw: RawMutex,
+    data: UnsafeCell<T>,
+}
+
+struct RawMutex {
+    /// `LOCKED`, `POISONED` and `WAITING` bits.
+    state: AtomicUsize,
+    waiters: sync::Mutex<WaitQueue>,
+    spin_limit: u32,
+    policy: WakePolicy,
+    fairness: Fairness,
+    stats: Stats,
+    instrument: Instrument,
+    acquired_at: AcquiredAt,
+    waiting_at: WaitingAt,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T: ?Sized> UnwindSafe for Mutex<T> {}
+impl<T: ?Sized> RefUnwindSafe for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    loom_const_fn! {
+        pub fn new(inner: T) -> Self {
+            MutexBuilder::new().build(inner)
+        }
+    }
+
+    /// Creates a mutex with the given wake policy; see `WakePolicy`.
+    pub fn with_policy(inner: T, policy: WakePolicy) -> Self {
+        MutexBuilder::new().wake_policy(policy).build(inner)
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { raw, data } = self;
+        let poisoned = raw.state.into_inner() & POISONED != 0;
+        let inner = data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    /// Replaces the data, returning the old value.
+    pub async fn set(&self, value: T) -> LockResult<T> {
+        self.with_lock(|data| mem::replace(data, value)).await
+    }
+
+    /// The same as `set`, named after `Cell::replace`.
+    pub async fn replace(&self, value: T) -> LockResult<T> {
+        self.set(value).await
+    }
+
+    /// Swaps the data of two mutexes.
+    ///
+    /// Both are locked through `lock_both`, so that two tasks swapping the
+    /// same pair in opposite directions cannot deadlock. Swapping a mutex
+    /// with itself does nothing.
+    pub async fn swap(&self, other: &Mutex<T>) -> LockResult<()> {
+        if ptr::eq(self, other) {
+            return Ok(());
+        }
+        poison::map_result(multi::lock_both(self, other).await, |(mut a, mut b)| {
+            mem::swap(&mut *a, &mut *b);
+        })
+    }
+}
+
+impl<T: Clone> Mutex<T> {
+    /// Returns a copy of the data.
+    pub async fn get_cloned(&self) -> LockResult<T> {
+        self.with_lock(|data| data.clone()).await
+    }
+
+    /// Returns a new, unlocked mutex holding a copy of the data.
+    ///
+    /// The copy is not poisoned, even if the original is.
+    pub async fn duplicate(&self) -> LockResult<Mutex<T>> {
+        poison::map_result(self.get_cloned().await, Mutex::new)
+    }
+}
+
+impl Mutex<()> {
+    /// Returns a builder to configure a mutex, the same as
+    /// `MutexBuilder::new()`: `Mutex::builder().fair(false).build(data)`.
+    pub const fn builder() -> MutexBuilder {
+        MutexBuilder::new()
+    }
+}
+
+/// Configures a `Mutex` before creating it.
+#[derive(Debug, Clone, Copy)]
+pub struct MutexBuilder {
+    spin_limit: u32,
+    policy: WakePolicy,
+    fair_after: Option<Duration>,
+}
+
+impl MutexBuilder {
+    pub const fn new() -> Self {
+        Self {
+            spin_limit: 6,
+            policy: WakePolicy::Handoff,
+            fair_after: None,
+        }
+    }
+
+    /// Sets how many rounds of exponential backoff `lock` spins for
+    /// before queueing up, doubling the spin each round.
+    ///
+    /// Spinning pays off when critical sections are short and the holder
+    /// runs on another thread. Zero disables spinning.
+    pub const fn spin_limit(self, spin_limit: u32) -> Self {
+        Self { spin_limit, ..self }
+    }
+
+    /// Sets whether releasing the lock hands it over to the first waiter.
+    ///
+    /// An unfair mutex instead unlocks and wakes the first waiter, which
+    /// then competes with newcomers. This gives more throughput, as the
+    /// lock never sits idle waiting for the woken task to be polled, but
+    /// a waiter may be overtaken repeatedly. Guards can still hand over
+    /// the lock with `MutexGuard::unlock_fair`, and a fair mutex can be
+    /// released unfairly with `MutexGuard::unlock_unfair`. The default is
+    /// fair.
+    pub const fn fair(self, fair: bool) -> Self {
+        let policy = if fair {
+            WakePolicy::Handoff
+        } else {
+            WakePolicy::WakeOne
+        };
+        self.wake_policy(policy)
+    }
+
+    /// Sets what releasing the lock does with the waiters, generalizing
+    /// `fair`. The default is `WakePolicy::Handoff`.
+    pub const fn wake_policy(self, policy: WakePolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Makes an unfair mutex hand the lock over to the first waiter once
+    /// the waiters have gone `timeout` without any of them getting it.
+    ///
+    /// Newcomers, including threads spinning in `lock`, can otherwise
+    /// overtake a waiter indefinitely. With this set, the mutex keeps the
+    /// throughput of unfair releases while bounding how long the queue
+    /// can stall. It has no effect with `WakePolicy::Handoff`. The default
+    /// never hands over.
+    pub const fn fair_after(self, timeout: Duration) -> Self {
+        Self {
+            fair_after: Some(timeout),
+            ..self
+        }
+    }
+
+    loom_const_fn! {
+        pub fn build<T>(self, inner: T) -> Mutex<T> {
+            Mutex {
+                raw: RawMutex::new(self.spin_limit, self.policy, self.fair_after),
+                data: UnsafeCell::new(inner),
+            }
+        }
+    }
+}
+
+impl Default for MutexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Waiters are served in FIFO order. Releasing the lock hands it over
+    /// to the first waiter directly, so a woken waiter already owns the
+    /// lock and cannot be overtaken by `try_lock` or by newcomers.
+    /// Unfair mutexes (see `MutexBuilder::fair`) only wake the first waiter,
+    /// and `WakePolicy::WakeAll` wakes every waiter.
+    #[track_caller]
+    pub fn lock(&self) -> MutexAcquire<'_, T> {
+        MutexAcquire {
+            mutex: self,
+            key: None,
+            caller: Caller::here(),
+            terminated: false,
+        }
+    }
+    /// Acquires the lock through an `Arc`, so that the guard has no
+    /// lifetime and can be moved to other tasks and threads.
+    #[track_caller]
+    pub fn lock_owned(self: &Arc<Self>) -> OwnedMutexAcquire<T> {
+        OwnedMutexAcquire {
+            mutex: self.clone(),
+            key: None,
+            caller: Caller::here(),
+            terminated: false,
+        }
+    }
+    /// Returns a stream that acquires the lock each time it is polled.
+    ///
+    /// Every acquisition queues up behind the other waiters, so the
+    /// previous guard must be dropped before asking for the next one.
+    #[track_caller]
+    pub fn lock_stream(&self) -> MutexLockStream<'_, T> {
+        MutexLockStream {
+            acquire: self.lock(),
+        }
+    }
+    /// Shares the IO object behind the mutex between tasks.
+    ///
+    /// The lock is taken for each read, write, flush and close, and kept
+    /// until it completes: the IO object wakes only the task it left
+    /// pending. Between operations other tasks may take their turn. A
+    /// poisoned lock is used all the same.
+    #[cfg(feature = "io")]
+    #[track_caller]
+    pub fn as_io(&self) -> MutexIo<'_, T>
+    where
+        T: Unpin,
+    {
+        MutexIo::new(self)
+    }
+    /// Shares the sink behind the mutex between tasks.
+    ///
+    /// The lock is taken from the first `poll_ready` until the
+    /// `start_send` that follows, so no other item can slip in between,
+    /// and from the first `poll_flush` or `poll_close` until it completes.
+    /// It is kept while the sink returns `Pending`, as the sink wakes only
+    /// the task that polled it. A poisoned lock is written through all the
+    /// same.
+    #[cfg(feature = "sink")]
+    #[track_caller]
+    pub fn as_sink(&self) -> MutexSink<'_, T> {
+        MutexSink::new(self)
+    }
+    #[track_caller]
+    pub fn lock_timeout<D>(&self, delay: D) -> Timeout<MutexAcquire<'_, T>, D>
+    where
+        D: Future<Output = ()>,
+    {
+        self.lock().timeout(delay)
+    }
+    /// Tries to acquire the lock until `timeout` has passed.
+    ///
+    /// The crate has no timer of its own: `sleep` turns the timeout into
+    /// a delay future, such as the sleep function of the executor in use.
+    #[track_caller]
+    pub fn try_lock_for<S, D>(
+        &self,
+        timeout: Duration,
+        sleep: S,
+    ) -> TryLockTimeout<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        TryLockTimeout::new(self.lock(), sleep(timeout))
+    }
+    /// Tries to acquire the lock until `deadline`.
+    ///
+    /// `sleep_until` turns the deadline into a delay future,
+    /// as in `try_lock_for`.
+    #[track_caller]
+    pub fn try_lock_until<S, D>(
+        &self,
+        deadline: Instant,
+        sleep_until: S,
+    ) -> TryLockTimeout<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Instant) -> D,
+        D: Future<Output = ()>,
+    {
+        TryLockTimeout::new(self.lock(), sleep_until(deadline))
+    }
+    /// Like `lock`, but reports poisoning as a `LockError`.
+    #[track_caller]
+    pub fn lock_checked(&self) -> LockChecked<MutexAcquire<'_, T>, future::Pending<()>> {
+        LockChecked::new(self.lock(), future::pending(), false)
+    }
+    /// Like `try_lock_for`, but fails with `LockError::TimedOut`.
+    #[track_caller]
+    pub fn lock_checked_for<S, D>(
+        &self,
+        timeout: Duration,
+        sleep: S,
+    ) -> LockChecked<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        LockChecked::new(self.lock(), sleep(timeout), true)
+    }
+    /// Acquires the lock unless `cancel` completes first,
+    /// in which case it fails with `LockError::Cancelled`.
+    #[track_caller]
+    pub fn lock_cancellable<C>(&self, cancel: C) -> LockChecked<MutexAcquire<'_, T>, C>
+    where
+        C: Future<Output = ()>,
+    {
+        LockChecked::new(self.lock(), cancel, false)
+    }
+    /// Like `lock_cancellable`, with `token.cancelled()` as the cancellation.
+    #[track_caller]
+    pub fn lock_with_cancel<'a>(
+        &'a self,
+        token: &'a CancellationToken,
+    ) -> LockChecked<MutexAcquire<'a, T>, Cancelled<'a>> {
+        self.lock_cancellable(token.cancelled())
+    }
+    /// Runs `f` on the locked data, releasing the lock as soon as it returns.
+    pub async fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> LockResult<R> {
+        poison::map_result(self.lock().await, |mut guard| f(&mut guard))
+    }
+    /// Acquires the lock, then passes the guard to `f`.
+    ///
+    /// Unlike `with_lock`, this returns a nameable future,
+    /// for use in hand-written futures.
+    #[track_caller]
+    pub fn lock_and_then<F, R>(&self, f: F) -> MutexLockAndThen<'_, T, F>
+    where
+        F: FnOnce(MutexGuard<'_, T>) -> R,
+    {
+        MutexLockAndThen {
+            acquire: self.lock(),
+            f: Some(f),
+        }
+    }
+    /// Acquires the lock, then iterates over copies of the items in the
+    /// data, holding the lock until the iterator is dropped.
+    ///
+    /// The items are cloned, as references to them would outlive the lock.
+    #[track_caller]
+    pub fn lock_iter<'a>(&'a self) -> MutexLockIter<'a, T>
+    where
+        &'a T: IntoIterator,
+    {
+        MutexLockAndThen {
+            acquire: self.lock(),
+            f: Some(MutexIter::new),
+        }
+    }
+    /// Like `with_lock`, but `f` may await while holding the lock.
+    pub async fn with_lock_async<R>(&self, f: impl AsyncFnOnce(&mut T) -> R) -> LockResult<R> {
+        let (mut guard, poisoned) = match self.lock().await {
+            Ok(guard) => (guard, false),
+            Err(err) => (err.into_inner(), true),
+        };
+        let ret = f(&mut guard).await;
+        drop(guard);
+        if poisoned {
+            Err(PoisonError::new(ret))
+        } else {
+            Ok(ret)
+        }
+    }
+    /// Like `with_lock_async`, but poisons the mutex if the future is
+    /// dropped before `f` completes.
+    ///
+    /// A future cancelled halfway, say by losing a `select!`, may leave
+    /// the data as half-updated as a panic would. The lock is released
+    /// either way, after the future of `f` is dropped.
+    pub async fn scope<R>(&self, f: impl AsyncFnOnce(&mut T) -> R) -> LockResult<R> {
+        let (mut guard, poisoned) = match self.lock().await {
+            Ok(guard) => (guard, false),
+            Err(err) => (err.into_inner(), true),
+        };
+        // Dropped before `guard`, while the lock is still held.
+        let mut cancelled = PoisonOnDrop {
+            raw: &self.raw,
+            armed: true,
+        };
+        let ret = f(&mut guard).await;
+        cancelled.armed = false;
+        drop(guard);
+        if poisoned {
+            Err(PoisonError::new(ret))
+        } else {
+            Ok(ret)
+        }
+    }
+    /// Acquires the lock once `predicate` holds for the data.
+    ///
+    /// Each time the predicate fails, the lock is released and the task
+    /// waits for someone else to acquire and release it before checking
+    /// again, as with a condition variable notified on every release.
+    #[track_caller]
+    pub fn lock_when<P>(&self, predicate: P) -> MutexLockWhen<'_, T, P>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        MutexLockWhen {
+            acquire: self.lock(),
+            predicate,
+        }
+    }
+    /// Like `try_lock`, but fails with `WouldBlock` unless `predicate`
+    /// holds for the data. The task is not woken later either way.
+    #[track_caller]
+    pub fn try_lock_when(
+        &self,
+        predicate: impl FnOnce(&T) -> bool,
+    ) -> TryLockResult<MutexGuard<'_, T>> {
+        let result = self.try_lock();
+        let holds = match &result {
+            Ok(guard) => predicate(guard),
+            Err(TryLockError::Poisoned(err)) => predicate(err.get_ref()),
+            Err(TryLockError::WouldBlock) => true,
+        };
+        if holds {
+            result
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+    /// Unlike `lock`, this does not queue up for the lock:
+    /// the task is merely woken whenever the lock is released.
+    #[track_caller]
+    pub fn poll_lock(&self, cx: &mut Context<'_>) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        match self.raw.poll_acquire_unqueued(cx) {
+            Poll::Ready(()) => Poll::Ready(self.guard(Caller::here())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    /// Takes the lock if it is free, without registering the task
+    /// to be woken.
+    ///
+    /// This is for callers that retry on their own schedule, such as
+    /// with a backoff: unlike `poll_lock`, a pending poll leaves nothing
+    /// behind, and nothing wakes the task when the lock is released.
+    #[track_caller]
+    pub fn poll_lock_unregistered(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        match self.raw.poll_acquire_unregistered(cx) {
+            Poll::Ready(()) => Poll::Ready(self.guard(Caller::here())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    /// Polls for the lock, keeping the queue position in `token`.
+    ///
+    /// This queues up like `lock`, for futures written by hand.
+    /// The token must have been created by this mutex's `wait_token`.
+    #[track_caller]
+    pub fn poll_lock_with(
+        &self,
+        token: &mut WaitToken<'_>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        assert!(
+            ptr::eq(token.raw, &self.raw),
+            "WaitToken used with another mutex"
+        );
+        self.poll_acquire(&mut token.key, cx, Caller::here())
+    }
+
+    /// Creates a slot for `poll_lock_with`. Dropping the token leaves the queue.
+    pub fn wait_token(&self) -> WaitToken<'_> {
+        WaitToken {
+            raw: &self.raw,
+            key: None,
+        }
+    }
+
+    /// Releases the lock; the same as dropping the guard.
+    pub fn unlock(guard: MutexGuard<'_, T>) {
+        drop(guard);
+    }
+
+    /// Acquires the lock by blocking the current thread.
+    ///
+    /// This is meant for synchronous code sharing the mutex with async
+    /// tasks. Calling it from within an async task blocks the executor,
+    /// which may deadlock if the lock holder runs on the same thread.
+    #[track_caller]
+    pub fn blocking_lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        blocking::block_on(self.lock())
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        if !self.raw.try_acquire_untasked() {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = MutexGuard::new(self, Caller::here());
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(guard).into())
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Like `try_lock`, but returns a guard that holds on to the `Arc`.
+    #[track_caller]
+    pub fn try_lock_owned(self: &Arc<Self>) -> TryLockResult<OwnedMutexGuard<T>> {
+        poison::map_try_result(self.try_lock(), |guard| {
+            OwnedMutexGuard::new(self.clone(), guard)
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.state.load(Ordering::Relaxed) & LOCKED != 0
+    }
+
+    /// Returns the contention statistics gathered since the mutex was created.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> MutexStats {
+        self.raw.stats.get()
+    }
+
+    /// Registers a hook called on the lock events of this mutex, after
+    /// the one set with `instrument::set_hook`, replacing the previous one.
+    #[cfg(feature = "instrument")]
+    pub fn set_event_hook(&self, hook: fn(&LockEvent)) {
+        self.raw.instrument.set_hook(Some(hook));
+    }
+
+    /// Unregisters the hook set with `set_event_hook`, if any.
+    #[cfg(feature = "instrument")]
+    pub fn take_event_hook(&self) {
+        self.raw.instrument.set_hook(None);
+    }
+
+    /// Returns the number of tasks queued up in `lock`.
+    ///
+    /// Tasks polling through `poll_lock` are not counted.
+    pub fn waiter_count(&self) -> usize {
+        self.raw.waiters().len()
+    }
+
+    /// Returns where the tasks queued up in `lock` called it, in the
+    /// order they started waiting, to find out what a hung program is
+    /// waiting for.
+    #[cfg(all(feature = "debug-tracking", feature = "std"))]
+    pub fn waiting_locations(&self) -> Vec<&'static Location<'static>> {
+        self.raw.waiting_at.locations()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.is_poisoned()
+    }
+
+    pub fn clear_poison(&self) {
+        self.raw.state.fetch_and(!POISONED, Ordering::Relaxed);
+    }
+
+    /// Releases the lock without a guard.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held through a guard that has been leaked or
+    /// forgotten, and the data must no longer be accessed through it.
+    pub unsafe fn force_unlock(&self) {
+        self.raw.unlock();
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let inner = unsafe { &mut *self.data.get() };
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    /// Returns a raw pointer to the data, for passing it to FFI.
+    ///
+    /// The pointer may only be dereferenced while the lock is held,
+    /// and accesses through it must not overlap with those through the guard.
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    fn poll_acquire(
+        &self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+        caller: Caller,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        let queued = *key;
+        match self.raw.poll_acquire(key, cx) {
+            Poll::Ready(()) => {
+                if let Some(key) = queued {
+                    self.raw.waiting_at.stopped(key);
+                }
+                Poll::Ready(self.guard(caller))
+            }
+            Poll::Pending => {
+                if let Some(key) = *key {
+                    self.raw.waiting_at.started(key, caller);
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    fn guard(&self, caller: Caller) -> LockResult<MutexGuard<'_, T>> {
+        let guard = MutexGuard::new(self, caller);
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Runs `f` on the data if the lock is free, without it counting as an
+    /// acquisition: stats, the event hook and deadlock detection never see
+    /// it, and a panic in `f` does not poison the mutex. For `Debug` and
+    /// serialization, which only look at the data.
+    pub(super) fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        struct Release<'a>(&'a RawMutex);
+        impl Drop for Release<'_> {
+            fn drop(&mut self) {
+                self.0.release_peek();
+            }
+        }
+
+        if !self.raw.try_acquire() {
+            return None;
+        }
+        let _release = Release(&self.raw);
+        Some(f(unsafe { &*self.data.get() }))
+    }
+}
+
+const LOCKED: usize = 1;
+const POISONED: usize = 2;
+/// Set while the queue holds waiters or wakers, so that releasing the lock
+/// only needs to look at the queue when someone may need a wakeup.
+const WAITING: usize = 4;
+
+impl RawMutex {
+    loom_const_fn! {
+        fn new(spin_limit: u32, policy: WakePolicy, fair_after: Option<Duration>) -> Self {
+            Self {
+                state: AtomicUsize::new(0),
+                waiters: sync::Mutex::new(WaitQueue::new()),
+                spin_limit,
+                policy,
+                fairness: Fairness::new(fair_after),
+                stats: Stats::new(),
+                instrument: Instrument::new(),
+                acquired_at: AcquiredAt::new(),
+                waiting_at: WaitingAt::new(),
+            }
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.state.fetch_or(LOCKED, Ordering::Acquire) & LOCKED == 0
+    }
+
+    fn try_acquire_spinning(&self) -> bool {
+        for round in 0..self.spin_limit {
+            if self.try_acquire() {
+                return true;
+            }
+            for _ in 0..1u32 << round.min(10) {
+                hint::spin_loop();
+            }
+        }
+        self.try_acquire()
+    }
+
+    /// Acquires the lock if it is free, or marks it as waited for.
+    ///
+    /// Must be called with the queue locked, and the caller must then
+    /// queue up if this fails.
+    fn try_acquire_or_wait(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            let (new, acquired) = if state & LOCKED == 0 {
+                (state | LOCKED, true)
+            } else {
+                (state | WAITING, false)
+            };
+            match self.state.compare_exchange_weak(
+                state,
+                new,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return acquired,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    fn poll_acquire(&self, key: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<()> {
+        self.instrument.requested(self.id(), *key, cx.waker());
+        if key.is_none() && self.try_acquire_spinning() {
+            deadlock::acquired(self.id(), None, Some(cx.waker()));
+            self.instrument.acquired(self.id(), None, Some(cx.waker()));
+            self.stats.acquired(None);
+            return Poll::Ready(());
+        }
+
+        let mut waiters = self.waiters();
+        let acquired = match *key {
+            // With fair releases, the lock is never released while someone
+            // is queued, so this can only succeed if the queue is empty.
+            None => self.try_acquire_or_wait(),
+            Some(k) => {
+                waiters.take_notified(k)
+                    // Only an unfair release leaves the lock free while
+                    // someone is queued, in which case the first waiter
+                    // has been woken, or all of them with `WakeAll`.
+                    || ((self.policy == WakePolicy::WakeAll || waiters.is_first(k))
+                        && self.try_acquire_or_wait()
+                        && {
+                            waiters.remove(k);
+                            true
+                        })
+            }
+        };
+        if !acquired {
+            waiters.register(key, cx.waker());
+            self.stats.wait_started(key.unwrap(), waiters.len());
+            // Recorded before a release can hand us the lock, which would
+            // otherwise look like waiting for a lock we hold.
+            deadlock::wait(self.id(), key.unwrap(), cx.waker());
+            drop(waiters);
+            self.instrument.wait_started(self.id(), key.unwrap(), cx.waker());
+            return Poll::Pending;
+        }
+        self.update_waiting(&waiters);
+        drop(waiters);
+        let key = key.take();
+        deadlock::acquired(self.id(), key, Some(cx.waker()));
+        self.instrument.acquired(self.id(), key, Some(cx.waker()));
+        self.stats.acquired(key);
+        if key.is_some() {
+            self.fairness.served();
+        }
+        Poll::Ready(())
+    }
+
+    fn poll_acquire_unqueued(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.try_acquire() {
+            let mut waiters = self.waiters();
+            // The lock may have been released before we took the queue;
+            // retry once so that the wakeup is not lost.
+            if !self.try_acquire_or_wait() {
+                waiters.push_waker(cx.waker());
+                return Poll::Pending;
+            }
+            self.update_waiting(&waiters);
+        }
+        self.acquired_unqueued(Some(cx.waker()));
+        Poll::Ready(())
+    }
+
+    fn poll_acquire_unregistered(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.try_acquire() {
+            return Poll::Pending;
+        }
+        self.acquired_unqueued(Some(cx.waker()));
+        Poll::Ready(())
+    }
+
+    /// Acquires the lock if it is free, outside of any task.
+    ///
+    /// With no waker to identify the caller, the holder is recorded as
+    /// unknown, which still keeps the previous one from being blamed.
+    fn try_acquire_untasked(&self) -> bool {
+        if !self.try_acquire() {
+            return false;
+        }
+        self.acquired_unqueued(None);
+        true
+    }
+
+    fn acquired_unqueued(&self, waker: Option<&Waker>) {
+        deadlock::acquired(self.id(), None, waker);
+        self.instrument.acquired(self.id(), None, waker);
+        self.stats.acquired(None);
+    }
+
+    fn abandon(&self, key: usize) {
+        deadlock::cancel(self.id(), key);
+        self.instrument.cancelled(self.id(), key);
+        self.stats.cancelled(key);
+        self.waiting_at.stopped(key);
+        let mut waiters = self.waiters();
+        let first = waiters.is_first(key);
+        let notified = waiters.remove(key);
+        self.update_waiting(&waiters);
+        if notified {
+            // We have been handed the lock; pass it on.
+            drop(waiters);
+            self.unlock();
+        } else if first && self.state.load(Ordering::Relaxed) & LOCKED == 0 {
+            // We may have been woken by an unfair release; pass that on.
+            let next = waiters.first_waker();
+            drop(waiters);
+            if let Some(next) = next {
+                self.wake(next);
+            }
+        }
+    }
+
+    fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & POISONED != 0
+    }
+
+    fn release(&self, is_panicking: bool) {
+        self.poison_if_panicking(is_panicking);
+        self.unlock();
+    }
+
+    fn release_fair(&self, is_panicking: bool) {
+        self.poison_if_panicking(is_panicking);
+        self.unlock_fair();
+    }
+
+    fn release_unfair(&self, is_panicking: bool) {
+        self.poison_if_panicking(is_panicking);
+        self.unlock_unfair();
+    }
+
+    /// Releases the lock like `release`, and sets `waker` aside for the
+    /// next release. This happens with the queue locked, so that no
+    /// release can come in between.
+    fn release_and_wait(&self, is_panicking: bool, waker: &Waker) {
+        self.poison_if_panicking(is_panicking);
+        self.acquired_at.clear();
+        let mut waiters = self.waiters();
+        // Set before unlocking, so that whoever acquires the lock next
+        // releases it through the queue and wakes `waker`.
+        self.state.fetch_or(WAITING, Ordering::Relaxed);
+        let next = match self.policy {
+            WakePolicy::Handoff => waiters.notify_one(),
+            WakePolicy::WakeOne | WakePolicy::WakeAll => None,
+        };
+        // As in `unlock_fair`, record the handoff with the queue locked.
+        deadlock::released(self.id(), next.as_ref());
+        if next.is_none() {
+            self.state.fetch_and(!LOCKED, Ordering::Release);
+        }
+        let mut wakers = match self.policy {
+            WakePolicy::Handoff => next.clone().into_iter().chain(waiters.take_wakers()).collect(),
+            WakePolicy::WakeOne => {
+                let first = waiters.first_waker();
+                first.into_iter().chain(waiters.take_wakers()).collect()
+            }
+            WakePolicy::WakeAll => waiters.wake_all(),
+        };
+        // Our waker may be left over from an earlier wait.
+        wakers.retain(|other: &Waker| !other.will_wake(waker));
+        waiters.push_waker(waker);
+        drop(waiters);
+        self.instrument.released(self.id());
+
+        for waker in wakers {
+            self.wake(waker);
+        }
+    }
+
+    fn poison(&self) {
+        self.state.fetch_or(POISONED, Ordering::Relaxed);
+    }
+
+    fn poison_if_panicking(&self, is_panicking: bool) {
+        if !is_panicking && thread::panicking() {
+            self.state.fetch_or(POISONED, Ordering::Relaxed);
+        }
+    }
+
+    fn unlock(&self) {
+        let waiting = self.state.load(Ordering::Relaxed) & WAITING != 0;
+        match self.policy {
+            WakePolicy::Handoff => self.unlock_fair(),
+            // Waiters that have been overtaken for too long get the lock.
+            _ if waiting && self.fairness.overdue() => self.unlock_fair(),
+            WakePolicy::WakeOne => self.unlock_unfair(),
+            WakePolicy::WakeAll => self.unlock_wake_all(),
+        }
+    }
+
+    /// Releases the lock with a single compare-exchange if nobody waits.
+    fn try_unlock_uncontended(&self) -> bool {
+        if !self.try_clear_locked() {
+            return false;
+        }
+        deadlock::released(self.id(), None);
+        self.instrument.released(self.id());
+        true
+    }
+
+    /// `try_unlock_uncontended` without reporting the release.
+    fn try_clear_locked(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & WAITING == 0 {
+            match self.state.compare_exchange_weak(
+                state,
+                state & !LOCKED,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => state = actual,
+            }
+        }
+        false
+    }
+
+    /// Releases a lock taken by `Mutex::peek`, reporting nothing. Whoever
+    /// queued up in the meantime is woken as by an unfair release.
+    fn release_peek(&self) {
+        if self.try_clear_locked() {
+            return;
+        }
+        self.state.fetch_and(!LOCKED, Ordering::Release);
+        let mut waiters = self.waiters();
+        let next = waiters.first_waker();
+        let wakers = waiters.take_wakers();
+        self.update_waiting(&waiters);
+        drop(waiters);
+
+        for waker in next.into_iter().chain(wakers) {
+            wake_batch::wake(waker);
+        }
+    }
+
+    fn unlock_fair(&self) {
+        self.acquired_at.clear();
+        if self.try_unlock_uncontended() {
+            return;
+        }
+        let mut waiters = self.waiters();
+        let next = waiters.notify_one();
+        // Record the handoff before the queue is unlocked. The next holder
+        // takes the lock with the queue locked, and may release it again
+        // before a later update, which would then record it as the holder
+        // for good.
+        deadlock::released(self.id(), next.as_ref());
+        if next.is_none() {
+            self.state.fetch_and(!LOCKED, Ordering::Release);
+        }
+        let wakers = waiters.take_wakers();
+        self.update_waiting(&waiters);
+        drop(waiters);
+        self.instrument.released(self.id());
+
+        if let Some(next) = next {
+            self.wake(next);
+        }
+        for waker in wakers {
+            self.wake(waker);
+        }
+    }
+
+    fn unlock_unfair(&self) {
+        self.acquired_at.clear();
+        if self.try_unlock_uncontended() {
+            return;
+        }
+        // Unlock before taking the queue, so that a task queueing up
+        // concurrently either sees the lock free or gets woken here.
+        self.state.fetch_and(!LOCKED, Ordering::Release);
+        let mut waiters = self.waiters();
+        let next = waiters.first_waker();
+        let wakers = waiters.take_wakers();
+        self.update_waiting(&waiters);
+        drop(waiters);
+        deadlock::released(self.id(), None);
+        self.instrument.released(self.id());
+
+        if let Some(next) = next {
+            self.wake(next);
+        }
+        for waker in wakers {
+            self.wake(waker);
+        }
+    }
+
+    fn unlock_wake_all(&self) {
+        self.acquired_at.clear();
+        if self.try_unlock_uncontended() {
+            return;
+        }
+        // As in `unlock_unfair`, unlock before taking the queue.
+        self.state.fetch_and(!LOCKED, Ordering::Release);
+        let mut waiters = self.waiters();
+        let wakers = waiters.wake_all();
+        self.update_waiting(&waiters);
+        drop(waiters);
+        deadlock::released(self.id(), None);
+        self.instrument.released(self.id());
+
+        for waker in wakers {
+            self.wake(waker);
+        }
+    }
+
+    /// Clears `WAITING` once the queue is empty. Must be called with the
+    /// queue locked, after removing waiters or wakers from it.
+    fn update_waiting(&self, waiters: &WaitQueue) {
+        if waiters.is_idle() {
+            self.state.fetch_and(!WAITING, Ordering::Relaxed);
+        }
+    }
+
+    /// Wakes a task waiting for the lock.
+    fn wake(&self, waker: Waker) {
+        self.instrument.woken(self.id(), &waker);
+        wake_batch::wake(waker);
+    }
+
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn queue_position(&self, key: Option<usize>) -> Option<usize> {
+        self.waiters().position(key?)
+    }
+
+    fn waiters(&self) -> sync::MutexGuard<'_, WaitQueue> {
+        // The waiter list is never left in an inconsistent state,
+        // so it is safe to ignore poisoning here.
+        self.waiters
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(x: T) -> Self {
+        Mutex::new(x)
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = self.peek(|data| f.debug_struct("Mutex").field("data", &data).finish());
+        if let Some(result) = shown {
+            result
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            let mut d = f.debug_struct("Mutex");
+            d.field("data", &LockedPlaceholder);
+            self.raw.acquired_at.fmt_field(&mut d);
+            d.finish()
+        }
+    }
+}
+
+/// Unlike the guard of `std::sync::Mutex`, this is `Send` whenever `T` is,
+/// since a task holding it across an await may move to another thread.
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+    is_panicking: bool,
+    locked: bool,
+}
+
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for MutexGuard<'a, T> {}
+
+// Tasks holding a guard or waiting for the lock must be able to move
+// between the threads of a work-stealing executor.
+fn _assert_send<'a, T: ?Sized + Send + 'a>() {
+    fn is_send<S: Send>() {}
+    is_send::<MutexGuard<'a, T>>();
+    is_send::<MappedMutexGuard<'a, T>>();
+    is_send::<OwnedMutexGuard<T>>();
+    is_send::<MutexAcquire<'a, T>>();
+    is_send::<OwnedMutexAcquire<T>>();
+    is_send::<MutexLockStream<'a, T>>();
+    is_send::<WaitToken<'a>>();
+}
+
+impl<'a, T: ?Sized + 'a> MutexGuard<'a, T> {
+    fn new(mutex: &'a Mutex<T>, caller: Caller) -> Self {
+        mutex.raw.acquired_at.set(caller);
+        Self {
+            mutex,
+            is_panicking: thread::panicking(),
+            locked: true,
+        }
+    }
+
+    /// Returns the mutex the guard locks, to lock it again later or
+    /// check it for poisoning.
+    pub fn mutex(this: &Self) -> &'a Mutex<T> {
+        this.mutex
+    }
+
+    /// Turns the guard into a guard of the same mutex as another type,
+    /// typically a trait object:
+    /// `MutexGuard::coerce(guard, |m| m as &Mutex<dyn Trait>)`.
+    ///
+    /// `f` is there to do the unsizing coercion, which generic code cannot
+    /// express on stable Rust. Unlike `map`, the result is still a
+    /// `MutexGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns another mutex.
+    pub fn coerce<U: ?Sized, F>(this: Self, f: F) -> MutexGuard<'a, U>
+    where
+        F: FnOnce(&'a Mutex<T>) -> &'a Mutex<U>,
+    {
+        let mutex = f(this.mutex);
+        assert!(
+            ptr::addr_eq(mutex, this.mutex),
+            "MutexGuard::coerce: the closure returned another mutex"
+        );
+        let guard = MutexGuard {
+            mutex,
+            is_panicking: this.is_panicking,
+            locked: this.locked,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data() }) as *mut U;
+        let guard = MappedMutexGuard {
+            raw: &this.mutex.raw,
+            data,
+            is_panicking: this.is_panicking,
+            shared: None,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Releases the lock, handing it over to the first waiter
+    /// even if the mutex is unfair.
+    pub fn unlock_fair(this: Self) {
+        if this.locked {
+            this.mutex.raw.release_fair(this.is_panicking);
+        }
+        mem::forget(this);
+    }
+
+    /// Releases the lock, waking the first waiter without handing it the
+    /// lock, even if the mutex is fair. A task polling `lock()` first can
+    /// then take the lock ahead of the waiter.
+    pub fn unlock_unfair(this: Self) {
+        if this.locked {
+            this.mutex.raw.release_unfair(this.is_panicking);
+        }
+        mem::forget(this);
+    }
+
+    /// Releases the lock, and has the next release wake `waker`.
+    fn release_and_wait(this: Self, waker: &Waker) {
+        if this.locked {
+            this.mutex.raw.release_and_wait(this.is_panicking, waker);
+        }
+        mem::forget(this);
+    }
+
+    /// Keeps the mutex locked forever, returning a reference to the data
+    /// that lives as long as the mutex.
+    pub fn leak(this: Self) -> &'a mut T {
+        let data = this.data();
+        mem::forget(this);
+        unsafe { &mut *data }
+    }
+
+    /// Holds the lock for `duration`, then releases it.
+    ///
+    /// This is meant for simulating contention in tests. As in
+    /// `Mutex::try_lock_for`, `sleep` turns the duration into a delay future.
+    pub fn hold_for<S, D>(this: Self, duration: Duration, sleep: S) -> MutexHold<'a, T, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        MutexHold {
+            guard: Some(this),
+            delay: sleep(duration),
+        }
+    }
+
+    /// Releases the lock while `f` runs, then acquires it again.
+    ///
+    /// Poisoning that happens while the lock is released is not reported.
+    /// If the returned future is dropped before the lock is acquired again,
+    /// the guard is left unlocked and any further access through it panics.
+    pub async fn unlocked<R>(this: &mut Self, f: impl Future<Output = R>) -> R {
+        this.mutex.raw.release(this.is_panicking);
+        this.locked = false;
+        let ret = f.await;
+        let guard = this
+            .mutex
+            .lock()
+            .await
+            .unwrap_or_else(PoisonError::into_inner);
+        this.is_panicking = guard.is_panicking;
+        mem::forget(guard);
+        this.locked = true;
+        ret
+    }
+
+    fn data(&self) -> *mut T {
+        assert!(self.locked, "MutexGuard used after `unlocked` was cancelled");
+        self.mutex.data.get()
+    }
+}
+
+impl<'a, A: 'a, B: 'a> MutexGuard<'a, (A, B)> {
+    /// Splits the guard into guards of the two fields, which can be used
+    /// and dropped separately, even on different threads. The lock is
+    /// released once both are dropped.
+    pub fn split(this: Self) -> (MappedMutexGuard<'a, A>, MappedMutexGuard<'a, B>) {
+        MappedMutexGuard::split(MutexGuard::map(this, |data| data))
+    }
+}
+
+impl<'a, T: 'a> MutexGuard<'a, [T]> {
+    /// Like `split`, but splits a slice at `mid` as `<[T]>::split_at_mut`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    pub fn split_at(
+        this: Self,
+        mid: usize,
+    ) -> (MappedMutexGuard<'a, [T]>, MappedMutexGuard<'a, [T]>) {
+        MappedMutexGuard::split_at(MutexGuard::map(this, |data| data), mid)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> ClearPoison for MutexGuard<'a, T> {
+    fn clear_poison(guard: &Self) {
+        guard.mutex.clear_poison();
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        if self.locked {
+            self.mutex.raw.release(self.is_panicking);
+        }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("MutexGuard");
+        d.field("data", &(self as &T));
+        self.mutex.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedMutexGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawMutex,
+    data: *mut T,
+    is_panicking: bool,
+    /// Shared by the parts of a split guard, the last of which releases
+    /// the lock.
+    shared: Option<Arc<()>>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<'a, T: ?Sized + Send + 'a> Send for MappedMutexGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for MappedMutexGuard<'a, T> {}
+
+impl<'a, T: ?Sized + 'a> MappedMutexGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(mut this: Self, f: F) -> MappedMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data }) as *mut U;
+        let guard = MappedMutexGuard {
+            raw: this.raw,
+            data,
+            is_panicking: this.is_panicking,
+            shared: this.shared.take(),
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Splits the guard into guards of two disjoint parts of the data,
+    /// as returned by `f`.
+    fn split_with<U: ?Sized, V: ?Sized, F>(
+        mut this: Self,
+        f: F,
+    ) -> (MappedMutexGuard<'a, U>, MappedMutexGuard<'a, V>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let (first, second) = f(unsafe { &mut *this.data });
+        let shared = this.shared.take().unwrap_or_default();
+        let first = MappedMutexGuard {
+            raw: this.raw,
+            data: first as *mut U,
+            is_panicking: this.is_panicking,
+            shared: Some(shared.clone()),
+            _marker: PhantomData,
+        };
+        let second = MappedMutexGuard {
+            raw: this.raw,
+            data: second as *mut V,
+            is_panicking: this.is_panicking,
+            shared: Some(shared),
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        (first, second)
+    }
+}
+
+impl<'a, A: 'a, B: 'a> MappedMutexGuard<'a, (A, B)> {
+    /// Like `MutexGuard::split`.
+    pub fn split(this: Self) -> (MappedMutexGuard<'a, A>, MappedMutexGuard<'a, B>) {
+        MappedMutexGuard::split_with(this, |(a, b)| (a, b))
+    }
+}
+
+impl<'a, T: 'a> MappedMutexGuard<'a, [T]> {
+    /// Like `MutexGuard::split_at`.
+    pub fn split_at(
+        this: Self,
+        mid: usize,
+    ) -> (MappedMutexGuard<'a, [T]>, MappedMutexGuard<'a, [T]>) {
+        MappedMutexGuard::split_with(this, |data| data.split_at_mut(mid))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MappedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            // Only the last part of a split guard gets the token back.
+            if Arc::into_inner(shared).is_none() {
+                self.raw.poison_if_panicking(self.is_panicking);
+                return;
+            }
+        }
+        self.raw.release(self.is_panicking);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("MappedMutexGuard");
+        d.field("data", &(self as &T));
+        self.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+/// The future returned by `Mutex::lock`.
+///
+/// Polling it again after it has completed starts a new acquisition, which
+/// queues up behind the other waiters like a fresh call to `lock` would.
+/// Until then, `is_terminated` returns `true`, so `select!` leaves it alone.
+#[derive(Debug)]
+pub struct MutexAcquire<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+    key: Option<usize>,
+    caller: Caller,
+    terminated: bool,
+}
+
+impl<'a, T: ?Sized + 'a> MutexAcquire<'a, T> {
+    /// Returns the mutex being acquired.
+    pub fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+
+    pub fn timeout<D>(self, delay: D) -> Timeout<Self, D>
+    where
+        D: Future<Output = ()>,
+    {
+        Timeout::new(self, delay)
+    }
+
+    /// Counts the tasks queued ahead of this one: 0 for the first in line.
+    ///
+    /// Returns `None` unless the future is waiting in the queue, i.e. before
+    /// it is first polled and after it acquires the lock. A caller can
+    /// check this between polls to give up when the queue is too long.
+    pub fn queue_position(&self) -> Option<usize> {
+        self.mutex.raw.queue_position(self.key)
+    }
+
+    /// Acquires the lock if it is free right now, like `try_lock`, or
+    /// gives the future back to wait for it.
+    ///
+    /// This lets a caller skip boxing or spawning the wait when the lock
+    /// is not contended. A future that is already queued is given back
+    /// as is, as taking the lock ahead of it would jump the queue.
+    pub fn try_now(self) -> Result<LockResult<MutexGuard<'a, T>>, Self> {
+        if self.key.is_some() || !self.mutex.raw.try_acquire_untasked() {
+            return Err(self);
+        }
+        Ok(self.mutex.guard(self.caller))
+    }
+
+    /// Converts this into a futures 0.1 future, which resolves to the guard
+    /// or fails with the poison error.
+    #[cfg(feature = "compat")]
+    pub fn compat(self) -> Compat<Pin<Box<Self>>> {
+        Compat::new(Box::pin(self))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Future for MutexAcquire<'a, T> {
+    type Output = LockResult<MutexGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let poll = this.mutex.poll_acquire(&mut this.key, cx, this.caller);
+        this.terminated = poll.is_ready();
+        poll
+    }
+}
+
+impl<'a, T: ?Sized + 'a> CheckedAcquire for MutexAcquire<'a, T> {
+    type Guard = MutexGuard<'a, T>;
+    fn poll_checked(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Guard, LockError<Self::Guard>>> {
+        self.poll(cx).map(|result| result.map_err(LockError::Poisoned))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> FusedFuture for MutexAcquire<'a, T> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MutexAcquire<'a, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.mutex.raw.abandon(key);
+        }
+    }
+}
+
+/// Holds a guard until a delay completes; see `MutexGuard::hold_for`.
+#[derive(Debug)]
+pub struct MutexHold<'a, T: ?Sized + 'a, D> {
+    guard: Option<MutexGuard<'a, T>>,
+    delay: D,
+}
+
+impl<'a, T: ?Sized + 'a, D: Future<Output = ()>> Future for MutexHold<'a, T, D> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The delay is pinned along with `self`; the guard is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let delay = unsafe { Pin::new_unchecked(&mut this.delay) };
+        match delay.poll(cx) {
+            Poll::Ready(()) => {
+                this.guard = None;
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MutexLockStream<'a, T: ?Sized + 'a> {
+    acquire: MutexAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Stream for MutexLockStream<'a, T> {
+    type Item = LockResult<MutexGuard<'a, T>>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The acquisition starts over once it has completed.
+        Pin::new(&mut self.acquire).poll(cx).map(Some)
+    }
+}
+
+pub struct MutexLockWhen<'a, T: ?Sized + 'a, P> {
+    acquire: MutexAcquire<'a, T>,
+    predicate: P,
+}
+
+impl<'a, T: ?Sized + 'a, P> Future for MutexLockWhen<'a, T, P>
+where
+    P: FnMut(&T) -> bool,
+{
+    type Output = LockResult<MutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The acquisition is pinned along with `self`; the predicate is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = unsafe { Pin::new_unchecked(&mut this.acquire) };
+        // The acquisition starts over once it has completed.
+        let result = match acquire.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        let holds = match &result {
+            Ok(guard) => (this.predicate)(guard),
+            Err(err) => (this.predicate)(err.get_ref()),
+        };
+        if holds {
+            return Poll::Ready(result);
+        }
+        let guard = result.unwrap_or_else(PoisonError::into_inner);
+        MutexGuard::release_and_wait(guard, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<'a, T: ?Sized + 'a, P> fmt::Debug for MutexLockWhen<'a, T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("MutexLockWhen { .. }")
+    }
+}
+
+pub struct MutexLockAndThen<'a, T: ?Sized + 'a, F> {
+    acquire: MutexAcquire<'a, T>,
+    f: Option<F>,
+}
+
+impl<'a, T: ?Sized + 'a, F, R> Future for MutexLockAndThen<'a, T, F>
+where
+    F: FnOnce(MutexGuard<'a, T>) -> R,
+{
+    type Output = LockResult<R>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `f` is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = Pin::new(&mut this.acquire);
+        match acquire.poll(cx) {
+            Poll::Ready(result) => {
+                let f = this.f.take().expect("MutexLockAndThen polled after completion");
+                Poll::Ready(poison::map_result(result, f))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a, F, R> FusedFuture for MutexLockAndThen<'a, T, F>
+where
+    F: FnOnce(MutexGuard<'a, T>) -> R,
+{
+    fn is_terminated(&self) -> bool {
+        self.f.is_none()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a, F> fmt::Debug for MutexLockAndThen<'a, T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexLockAndThen")
+            .field("acquire", &self.acquire)
+            .finish()
+    }
+}
+
+/// The future returned by `Mutex::lock_iter`.
+pub type MutexLockIter<'a, T> = MutexLockAndThen<'a, T, fn(MutexGuard<'a, T>) -> MutexIter<'a, T>>;
+
+/// Iterates over copies of the items in the data of a locked mutex.
+///
+/// Created by `Mutex::lock_iter`.
+pub struct MutexIter<'a, T: ?Sized + 'a>
+where
+    &'a T: IntoIterator,
+{
+    // Borrows the data locked by `guard`, so it must be dropped first.
+    iter: <&'a T as IntoIterator>::IntoIter,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> MutexIter<'a, T>
+where
+    &'a T: IntoIterator,
+{
+    fn new(guard: MutexGuard<'a, T>) -> Self {
+        // The data stays locked, and only shared, as long as `guard` lives.
+        let data = unsafe { &*guard.data() };
+        Self {
+            iter: data.into_iter(),
+            guard,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a, U: Clone + 'a> Iterator for MutexIter<'a, T>
+where
+    &'a T: IntoIterator<Item = &'a U>,
+{
+    type Item = U;
+    fn next(&mut self) -> Option<U> {
+        self.iter.next().cloned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MutexIter<'a, T>
+where
+    &'a T: IntoIterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexIter")
+            .field("guard", &self.guard)
+            .finish()
+    }
+}
+
+pub struct OwnedMutexGuard<T: ?Sized> {
+    mutex: Arc<Mutex<T>>,
+    is_panicking: bool,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for OwnedMutexGuard<T> {}
+
+impl<T: ?Sized> OwnedMutexGuard<T> {
+    fn new(mutex: Arc<Mutex<T>>, guard: MutexGuard<'_, T>) -> Self {
+        let is_panicking = guard.is_panicking;
+        mem::forget(guard);
+        Self {
+            mutex,
+            is_panicking,
+        }
+    }
+
+    pub fn mutex(this: &Self) -> &Arc<Mutex<T>> {
+        &this.mutex
+    }
+}
+
+impl<T: ?Sized> ClearPoison for OwnedMutexGuard<T> {
+    fn clear_poison(guard: &Self) {
+        guard.mutex.clear_poison();
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedMutexGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedMutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexGuard<T> {
+    fn drop(&mut self) {
+        self.mutex.raw.release(self.is_panicking);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("OwnedMutexGuard");
+        d.field("data", &(self as &T));
+        self.mutex.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+/// The future returned by `Mutex::lock_owned`.
+///
+/// Like `MutexAcquire`, it starts over if polled after completion.
+#[derive(Debug)]
+pub struct OwnedMutexAcquire<T: ?Sized> {
+    mutex: Arc<Mutex<T>>,
+    key: Option<usize>,
+    caller: Caller,
+    terminated: bool,
+}
+
+impl<T: ?Sized> OwnedMutexAcquire<T> {
+    pub fn mutex(&self) -> &Arc<Mutex<T>> {
+        &self.mutex
+    }
+
+    /// Like `MutexAcquire::queue_position`.
+    pub fn queue_position(&self) -> Option<usize> {
+        self.mutex.raw.queue_position(self.key)
+    }
+}
+
+impl<T: ?Sized> Future for OwnedMutexAcquire<T> {
+    type Output = LockResult<OwnedMutexGuard<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mutex = &this.mutex;
+        let result = match mutex.poll_acquire(&mut this.key, cx, this.caller) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                this.terminated = false;
+                return Poll::Pending;
+            }
+        };
+        let result = poison::map_result(result, |guard| {
+            OwnedMutexGuard::new(mutex.clone(), guard)
+        });
+        this.terminated = true;
+        Poll::Ready(result)
+    }
+}
+
+impl<T: ?Sized> FusedFuture for OwnedMutexAcquire<T> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexAcquire<T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.mutex.raw.abandon(key);
+        }
+    }
+}
+
+/// Poisons the mutex when dropped armed; see `Mutex::scope`.
+struct PoisonOnDrop<'a> {
+    raw: &'a RawMutex,
+    armed: bool,
+}
+
+impl<'a> Drop for PoisonOnDrop<'a> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.raw.poison();
+        }
+    }
+}
+
+/// A queue position for `Mutex::poll_lock_with`.
+pub struct WaitToken<'a> {
+    raw: &'a RawMutex,
+    key: Option<usize>,
+}
+
+impl<'a> Drop for WaitToken<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.raw.abandon(key);
+        }
+    }
+}
+
+impl<'a> fmt::Debug for WaitToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("WaitToken { .. }")
+    }
+}
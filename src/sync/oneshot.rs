@@ -0,0 +1,133 @@
+//! A channel for sending a single value.
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use crate::loom::sync;
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(sync::Mutex::new(State {
+        value: None,
+        sender_gone: false,
+        receiver_gone: false,
+        waker: None,
+    }));
+    let sender = Sender {
+        shared: shared.clone(),
+    };
+    (sender, Receiver { shared })
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_gone: bool,
+    receiver_gone: bool,
+    waker: Option<Waker>,
+}
+
+fn state<T>(shared: &sync::Mutex<State<T>>) -> sync::MutexGuard<'_, State<T>> {
+    // The state is never left inconsistent,
+    // so it is safe to ignore poisoning here.
+    shared.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub struct Sender<T> {
+    shared: Arc<sync::Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Completes the channel with `value`.
+    ///
+    /// Returns the value back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = state(&self.shared);
+        if state.receiver_gone {
+            return Err(value);
+        }
+        state.value = Some(value);
+        Ok(())
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        state(&self.shared).receiver_gone
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = state(&self.shared);
+        state.sender_gone = true;
+        let waker = state.waker.take();
+        drop(state);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Sender { .. }")
+    }
+}
+
+/// Resolves to the sent value, or to `Canceled` if the sender is dropped
+/// without sending.
+pub struct Receiver<T> {
+    shared: Arc<sync::Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<Option<T>, Canceled> {
+        let mut state = state(&self.shared);
+        match state.value.take() {
+            Some(value) => Ok(Some(value)),
+            None if state.sender_gone => Err(Canceled(())),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = state(&self.shared);
+        match state.value.take() {
+            Some(value) => Poll::Ready(Ok(value)),
+            None if state.sender_gone => Poll::Ready(Err(Canceled(()))),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        state(&self.shared).receiver_gone = true;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Receiver { .. }")
+    }
+}
+
+/// The sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled(());
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("oneshot canceled")
+    }
+}
+
+impl Error for Canceled {}
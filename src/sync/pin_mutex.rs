@@ -0,0 +1,122 @@
+use std::fmt;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::{LockResult, TryLockResult};
+use std::task::{Context, Poll};
+
+use futures_core::future::FusedFuture;
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+use crate::poison;
+
+/// A `Mutex` with structural pinning, for data that must not move, such
+/// as a future or a stream.
+///
+/// The mutex is locked through a `Pin<&Self>`, so the data is pinned
+/// along with it, and guards hand it out as `Pin<&mut T>` through
+/// `as_mut`. Nothing gives out `&mut T` unless `T: Unpin`.
+pub struct PinMutex<T: ?Sized> {
+    inner: Mutex<T>,
+}
+
+impl<T> PinMutex<T> {
+    loom_const_fn! {
+        pub fn new(inner: T) -> Self {
+            Self {
+                inner: Mutex::new(inner),
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> PinMutex<T> {
+    #[track_caller]
+    pub fn lock(self: Pin<&Self>) -> PinMutexAcquire<'_, T> {
+        PinMutexAcquire {
+            acquire: self.get_ref().inner.lock(),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(self: Pin<&Self>) -> TryLockResult<PinMutexGuard<'_, T>> {
+        poison::map_try_result(self.get_ref().inner.try_lock(), PinMutexGuard::new)
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for PinMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PinMutex")
+            .field("inner", &&self.inner)
+            .finish()
+    }
+}
+
+pub struct PinMutexAcquire<'a, T: ?Sized + 'a> {
+    acquire: MutexAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for PinMutexAcquire<'a, T> {
+    type Output = LockResult<PinMutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let acquire = unsafe { self.map_unchecked_mut(|this| &mut this.acquire) };
+        match acquire.poll(cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, PinMutexGuard::new)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> FusedFuture for PinMutexAcquire<'a, T> {
+    fn is_terminated(&self) -> bool {
+        self.acquire.is_terminated()
+    }
+}
+
+impl<'a, T: ?Sized + 'a> fmt::Debug for PinMutexAcquire<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("PinMutexAcquire { .. }")
+    }
+}
+
+pub struct PinMutexGuard<'a, T: ?Sized + 'a> {
+    guard: MutexGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> PinMutexGuard<'a, T> {
+    fn new(guard: MutexGuard<'a, T>) -> Self {
+        Self { guard }
+    }
+
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // The mutex is pinned, and the data is never moved out of it.
+        unsafe { Pin::new_unchecked(&mut *self.guard) }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for PinMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized + Unpin + 'a> DerefMut for PinMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for PinMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.guard, f)
+    }
+}
@@ -0,0 +1,1211 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, LockResult, PoisonError, TryLockError, TryLockResult};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::blocking;
+use crate::loom::sync::atomic::{AtomicBool, Ordering};
+use crate::loom::sync;
+use crate::poison;
+use crate::waiter::WaitQueue;
+
+/// A reader-writer lock whose acquisition is a future, shared between
+/// threads.
+///
+/// Unlike those of `std::sync::RwLock`, the guards are `Send`, and read
+/// guards are `Send` and `Sync` whenever `T` is both, so that tasks may
+/// hold them across an await on a work-stealing executor and hand them to
+/// worker threads.
+pub struct RwLock<T: ?Sized> {
+    raw: RawRwLock,
+    data: UnsafeCell<T>,
+}
+
+/// The state of a `RwLock`, apart from the data it protects.
+struct RawRwLock {
+    state: sync::Mutex<State>,
+    poisoned: AtomicBool,
+}
+
+/// Everything that acquisitions and releases decide on is kept under one
+/// lock, so that no release can slip in between a failed acquisition and
+/// its registration.
+struct State {
+    readers: usize,
+    writer: bool,
+    upgradable: bool,
+    /// One queue per `Access`, so that releasing the lock only wakes
+    /// the waiters that can make progress.
+    waiters: [WaitQueue; 4],
+    /// Queued writers and upgrades, which block new readers
+    /// if writers are preferred.
+    waiting_writers: usize,
+    prefer_writers: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Upgradable,
+    Write,
+    Upgrade,
+}
+
+impl Access {
+    /// Whether waiting for this access holds back new readers
+    /// if writers are preferred.
+    fn is_exclusive(self) -> bool {
+        match self {
+            Access::Read | Access::Upgradable => false,
+            Access::Write | Access::Upgrade => true,
+        }
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T: ?Sized> UnwindSafe for RwLock<T> {}
+impl<T: ?Sized> RefUnwindSafe for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    loom_const_fn! {
+        pub fn new(inner: T) -> Self {
+            RwLockBuilder::new().build(inner)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { raw, data } = self;
+        let poisoned = raw.is_poisoned();
+        let inner = data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+}
+
+/// Configures a `RwLock` before creating it.
+#[derive(Debug, Clone, Copy)]
+pub struct RwLockBuilder {
+    prefer_writers: bool,
+}
+
+impl RwLockBuilder {
+    pub const fn new() -> Self {
+        Self {
+            prefer_writers: true,
+        }
+    }
+
+    /// Sets whether a queued writer blocks new readers.
+    ///
+    /// Preferring writers keeps a steady stream of readers from starving
+    /// writers, but a task that takes a second read lock while holding one
+    /// deadlocks if a writer queues up in between, unless it uses
+    /// `RwLock::read_recursive`. The default is to prefer writers.
+    pub const fn prefer_writers(self, prefer_writers: bool) -> Self {
+        Self { prefer_writers }
+    }
+
+    loom_const_fn! {
+        pub fn build<T>(self, inner: T) -> RwLock<T> {
+            RwLock {
+                raw: RawRwLock {
+                    state: sync::Mutex::new(State {
+                        readers: 0,
+                        writer: false,
+                        upgradable: false,
+                        waiters: [
+                            WaitQueue::new(),
+                            WaitQueue::new(),
+                            WaitQueue::new(),
+                            WaitQueue::new(),
+                        ],
+                        waiting_writers: 0,
+                        prefer_writers: self.prefer_writers,
+                    }),
+                    poisoned: AtomicBool::new(false),
+                },
+                data: UnsafeCell::new(inner),
+            }
+        }
+    }
+}
+
+impl Default for RwLockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadAcquire<'_, T> {
+        RwLockReadAcquire {
+            rwlock: self,
+            key: None,
+            recursive: false,
+        }
+    }
+    /// Like `read`, but gets past queued writers while the lock is
+    /// already shared, so that a task holding a read guard can take
+    /// another one even if writers are preferred.
+    ///
+    /// Tasks have no identity, so this lets in any reader while some
+    /// task holds the lock for reading. Use it only where the task
+    /// already holds a read guard, or writers may starve.
+    pub fn read_recursive(&self) -> RwLockReadAcquire<'_, T> {
+        RwLockReadAcquire {
+            rwlock: self,
+            key: None,
+            recursive: true,
+        }
+    }
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<LockResult<RwLockReadGuard<'_, T>>> {
+        let mut state = self.raw.state();
+        if state.readers_blocked() {
+            state.waiters[Access::Read as usize].push_waker(cx.waker());
+            return Poll::Pending;
+        }
+        state.readers += 1;
+        drop(state);
+
+        Poll::Ready(self.read_guard())
+    }
+
+    /// Acquires shared access by blocking the current thread.
+    ///
+    /// This is meant for synchronous code, such as a thread pool, sharing
+    /// the lock with async tasks. Calling it from within an async task
+    /// blocks the executor, which may deadlock if the writer runs on the
+    /// same thread.
+    pub fn blocking_read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        blocking::block_on(self.read())
+    }
+
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        let mut state = self.raw.state();
+        if state.readers_blocked() {
+            return Err(TryLockError::WouldBlock);
+        }
+        state.readers += 1;
+        drop(state);
+        self.read_guard().map_err(TryLockError::from)
+    }
+
+    /// Like `try_read`, but gets past queued writers as `read_recursive` does.
+    pub fn try_read_recursive(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        let mut state = self.raw.state();
+        if state.recursive_readers_blocked() {
+            return Err(TryLockError::WouldBlock);
+        }
+        state.readers += 1;
+        drop(state);
+        self.read_guard().map_err(TryLockError::from)
+    }
+
+    /// Acquires shared access that can later be upgraded to exclusive access.
+    ///
+    /// An upgradable reader coexists with plain readers, but excludes
+    /// writers and other upgradable readers.
+    pub fn upgradable_read(&self) -> RwLockUpgradableReadAcquire<'_, T> {
+        RwLockUpgradableReadAcquire {
+            rwlock: self,
+            key: None,
+        }
+    }
+
+    pub fn try_upgradable_read(&self) -> TryLockResult<RwLockUpgradableReadGuard<'_, T>> {
+        let mut state = self.raw.state();
+        if state.readers_blocked() || state.upgradable {
+            return Err(TryLockError::WouldBlock);
+        }
+        state.upgradable = true;
+        drop(state);
+        self.upgradable_guard().map_err(TryLockError::from)
+    }
+
+    pub fn write(&self) -> RwLockWriteAcquire<'_, T> {
+        RwLockWriteAcquire {
+            rwlock: self,
+            key: None,
+        }
+    }
+    pub fn poll_write(&self, cx: &mut Context<'_>) -> Poll<LockResult<RwLockWriteGuard<'_, T>>> {
+        let mut state = self.raw.state();
+        if state.writers_blocked() {
+            state.waiters[Access::Write as usize].push_waker(cx.waker());
+            return Poll::Pending;
+        }
+        state.writer = true;
+        drop(state);
+
+        Poll::Ready(self.write_guard())
+    }
+
+    /// Acquires exclusive access by blocking the current thread,
+    /// like `blocking_read`.
+    pub fn blocking_write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        blocking::block_on(self.write())
+    }
+
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        let mut state = self.raw.state();
+        if state.writers_blocked() {
+            return Err(TryLockError::WouldBlock);
+        }
+        state.writer = true;
+        drop(state);
+        self.write_guard().map_err(TryLockError::from)
+    }
+
+    /// Acquires shared access through an `Arc`, so that the guard has no
+    /// lifetime and can be stored away or moved into a spawned task.
+    pub fn read_owned(self: &Arc<Self>) -> OwnedRwLockReadAcquire<T> {
+        OwnedRwLockReadAcquire {
+            rwlock: self.clone(),
+            key: None,
+        }
+    }
+
+    pub fn try_read_owned(self: &Arc<Self>) -> TryLockResult<OwnedRwLockReadGuard<T>> {
+        poison::map_try_result(self.try_read(), |guard| {
+            OwnedRwLockReadGuard::new(self.clone(), guard)
+        })
+    }
+
+    /// Acquires exclusive access through an `Arc`, like `read_owned`.
+    pub fn write_owned(self: &Arc<Self>) -> OwnedRwLockWriteAcquire<T> {
+        OwnedRwLockWriteAcquire {
+            rwlock: self.clone(),
+            key: None,
+        }
+    }
+
+    pub fn try_write_owned(self: &Arc<Self>) -> TryLockResult<OwnedRwLockWriteGuard<T>> {
+        poison::map_try_result(self.try_write(), |guard| {
+            OwnedRwLockWriteGuard::new(self.clone(), guard)
+        })
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.is_poisoned()
+    }
+
+    pub fn clear_poison(&self) {
+        self.raw.poisoned.store(false, Ordering::Relaxed);
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let inner = unsafe { &mut *self.data.get() };
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    fn poll_acquire_read(
+        &self,
+        key: &mut Option<usize>,
+        recursive: bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockReadGuard<'_, T>>> {
+        let mut state = self.raw.state();
+        let blocked = if recursive {
+            state.recursive_readers_blocked()
+        } else {
+            state.readers_blocked()
+        };
+        if blocked {
+            state.register(Access::Read, key, cx.waker());
+            return Poll::Pending;
+        }
+        state.abandon(Access::Read, key);
+        state.readers += 1;
+        drop(state);
+
+        Poll::Ready(self.read_guard())
+    }
+
+    fn poll_acquire_write(
+        &self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockWriteGuard<'_, T>>> {
+        let mut state = self.raw.state();
+        if state.writers_blocked() {
+            state.register(Access::Write, key, cx.waker());
+            return Poll::Pending;
+        }
+        state.abandon(Access::Write, key);
+        state.writer = true;
+        drop(state);
+
+        Poll::Ready(self.write_guard())
+    }
+
+    fn poll_acquire_upgradable(
+        &self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockUpgradableReadGuard<'_, T>>> {
+        let mut state = self.raw.state();
+        if state.readers_blocked() || state.upgradable {
+            state.register(Access::Upgradable, key, cx.waker());
+            return Poll::Pending;
+        }
+        state.abandon(Access::Upgradable, key);
+        state.upgradable = true;
+        drop(state);
+
+        Poll::Ready(self.upgradable_guard())
+    }
+
+    /// Waits for the remaining readers to leave, then trades the upgradable
+    /// read for the write lock. Only the holder of the upgradable read may
+    /// call this.
+    fn poll_upgrade(&self, key: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.raw.state();
+        if state.readers > 0 {
+            state.register(Access::Upgrade, key, cx.waker());
+            return Poll::Pending;
+        }
+        state.abandon(Access::Upgrade, key);
+        state.upgradable = false;
+        state.writer = true;
+
+        Poll::Ready(())
+    }
+
+    fn read_guard(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let guard = RwLockReadGuard { rwlock: self };
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    fn upgradable_guard(&self) -> LockResult<RwLockUpgradableReadGuard<'_, T>> {
+        let guard = RwLockUpgradableReadGuard { rwlock: self };
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    fn write_guard(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let guard = RwLockWriteGuard::new(self);
+        if self.raw.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+}
+
+impl State {
+    fn readers_blocked(&self) -> bool {
+        self.writer || (self.prefer_writers && self.waiting_writers > 0)
+    }
+
+    /// Queued writers only block recursive readers while nobody else
+    /// shares the lock.
+    fn recursive_readers_blocked(&self) -> bool {
+        let shared = self.readers > 0 || self.upgradable;
+        self.writer || (!shared && self.readers_blocked())
+    }
+
+    fn writers_blocked(&self) -> bool {
+        self.writer || self.upgradable || self.readers > 0
+    }
+
+    fn register(&mut self, access: Access, key: &mut Option<usize>, waker: &Waker) {
+        if key.is_none() && access.is_exclusive() {
+            self.waiting_writers += 1;
+        }
+        self.waiters[access as usize].register(key, waker);
+    }
+
+    fn abandon(&mut self, access: Access, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            self.waiters[access as usize].remove(key);
+            if access.is_exclusive() {
+                self.waiting_writers -= 1;
+            }
+        }
+    }
+
+    /// Wakes the waiters that can make progress in the current state:
+    /// the first writer or upgrade, or all readers and the first
+    /// upgradable reader.
+    fn wake_ready(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        if self.upgradable && self.readers == 0 {
+            wakers.extend(self.waiters[Access::Upgrade as usize].first_waker());
+        }
+        if !self.writers_blocked() {
+            let waiters = &mut self.waiters[Access::Write as usize];
+            wakers.extend(waiters.take_wakers());
+            wakers.extend(waiters.first_waker());
+        }
+        if !self.readers_blocked() {
+            wakers.extend(self.waiters[Access::Read as usize].wake_all());
+            if !self.upgradable {
+                wakers.extend(self.waiters[Access::Upgradable as usize].first_waker());
+            }
+        }
+        wakers
+    }
+}
+
+impl RawRwLock {
+    fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+
+    fn poison_if_panicking(&self, is_panicking: bool) {
+        if !is_panicking && thread::panicking() {
+            self.poisoned.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Withdraws a waiter that gives up waiting.
+    fn cancel(&self, access: Access, key: &mut Option<usize>) {
+        if key.is_none() {
+            return;
+        }
+        let mut state = self.state();
+        state.abandon(access, key);
+        // Only plain readers are all woken at once; anyone else may
+        // have been the single waiter woken, or may be holding back
+        // the readers.
+        if access != Access::Read {
+            self.wake_ready(state);
+        }
+    }
+
+    /// Wakes the waiters that can make progress, after releasing `state`.
+    fn wake_ready(&self, mut state: sync::MutexGuard<'_, State>) {
+        let wakers = state.wake_ready();
+        drop(state);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn read_unlock(&self) {
+        let mut state = self.state();
+        state.readers -= 1;
+        if state.readers == 0 {
+            self.wake_ready(state);
+        }
+    }
+
+    fn write_unlock(&self, is_panicking: bool) {
+        self.poison_if_panicking(is_panicking);
+        let mut state = self.state();
+        state.writer = false;
+        self.wake_ready(state);
+    }
+
+    fn upgradable_unlock(&self) {
+        let mut state = self.state();
+        state.upgradable = false;
+        self.wake_ready(state);
+    }
+
+    fn state(&self) -> sync::MutexGuard<'_, State> {
+        // The state is never left inconsistent,
+        // so it is safe to ignore poisoning here.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(x: T) -> Self {
+        RwLock::new(x)
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let guard = match self.try_read() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(err)) => Ok(err.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(()),
+        };
+        if let Ok(guard) = guard {
+            f.debug_struct("RwLock")
+                .field("data", &(&guard as &T))
+                .finish()
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            f.debug_struct("RwLock")
+                .field("data", &LockedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+// Read guards may be shared with, and moved to, other threads, such as
+// a thread pool working on the data while the task awaits it.
+fn _assert_send_sync<'a, T: ?Sized + Send + Sync + 'a>() {
+    fn is_send_sync<S: Send + Sync>() {}
+    is_send_sync::<RwLockReadGuard<'a, T>>();
+    is_send_sync::<MappedRwLockReadGuard<'a, T>>();
+    is_send_sync::<OwnedRwLockReadGuard<T>>();
+    is_send_sync::<RwLockUpgradableReadGuard<'a, T>>();
+    is_send_sync::<RwLockWriteGuard<'a, T>>();
+    is_send_sync::<MappedRwLockWriteGuard<'a, T>>();
+    is_send_sync::<OwnedRwLockWriteGuard<T>>();
+    is_send_sync::<RwLockReadAcquire<'a, T>>();
+    is_send_sync::<RwLockWriteAcquire<'a, T>>();
+    is_send_sync::<RwLockUpgradableReadAcquire<'a, T>>();
+    is_send_sync::<RwLockUpgrade<'a, T>>();
+    is_send_sync::<OwnedRwLockReadAcquire<T>>();
+    is_send_sync::<OwnedRwLockWriteAcquire<T>>();
+}
+
+impl<'a, T: ?Sized + 'a> RwLockReadGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(unsafe { &*this.rwlock.data.get() }) as *const U;
+        let guard = MappedRwLockReadGuard {
+            raw: &this.rwlock.raw,
+            data,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(unsafe { &*this.rwlock.data.get() }) {
+            Some(data) => {
+                let data = data as *const U;
+                let guard = MappedRwLockReadGuard {
+                    raw: &this.rwlock.raw,
+                    data,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    is_panicking: bool,
+}
+
+impl<'a, T: ?Sized + 'a> RwLockWriteGuard<'a, T> {
+    fn new(rwlock: &'a RwLock<T>) -> Self {
+        Self {
+            rwlock,
+            is_panicking: thread::panicking(),
+        }
+    }
+
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.rwlock.data.get() }) as *mut U;
+        let guard = MappedRwLockWriteGuard {
+            raw: &this.rwlock.raw,
+            data,
+            is_panicking: this.is_panicking,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *this.rwlock.data.get() }) {
+            Some(data) => {
+                let data = data as *mut U;
+                let guard = MappedRwLockWriteGuard {
+                    raw: &this.rwlock.raw,
+                    data,
+                    is_panicking: this.is_panicking,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+
+    /// Turns exclusive access into shared access without releasing the lock.
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let rwlock = this.rwlock;
+        rwlock.raw.poison_if_panicking(this.is_panicking);
+        mem::forget(this);
+        let mut state = rwlock.raw.state();
+        state.writer = false;
+        state.readers += 1;
+        // Let the other readers in.
+        rwlock.raw.wake_ready(state);
+        RwLockReadGuard { rwlock }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct RwLockUpgradableReadGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+impl<'a, T: ?Sized + 'a> RwLockUpgradableReadGuard<'a, T> {
+    /// Waits for the other readers to leave, then turns the guard into a
+    /// write guard. No writer can get in between.
+    pub fn upgrade(this: Self) -> RwLockUpgrade<'a, T> {
+        RwLockUpgrade {
+            guard: Some(this),
+            key: None,
+        }
+    }
+
+    pub fn try_upgrade(this: Self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        let mut state = this.rwlock.raw.state();
+        if state.readers > 0 {
+            drop(state);
+            return Err(this);
+        }
+        state.upgradable = false;
+        state.writer = true;
+        drop(state);
+        Ok(Self::into_write_guard(this))
+    }
+
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let rwlock = this.rwlock;
+        mem::forget(this);
+        let mut state = rwlock.raw.state();
+        state.upgradable = false;
+        state.readers += 1;
+        rwlock.raw.wake_ready(state);
+        RwLockReadGuard { rwlock }
+    }
+
+    /// Must be called once the state has been switched over to a writer.
+    fn into_write_guard(this: Self) -> RwLockWriteGuard<'a, T> {
+        let rwlock = this.rwlock;
+        mem::forget(this);
+        RwLockWriteGuard::new(rwlock)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockUpgradableReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgradableReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.upgradable_unlock();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockUpgradableReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedRwLockReadGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawRwLock,
+    data: *const T,
+    _marker: PhantomData<&'a T>,
+}
+
+unsafe impl<'a, T: ?Sized + Sync + 'a> Send for MappedRwLockReadGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for MappedRwLockReadGuard<'a, T> {}
+
+impl<'a, T: ?Sized + 'a> MappedRwLockReadGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(unsafe { &*this.data }) as *const U;
+        let guard = MappedRwLockReadGuard {
+            raw: this.raw,
+            data,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(unsafe { &*this.data }) {
+            Some(data) => {
+                let data = data as *const U;
+                let guard = MappedRwLockReadGuard {
+                    raw: this.raw,
+                    data,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedRwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedRwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.raw.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedRwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedRwLockWriteGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawRwLock,
+    data: *mut T,
+    is_panicking: bool,
+    _marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<'a, T: ?Sized + Send + 'a> Send for MappedRwLockWriteGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync + 'a> Sync for MappedRwLockWriteGuard<'a, T> {}
+
+impl<'a, T: ?Sized + 'a> MappedRwLockWriteGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data }) as *mut U;
+        let guard = MappedRwLockWriteGuard {
+            raw: this.raw,
+            data,
+            is_panicking: this.is_panicking,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *this.data }) {
+            Some(data) => {
+                let data = data as *mut U;
+                let guard = MappedRwLockWriteGuard {
+                    raw: this.raw,
+                    data,
+                    is_panicking: this.is_panicking,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedRwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MappedRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedRwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedRwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockReadAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+    recursive: bool,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockReadAcquire<'a, T> {
+    type Output = LockResult<RwLockReadGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_read(&mut this.key, this.recursive, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockReadAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Read, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockWriteAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockWriteAcquire<'a, T> {
+    type Output = LockResult<RwLockWriteGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_write(&mut this.key, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockWriteAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Write, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockUpgradableReadAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockUpgradableReadAcquire<'a, T> {
+    type Output = LockResult<RwLockUpgradableReadGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_upgradable(&mut this.key, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgradableReadAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Upgradable, &mut self.key);
+    }
+}
+
+/// Dropping the future before it completes releases the upgradable read.
+#[derive(Debug)]
+pub struct RwLockUpgrade<'a, T: ?Sized + 'a> {
+    guard: Option<RwLockUpgradableReadGuard<'a, T>>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockUpgrade<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = this
+            .guard
+            .as_ref()
+            .expect("RwLockUpgrade polled after completion")
+            .rwlock;
+        match rwlock.poll_upgrade(&mut this.key, cx) {
+            Poll::Ready(()) => {
+                let guard = this.guard.take().unwrap();
+                Poll::Ready(RwLockUpgradableReadGuard::into_write_guard(guard))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgrade<'a, T> {
+    fn drop(&mut self) {
+        if let Some(guard) = &self.guard {
+            guard.rwlock.raw.cancel(Access::Upgrade, &mut self.key);
+        }
+    }
+}
+
+pub struct OwnedRwLockReadGuard<T: ?Sized> {
+    rwlock: Arc<RwLock<T>>,
+}
+
+impl<T: ?Sized> OwnedRwLockReadGuard<T> {
+    fn new(rwlock: Arc<RwLock<T>>, guard: RwLockReadGuard<'_, T>) -> Self {
+        mem::forget(guard);
+        Self { rwlock }
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedRwLockReadGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockReadGuard<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.read_unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedRwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct OwnedRwLockWriteGuard<T: ?Sized> {
+    rwlock: Arc<RwLock<T>>,
+    is_panicking: bool,
+}
+
+impl<T: ?Sized> OwnedRwLockWriteGuard<T> {
+    fn new(rwlock: Arc<RwLock<T>>, guard: RwLockWriteGuard<'_, T>) -> Self {
+        let is_panicking = guard.is_panicking;
+        mem::forget(guard);
+        Self {
+            rwlock,
+            is_panicking,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedRwLockWriteGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedRwLockWriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockWriteGuard<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedRwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct OwnedRwLockReadAcquire<T: ?Sized> {
+    rwlock: Arc<RwLock<T>>,
+    key: Option<usize>,
+}
+
+impl<T: ?Sized> Future for OwnedRwLockReadAcquire<T> {
+    type Output = LockResult<OwnedRwLockReadGuard<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = &this.rwlock;
+        match rwlock.poll_acquire_read(&mut this.key, false, cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                OwnedRwLockReadGuard::new(rwlock.clone(), guard)
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockReadAcquire<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Read, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct OwnedRwLockWriteAcquire<T: ?Sized> {
+    rwlock: Arc<RwLock<T>>,
+    key: Option<usize>,
+}
+
+impl<T: ?Sized> Future for OwnedRwLockWriteAcquire<T> {
+    type Output = LockResult<OwnedRwLockWriteGuard<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = &this.rwlock;
+        match rwlock.poll_acquire_write(&mut this.key, cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                OwnedRwLockWriteGuard::new(rwlock.clone(), guard)
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockWriteAcquire<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Write, &mut self.key);
+    }
+}
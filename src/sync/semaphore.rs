@@ -0,0 +1,175 @@
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::loom::sync;
+use crate::waiter::WaitQueue;
+
+pub struct Semaphore {
+    state: sync::Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: sync::Mutex::new(State {
+                permits,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state().permits
+    }
+
+    pub fn acquire(&self, n: usize) -> SemaphoreAcquire<'_> {
+        SemaphoreAcquire {
+            semaphore: self,
+            permits: n,
+            key: None,
+        }
+    }
+
+    pub fn try_acquire(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state();
+        // Queued waiters take precedence over newcomers.
+        if !state.waiters.is_empty() || state.permits < n {
+            return None;
+        }
+        state.permits -= n;
+        Some(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        let mut state = self.state();
+        state.permits += n;
+        let next = state.waiters.first_waker();
+        drop(state);
+
+        if let Some(next) = next {
+            next.wake();
+        }
+    }
+
+    fn poll_acquire(
+        &self,
+        key: &mut Option<usize>,
+        n: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<SemaphorePermit<'_>> {
+        let mut state = self.state();
+        let first = match *key {
+            None => state.waiters.is_empty(),
+            Some(k) => state.waiters.is_first(k),
+        };
+        if !first || state.permits < n {
+            state.waiters.register(key, cx.waker());
+            return Poll::Pending;
+        }
+        state.permits -= n;
+        let mut next = None;
+        if let Some(key) = key.take() {
+            state.waiters.remove(key);
+            // The next waiter may be satisfied by the remaining permits.
+            next = state.waiters.first_waker();
+        }
+        drop(state);
+
+        if let Some(next) = next {
+            next.wake();
+        }
+        Poll::Ready(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    fn abandon(&self, key: usize) {
+        let mut state = self.state();
+        let first = state.waiters.is_first(key);
+        state.waiters.remove(key);
+        let next = if first {
+            state.waiters.first_waker()
+        } else {
+            None
+        };
+        drop(state);
+
+        if let Some(next) = next {
+            next.wake();
+        }
+    }
+
+    fn state(&self) -> sync::MutexGuard<'_, State> {
+        // The state is never left inconsistent,
+        // so it is safe to ignore poisoning here.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+impl<'a> fmt::Debug for SemaphorePermit<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct SemaphoreAcquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    key: Option<usize>,
+}
+
+impl<'a> Future for SemaphoreAcquire<'a> {
+    type Output = SemaphorePermit<'a>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.semaphore.poll_acquire(&mut this.key, this.permits, cx)
+    }
+}
+
+impl<'a> Drop for SemaphoreAcquire<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.semaphore.abandon(key);
+        }
+    }
+}
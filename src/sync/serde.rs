@@ -0,0 +1,24 @@
+//! `Serialize` and `Deserialize` for `Mutex`, behind the `serde` feature.
+
+use ::serde::de::{Deserialize, Deserializer};
+use ::serde::ser::{Error, Serialize, Serializer};
+
+use super::mutex::Mutex;
+
+/// Serializes the data if the lock is free, as serializing happens outside
+/// of tasks, where waiting is not an option. Fails if the lock is held.
+/// As with `Debug`, this is not an acquisition: it shows up in neither the
+/// stats nor the event hook, and a poisoned mutex serializes its data anyway.
+impl<T: ?Sized + Serialize> Serialize for Mutex<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.peek(|data| T::serialize(data, serializer))
+            .unwrap_or_else(|| Err(S::Error::custom("cannot serialize a locked mutex")))
+    }
+}
+
+/// Deserializes the data into a new, unlocked mutex.
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Mutex<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Mutex::new)
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{PoisonError, TryLockError};
+use std::task::{Context, Poll};
+
+use futures_core::future::FusedFuture;
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+
+/// A `Mutex` that ignores poisoning.
+///
+/// A panic while the lock is held does not affect later acquisitions,
+/// so `lock` resolves directly to a guard.
+pub struct UnpoisonedMutex<T: ?Sized> {
+    inner: Mutex<T>,
+}
+
+impl<T> UnpoisonedMutex<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: Mutex::new(inner),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: ?Sized> UnpoisonedMutex<T> {
+    #[track_caller]
+    pub fn lock(&self) -> UnpoisonedMutexAcquire<'_, T> {
+        UnpoisonedMutexAcquire {
+            inner: self.inner.lock(),
+        }
+    }
+    #[track_caller]
+    pub fn poll_lock(&self, cx: &mut Context<'_>) -> Poll<MutexGuard<'_, T>> {
+        match self.inner.poll_lock(cx) {
+            Poll::Ready(result) => Poll::Ready(result.unwrap_or_else(PoisonError::into_inner)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> From<T> for UnpoisonedMutex<T> {
+    fn from(x: T) -> Self {
+        UnpoisonedMutex::new(x)
+    }
+}
+
+impl<T: Default> Default for UnpoisonedMutex<T> {
+    fn default() -> Self {
+        UnpoisonedMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for UnpoisonedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(guard) = self.try_lock() {
+            f.debug_struct("UnpoisonedMutex")
+                .field("data", &(&guard as &T))
+                .finish()
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            f.debug_struct("UnpoisonedMutex")
+                .field("data", &LockedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnpoisonedMutexAcquire<'a, T: ?Sized + 'a> {
+    inner: MutexAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for UnpoisonedMutexAcquire<'a, T> {
+    type Output = MutexGuard<'a, T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.inner).poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result.unwrap_or_else(PoisonError::into_inner)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> FusedFuture for UnpoisonedMutexAcquire<'a, T> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_terminated()
+    }
+}
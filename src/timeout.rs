@@ -0,0 +1,98 @@
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::poison::{LockResult, TryLockError, TryLockResult};
+
+/// Races an acquisition against a delay.
+///
+/// The delay can be any future that completes when the time is up, such as
+/// a timer from the executor in use. If it fires first, the acquisition is
+/// dropped right away, which also removes the task from the wait queue,
+/// even if the `Timeout` itself is kept around.
+#[derive(Debug)]
+pub struct Timeout<F, D> {
+    /// `None` once the delay has fired.
+    inner: Option<F>,
+    delay: D,
+}
+
+impl<F, D> Timeout<F, D> {
+    pub(crate) fn new(inner: F, delay: D) -> Self {
+        Self {
+            inner: Some(inner),
+            delay,
+        }
+    }
+}
+
+impl<F, D> Future for Timeout<F, D>
+where
+    F: Future,
+    D: Future<Output = ()>,
+{
+    type Output = Result<F::Output, Elapsed>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Both futures are pinned along with `self`.
+        let inner = unsafe { self.as_mut().map_unchecked_mut(|this| &mut this.inner) };
+        let acquire = inner
+            .as_pin_mut()
+            .expect("Timeout polled after the delay fired");
+        if let Poll::Ready(output) = acquire.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        let delay = unsafe { self.as_mut().map_unchecked_mut(|this| &mut this.delay) };
+        if delay.poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        // Leave the queue right away rather than when `self` is dropped,
+        // or a release could hand the lock over to the dead acquisition.
+        unsafe { self.map_unchecked_mut(|this| &mut this.inner) }.set(None);
+        Poll::Ready(Err(Elapsed(())))
+    }
+}
+
+/// Like `Timeout`, but reports the elapsed delay as `WouldBlock`,
+/// in the same shape as `try_lock`.
+#[derive(Debug)]
+pub struct TryLockTimeout<F, D> {
+    inner: Timeout<F, D>,
+}
+
+impl<F, D> TryLockTimeout<F, D> {
+    pub(crate) fn new(inner: F, delay: D) -> Self {
+        Self {
+            inner: Timeout::new(inner, delay),
+        }
+    }
+}
+
+impl<F, D, G> Future for TryLockTimeout<F, D>
+where
+    F: Future<Output = LockResult<G>>,
+    D: Future<Output = ()>,
+{
+    type Output = TryLockResult<G>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = unsafe { self.map_unchecked_mut(|this| &mut this.inner) };
+        match inner.poll(cx) {
+            Poll::Ready(Ok(result)) => Poll::Ready(result.map_err(TryLockError::from)),
+            Poll::Ready(Err(Elapsed(()))) => Poll::Ready(Err(TryLockError::WouldBlock)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("lock acquisition timed out")
+    }
+}
+
+impl Error for Elapsed {}
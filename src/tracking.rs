@@ -0,0 +1,147 @@
+//! Where the guard of a mutex was acquired, and where its waiters are
+//! waiting, behind the `debug-tracking` feature.
+
+#[cfg(feature = "debug-tracking")]
+mod imp {
+    use core::fmt;
+    use core::panic::Location;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    /// The call site of a lock method, kept by its future until the lock
+    /// is acquired.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Caller(pub(super) &'static Location<'static>);
+
+    impl Caller {
+        #[track_caller]
+        #[inline]
+        pub(crate) fn here() -> Self {
+            Caller(Location::caller())
+        }
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct AcquiredAt(AtomicPtr<Location<'static>>);
+
+    impl AcquiredAt {
+        pub(crate) const fn new() -> Self {
+            AcquiredAt(AtomicPtr::new(ptr::null_mut()))
+        }
+
+        pub(crate) fn set(&self, caller: Caller) {
+            let location = caller.0 as *const Location<'static> as *mut _;
+            self.0.store(location, Ordering::Relaxed);
+        }
+
+        pub(crate) fn clear(&self) {
+            self.0.store(ptr::null_mut(), Ordering::Relaxed);
+        }
+
+        /// Adds the location to the `Debug` output of a mutex or guard,
+        /// unless the lock has been released since.
+        pub(crate) fn fmt_field(&self, f: &mut fmt::DebugStruct<'_, '_>) {
+            let location = unsafe { self.0.load(Ordering::Relaxed).as_ref() };
+            if let Some(location) = location {
+                f.field("acquired_at", &format_args!("{}", location));
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "debug-tracking"))]
+mod imp {
+    use core::fmt;
+
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct Caller;
+
+    impl Caller {
+        #[inline]
+        pub(crate) fn here() -> Self {
+            Caller
+        }
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct AcquiredAt;
+
+    impl AcquiredAt {
+        #[inline]
+        pub(crate) const fn new() -> Self {
+            AcquiredAt
+        }
+
+        #[inline]
+        pub(crate) fn set(&self, _caller: Caller) {}
+
+        #[inline]
+        pub(crate) fn clear(&self) {}
+
+        #[inline]
+        pub(crate) fn fmt_field(&self, _f: &mut fmt::DebugStruct<'_, '_>) {}
+    }
+}
+
+/// The waiters need a lock of their own, so they are only tracked with
+/// `std`.
+#[cfg(all(feature = "debug-tracking", feature = "std"))]
+mod waiting {
+    use std::panic::Location;
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::Caller;
+
+    /// The call sites of the lock methods whose futures are queued, by key.
+    #[derive(Debug)]
+    pub(crate) struct WaitingAt(Mutex<Vec<(usize, &'static Location<'static>)>>);
+
+    impl WaitingAt {
+        pub(crate) const fn new() -> Self {
+            WaitingAt(Mutex::new(Vec::new()))
+        }
+
+        pub(crate) fn started(&self, key: usize, caller: Caller) {
+            let mut waiting = self.waiting();
+            if !waiting.iter().any(|&(k, _)| k == key) {
+                waiting.push((key, caller.0));
+            }
+        }
+
+        pub(crate) fn stopped(&self, key: usize) {
+            self.waiting().retain(|&(k, _)| k != key);
+        }
+
+        pub(crate) fn locations(&self) -> Vec<&'static Location<'static>> {
+            self.waiting().iter().map(|&(_, location)| location).collect()
+        }
+
+        fn waiting(&self) -> MutexGuard<'_, Vec<(usize, &'static Location<'static>)>> {
+            self.0.lock().unwrap_or_else(|err| err.into_inner())
+        }
+    }
+}
+
+#[cfg(not(all(feature = "debug-tracking", feature = "std")))]
+mod waiting {
+    use super::Caller;
+
+    #[derive(Debug)]
+    pub(crate) struct WaitingAt;
+
+    impl WaitingAt {
+        #[inline]
+        pub(crate) const fn new() -> Self {
+            WaitingAt
+        }
+
+        #[inline]
+        pub(crate) fn started(&self, _key: usize, _caller: Caller) {}
+
+        #[inline]
+        pub(crate) fn stopped(&self, _key: usize) {}
+    }
+}
+
+pub(crate) use self::imp::{AcquiredAt, Caller};
+pub(crate) use self::waiting::WaitingAt;
@@ -0,0 +1,132 @@
+use core::cell::Cell;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::waiter::WaitQueue;
+
+pub struct Barrier {
+    n: usize,
+    arrived: Cell<usize>,
+    generation: Cell<usize>,
+    waiters: Cell<WaitQueue>,
+}
+
+impl Barrier {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            arrived: Cell::new(0),
+            generation: Cell::new(0),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    /// Waits until `n` tasks have called `wait`.
+    ///
+    /// A task arrives at the barrier when the future is first polled.
+    /// Dropping the future before the barrier is released withdraws
+    /// the arrival.
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            generation: None,
+            key: None,
+        }
+    }
+
+    fn poll_wait(
+        &self,
+        generation: &mut Option<usize>,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<BarrierWaitResult> {
+        let gen = match *generation {
+            Some(gen) => gen,
+            None => {
+                let arrived = self.arrived.get() + 1;
+                if arrived >= self.n {
+                    self.arrived.set(0);
+                    self.generation.set(self.generation.get().wrapping_add(1));
+                    self.wake_all();
+                    return Poll::Ready(BarrierWaitResult(true));
+                }
+                self.arrived.set(arrived);
+                *generation = Some(self.generation.get());
+                self.generation.get()
+            }
+        };
+        if gen == self.generation.get() {
+            self.with_waiters(|waiters| waiters.register(key, cx.waker()));
+            return Poll::Pending;
+        }
+        self.abandon(key);
+        *generation = None;
+
+        Poll::Ready(BarrierWaitResult(false))
+    }
+
+    fn abandon(&self, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            self.with_waiters(|waiters| waiters.remove(key));
+        }
+    }
+
+    fn wake_all(&self) {
+        for waker in self.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Barrier")
+            .field("n", &self.n)
+            .field("arrived", &self.arrived.get())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Returns true for exactly one task per release of the barrier:
+    /// the one whose arrival released it.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    generation: Option<usize>,
+    key: Option<usize>,
+}
+
+impl<'a> Future for BarrierWait<'a> {
+    type Output = BarrierWaitResult;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.barrier.poll_wait(&mut this.generation, &mut this.key, cx)
+    }
+}
+
+impl<'a> Drop for BarrierWait<'a> {
+    fn drop(&mut self) {
+        self.barrier.abandon(&mut self.key);
+        if self.generation.take() == Some(self.barrier.generation.get()) {
+            self.barrier.arrived.set(self.barrier.arrived.get() - 1);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use core::cell::Cell;
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+use crate::poison::LockResult;
+use crate::waiter::WaitQueue;
+
+pub struct Condvar {
+    waiters: Cell<WaitQueue>,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    pub fn wait<'a, T: ?Sized>(&'a self, guard: MutexGuard<'a, T>) -> CondvarWait<'a, T> {
+        CondvarWait {
+            condvar: self,
+            state: WaitState::Unlocking(guard),
+        }
+    }
+
+    pub fn notify_one(&self) {
+        if let Some(waker) = self.with_waiters(|waiters| waiters.notify_one()) {
+            waker.wake();
+        }
+    }
+
+    pub fn notify_all(&self) {
+        for waker in self.with_waiters(|waiters| waiters.notify_all()) {
+            waker.wake();
+        }
+    }
+
+    fn abandon(&self, key: usize) {
+        if self.with_waiters(|waiters| waiters.remove(key)) {
+            // We have consumed a notification; pass it on.
+            self.notify_one();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Condvar::new()
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Condvar { .. }")
+    }
+}
+
+pub struct CondvarWait<'a, T: ?Sized + 'a> {
+    condvar: &'a Condvar,
+    state: WaitState<'a, T>,
+}
+
+enum WaitState<'a, T: ?Sized + 'a> {
+    Unlocking(MutexGuard<'a, T>),
+    Waiting(&'a Mutex<T>, Option<usize>),
+    Relocking(MutexAcquire<'a, T>),
+    Done,
+}
+
+impl<'a, T: ?Sized + 'a> Future for CondvarWait<'a, T> {
+    type Output = LockResult<MutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The acquisition in `Relocking` is pinned along with `self`,
+        // so it is polled in place rather than moved out of `state`.
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if let WaitState::Relocking(acquire) = &mut this.state {
+                let acquire = unsafe { Pin::new_unchecked(acquire) };
+                if let Poll::Ready(result) = acquire.poll(cx) {
+                    this.state = WaitState::Done;
+                    return Poll::Ready(result);
+                }
+                return Poll::Pending;
+            }
+            match mem::replace(&mut this.state, WaitState::Done) {
+                WaitState::Unlocking(guard) => {
+                    // Register before unlocking, so that a notification sent
+                    // by the next owner of the mutex cannot be missed.
+                    let mutex = MutexGuard::mutex(&guard);
+                    let mut key = None;
+                    this.condvar
+                        .with_waiters(|waiters| waiters.register(&mut key, cx.waker()));
+                    drop(guard);
+                    this.state = WaitState::Waiting(mutex, key);
+                    return Poll::Pending;
+                }
+                WaitState::Waiting(mutex, mut key) => {
+                    let notified = this.condvar.with_waiters(|waiters| {
+                        if waiters.take_notified(key.unwrap()) {
+                            return true;
+                        }
+                        waiters.register(&mut key, cx.waker());
+                        false
+                    });
+                    if notified {
+                        this.state = WaitState::Relocking(mutex.lock());
+                    } else {
+                        this.state = WaitState::Waiting(mutex, key);
+                        return Poll::Pending;
+                    }
+                }
+                WaitState::Relocking(_) => unreachable!(),
+                WaitState::Done => panic!("CondvarWait polled after completion"),
+            }
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for CondvarWait<'a, T> {
+    fn drop(&mut self) {
+        if let WaitState::Waiting(_, Some(key)) = self.state {
+            self.condvar.abandon(key);
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> fmt::Debug for CondvarWait<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CondvarWait")
+            .field("condvar", &self.condvar)
+            .finish()
+    }
+}
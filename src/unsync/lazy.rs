@@ -0,0 +1,117 @@
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_core::future::FusedFuture;
+
+use super::mutex::{MappedMutexGuard, Mutex, MutexAcquire, MutexGuard};
+use crate::poison::{self, LockResult, TryLockResult};
+
+/// A `Mutex` whose data is created by the first acquisition.
+///
+/// `new` is `const`, so a `LazyMutex` can live in a `thread_local!`
+/// without wrapping a `Mutex` in a `OnceCell`. The initializer runs while
+/// the lock is held, so tasks waiting for the lock meanwhile see the
+/// initialized data. If it panics, the mutex is poisoned, and later
+/// acquisitions panic as well.
+pub struct LazyMutex<T, F = fn() -> T> {
+    inner: Mutex<State<T, F>>,
+}
+
+enum State<T, F> {
+    Uninit(F),
+    Init(T),
+    /// The initializer panicked.
+    Failed,
+}
+
+impl<T, F: FnOnce() -> T> State<T, F> {
+    fn force(&mut self) -> &mut T {
+        if let State::Uninit(_) = self {
+            let init = match mem::replace(self, State::Failed) {
+                State::Uninit(init) => init,
+                _ => unreachable!(),
+            };
+            *self = State::Init(init());
+        }
+        match self {
+            State::Init(value) => value,
+            _ => panic!("LazyMutex initializer panicked"),
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> LazyMutex<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            inner: Mutex::new(State::Uninit(init)),
+        }
+    }
+
+    /// Like `Mutex::lock`, but runs the initializer first if no
+    /// acquisition has yet.
+    #[track_caller]
+    pub fn lock(&self) -> LazyMutexAcquire<'_, T, F> {
+        LazyMutexAcquire {
+            acquire: self.inner.lock(),
+        }
+    }
+
+    /// Like `Mutex::try_lock`, but runs the initializer first if no
+    /// acquisition has yet.
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<MappedMutexGuard<'_, T>> {
+        poison::map_try_result(self.inner.try_lock(), |guard| {
+            MutexGuard::map(guard, State::force)
+        })
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Returns the data, if the initializer has run.
+    pub fn into_inner(self) -> LockResult<Option<T>> {
+        poison::map_result(self.inner.into_inner(), |state| match state {
+            State::Init(value) => Some(value),
+            _ => None,
+        })
+    }
+}
+
+impl<T, F> fmt::Debug for LazyMutex<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("LazyMutex { .. }")
+    }
+}
+
+pub struct LazyMutexAcquire<'a, T, F> {
+    acquire: MutexAcquire<'a, State<T, F>>,
+}
+
+impl<'a, T, F: FnOnce() -> T> Future for LazyMutexAcquire<'a, T, F> {
+    type Output = LockResult<MappedMutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let acquire = unsafe { self.map_unchecked_mut(|this| &mut this.acquire) };
+        match acquire.poll(cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                MutexGuard::map(guard, State::force)
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T, F: FnOnce() -> T> FusedFuture for LazyMutexAcquire<'a, T, F> {
+    fn is_terminated(&self) -> bool {
+        self.acquire.is_terminated()
+    }
+}
+
+impl<'a, T, F> fmt::Debug for LazyMutexAcquire<'a, T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("LazyMutexAcquire { .. }")
+    }
+}
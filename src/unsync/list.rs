@@ -0,0 +1,204 @@
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::ptr;
+use core::task::Waker;
+
+/// An intrusive FIFO queue of tasks waiting for a lock.
+///
+/// The nodes live inside the acquire futures themselves, which are pinned
+/// while they wait, so queueing up does not allocate. Notifying a waiter
+/// unlinks it from the list and hands the lock over to it.
+pub(super) struct WaiterList {
+    head: Cell<*const Waiter>,
+    tail: Cell<*const Waiter>,
+    len: Cell<usize>,
+}
+
+// The list only points into waiters that borrow the lock it belongs to,
+// so it cannot be moved to another thread while it is non-empty.
+unsafe impl Send for WaiterList {}
+
+pub(super) struct Waiter {
+    prev: Cell<*const Waiter>,
+    next: Cell<*const Waiter>,
+    waker: Cell<Option<Waker>>,
+    state: Cell<State>,
+    _pinned: PhantomPinned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Queued,
+    Notified,
+}
+
+impl WaiterList {
+    pub(super) const fn new() -> Self {
+        Self {
+            head: Cell::new(ptr::null()),
+            tail: Cell::new(ptr::null()),
+            len: Cell::new(0),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    pub(super) fn is_first(&self, waiter: &Waiter) -> bool {
+        ptr::eq(self.head.get(), waiter)
+    }
+
+    /// Counts the waiters ahead of `waiter`, if it is queued.
+    /// A notified waiter counts as the first.
+    pub(super) fn position(&self, waiter: &Waiter) -> Option<usize> {
+        match waiter.state.get() {
+            State::Idle => None,
+            State::Notified => Some(0),
+            State::Queued => {
+                let mut position = 0;
+                let mut node = self.head.get();
+                while !ptr::eq(node, waiter) {
+                    node = unsafe { (*node).next.get() };
+                    position += 1;
+                }
+                Some(position)
+            }
+        }
+    }
+
+    /// Returns the wakers of all waiters, leaving them queued.
+    pub(super) fn wakers(&self) -> Vec<Waker> {
+        let mut wakers = Vec::with_capacity(self.len.get());
+        let mut node = self.head.get();
+        while let Some(waiter) = unsafe { node.as_ref() } {
+            let waker = waiter.waker.take();
+            waiter.waker.set(waker.clone());
+            wakers.extend(waker);
+            node = waiter.next.get();
+        }
+        wakers
+    }
+
+    /// Returns the waker of the first waiter, leaving it queued.
+    pub(super) fn first_waker(&self) -> Option<Waker> {
+        let waiter = unsafe { self.head.get().as_ref() }?;
+        let waker = waiter.waker.take();
+        waiter.waker.set(waker.clone());
+        waker
+    }
+
+    /// Enqueues the waiter, or updates its waker if it is already queued.
+    ///
+    /// # Safety
+    ///
+    /// The waiter must be removed with `remove` before it is dropped.
+    pub(super) unsafe fn register(&self, waiter: Pin<&Waiter>, waker: &Waker) {
+        let waiter = waiter.get_ref();
+        match waiter.state.get() {
+            State::Idle => {
+                waiter.waker.set(Some(waker.clone()));
+                waiter.prev.set(self.tail.get());
+                match self.tail.get().as_ref() {
+                    Some(tail) => tail.next.set(waiter),
+                    None => self.head.set(waiter),
+                }
+                self.tail.set(waiter);
+                self.len.set(self.len.get() + 1);
+                waiter.state.set(State::Queued);
+            }
+            State::Queued => {
+                let old = waiter.waker.take();
+                let waker = match old {
+                    Some(old) if old.will_wake(waker) => old,
+                    _ => waker.clone(),
+                };
+                waiter.waker.set(Some(waker));
+            }
+            State::Notified => {}
+        }
+    }
+
+    /// Resets the waiter if it has been notified.
+    pub(super) fn take_notified(&self, waiter: &Waiter) -> bool {
+        if waiter.state.get() == State::Notified {
+            waiter.state.set(State::Idle);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the waiter, returning whether it had been notified.
+    pub(super) fn remove(&self, waiter: &Waiter) -> bool {
+        match waiter.state.get() {
+            State::Idle => false,
+            State::Queued => {
+                self.unlink(waiter);
+                waiter.waker.take();
+                waiter.state.set(State::Idle);
+                false
+            }
+            State::Notified => {
+                waiter.state.set(State::Idle);
+                true
+            }
+        }
+    }
+
+    /// Dequeues and notifies the first waiter, returning its waker.
+    pub(super) fn notify_one(&self) -> Option<Waker> {
+        let waiter = unsafe { self.head.get().as_ref() }?;
+        self.unlink(waiter);
+        waiter.state.set(State::Notified);
+        waiter.waker.take()
+    }
+
+    fn unlink(&self, waiter: &Waiter) {
+        let prev = waiter.prev.replace(ptr::null());
+        let next = waiter.next.replace(ptr::null());
+        match unsafe { prev.as_ref() } {
+            Some(prev) => prev.next.set(next),
+            None => self.head.set(next),
+        }
+        match unsafe { next.as_ref() } {
+            Some(next) => next.prev.set(prev),
+            None => self.tail.set(prev),
+        }
+        self.len.set(self.len.get() - 1);
+    }
+}
+
+impl fmt::Debug for WaiterList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("WaiterList { .. }")
+    }
+}
+
+impl Waiter {
+    pub(super) fn new() -> Self {
+        Self {
+            prev: Cell::new(ptr::null()),
+            next: Cell::new(ptr::null()),
+            waker: Cell::new(None),
+            state: Cell::new(State::Idle),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    pub(super) fn is_queued(&self) -> bool {
+        self.state.get() == State::Queued
+    }
+}
+
+impl fmt::Debug for Waiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Waiter")
+            .field("state", &self.state.get())
+            .finish()
+    }
+}
@@ -0,0 +1,351 @@
+//! A bounded multi-producer, single-consumer channel.
+
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use crate::waiter::WaitQueue;
+
+/// Creates a channel holding at most `capacity` values.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc::channel: capacity must be positive");
+    let shared = Rc::new(Shared {
+        buffer: RefCell::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        senders: Cell::new(1),
+        closed: Cell::new(false),
+        send_waiters: Cell::new(WaitQueue::new()),
+        recv_waker: Cell::new(None),
+    });
+    let sender = Sender {
+        shared: shared.clone(),
+    };
+    (sender, Receiver { shared })
+}
+
+struct Shared<T> {
+    buffer: RefCell<VecDeque<T>>,
+    capacity: usize,
+    senders: Cell<usize>,
+    closed: Cell<bool>,
+    send_waiters: Cell<WaitQueue>,
+    recv_waker: Cell<Option<Waker>>,
+}
+
+impl<T> Shared<T> {
+    fn has_room(&self) -> bool {
+        self.buffer.borrow().len() < self.capacity
+    }
+
+    fn push(&self, value: T) {
+        self.buffer.borrow_mut().push_back(value);
+        self.wake_receiver();
+    }
+
+    /// No value can arrive any more: the channel is closed or every
+    /// sender is gone.
+    fn is_done(&self) -> bool {
+        self.closed.get() || self.senders.get() == 0
+    }
+
+    fn wake_receiver(&self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn wake_first_sender(&self) {
+        if let Some(waker) = self.with_waiters(|waiters| waiters.first_waker()) {
+            waker.wake();
+        }
+    }
+
+    fn abandon(&self, key: usize) {
+        let first = self.with_waiters(|waiters| {
+            let first = waiters.is_first(key);
+            waiters.remove(key);
+            first
+        });
+        if first && self.has_room() {
+            self.wake_first_sender();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.send_waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.send_waiters.replace(waiters);
+        ret
+    }
+}
+
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a value, waiting for room in the channel.
+    ///
+    /// Blocked senders are served in FIFO order.
+    pub fn send(&self, value: T) -> SenderSend<'_, T> {
+        SenderSend {
+            sender: self,
+            value: Some(value),
+            key: None,
+        }
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let shared = &self.shared;
+        if shared.closed.get() {
+            return Err(TrySendError::Closed(value));
+        }
+        // Queued senders take precedence over newcomers.
+        if !shared.with_waiters(|waiters| waiters.is_empty()) || !shared.has_room() {
+            return Err(TrySendError::Full(value));
+        }
+        shared.push(value);
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.get()
+    }
+
+    fn poll_send(
+        &self,
+        value: &mut Option<T>,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        let shared = &self.shared;
+        if shared.closed.get() {
+            if let Some(key) = key.take() {
+                shared.abandon(key);
+            }
+            let value = value.take().expect("SenderSend polled after completion");
+            return Poll::Ready(Err(SendError(value)));
+        }
+        let first = shared.with_waiters(|waiters| match *key {
+            None => waiters.is_empty(),
+            Some(k) => waiters.is_first(k),
+        });
+        if !first || !shared.has_room() {
+            shared.with_waiters(|waiters| waiters.register(key, cx.waker()));
+            return Poll::Pending;
+        }
+        let value = value.take().expect("SenderSend polled after completion");
+        shared.push(value);
+        if let Some(key) = key.take() {
+            shared.with_waiters(|waiters| waiters.remove(key));
+            // The next sender may fit in the remaining room.
+            if shared.has_room() {
+                shared.wake_first_sender();
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.set(self.shared.senders.get() + 1);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let senders = self.shared.senders.get() - 1;
+        self.shared.senders.set(senders);
+        if senders == 0 {
+            self.shared.wake_receiver();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Sender { .. }")
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value.
+    ///
+    /// Resolves to `None` once the channel is empty and either all senders
+    /// are gone or the channel has been closed.
+    pub fn recv(&mut self) -> ReceiverRecv<'_, T> {
+        ReceiverRecv { receiver: self }
+    }
+
+    pub fn try_recv(&mut self) -> Result<Option<T>, TryRecvError> {
+        match self.pop() {
+            Some(value) => Ok(Some(value)),
+            None if self.shared.is_done() => Ok(None),
+            None => Err(TryRecvError(())),
+        }
+    }
+
+    /// Closes the channel, failing all pending and future sends.
+    /// Values already in the channel can still be received.
+    pub fn close(&mut self) {
+        self.shared.closed.set(true);
+        for waker in self.shared.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let value = self.shared.buffer.borrow_mut().pop_front();
+        if value.is_some() {
+            self.shared.wake_first_sender();
+        }
+        value
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(value) = self.pop() {
+            return Poll::Ready(Some(value));
+        }
+        if self.shared.is_done() {
+            return Poll::Ready(None);
+        }
+        self.shared.recv_waker.set(Some(cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Receiver { .. }")
+    }
+}
+
+pub struct SenderSend<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T> Unpin for SenderSend<'a, T> {}
+
+impl<'a, T> Future for SenderSend<'a, T> {
+    type Output = Result<(), SendError<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.sender.poll_send(&mut this.value, &mut this.key, cx)
+    }
+}
+
+impl<'a, T> Drop for SenderSend<'a, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.sender.shared.abandon(key);
+        }
+    }
+}
+
+impl<'a, T> fmt::Debug for SenderSend<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("SenderSend { .. }")
+    }
+}
+
+#[derive(Debug)]
+pub struct ReceiverRecv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<'a, T> Future for ReceiverRecv<'a, T> {
+    type Output = Option<T>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// The receiver is gone; the unsent value is returned.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("SendError { .. }")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => f.pad("Full(..)"),
+            TrySendError::Closed(..) => f.pad("Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(..) => f.write_str("sending on a full channel"),
+            TrySendError::Closed(..) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+/// The channel is empty, but senders remain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TryRecvError(());
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("receiving on an empty channel")
+    }
+}
+
+impl Error for TryRecvError {}
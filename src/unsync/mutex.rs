@@ -0,0 +1,1826 @@
+use alloc::rc::Rc;
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::future::{self, Future};
+use core::marker::PhantomData;
+use core::mem::{self, ManuallyDrop};
+use core::ops::{Deref, DerefMut};
+#[cfg(all(feature = "debug-tracking", feature = "std"))]
+use core::panic::Location;
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use futures_core::future::FusedFuture;
+use futures_core::Stream;
+#[cfg(feature = "compat")]
+use futures_util::compat::Compat;
+
+#[cfg(feature = "io")]
+use super::io::MutexIo;
+use super::list::{Waiter, WaiterList};
+use super::multi;
+use super::poison_scope::PanicWatch;
+#[cfg(feature = "sink")]
+use super::sink::MutexSink;
+#[cfg(feature = "std")]
+use crate::cancel::{CancellationToken, Cancelled};
+use crate::deadlock;
+use crate::error::sealed::CheckedAcquire;
+use crate::error::{LockChecked, LockError};
+use crate::fairness::Fairness;
+use crate::instrument::Instrument;
+#[cfg(feature = "instrument")]
+use crate::instrument::LockEvent;
+use crate::poison::sealed::ClearPoison;
+use crate::poison::{self, LockResult, PoisonError, TryLockError, TryLockResult};
+use crate::small_deque::SmallDeque;
+#[cfg(feature = "stats")]
+use crate::stats::MutexStats;
+use crate::stats::Stats;
+use crate::timeout::{Timeout, TryLockTimeout};
+use crate::tracking::{AcquiredAt, Caller, WaitingAt};
+use crate::wake_batch;
+use crate::wake_policy::WakePolicy;
+use crate::waiter::INLINE_WAITERS;
+
+/// A mutual exclusion primitive whose acquisition is a future.
+///
+/// Pinning is not structural: every guard hands out `&mut T`, which would
+/// allow moving the data out from under a `Pin`. To protect a `!Unpin` value
+/// such as a future or a stream, use a `PinMutex`, or store it as
+/// `Pin<Box<T>>` and poll it through `guard.as_mut()`.
+///
+/// `T` may be unsized. The data is the last field, so a pointer to a mutex
+/// coerces along with its contents: `Rc<Mutex<S>>` becomes
+/// `Rc<Mutex<dyn Trait>>` for any `S: Trait`, and `Box<Mutex<[u8; 4]>>`
+/// becomes `Box<Mutex<[u8]>>`.
+pub struct Mutex<T: ?Sized> {
+    raw: RawMutex,
+    data: UnsafeCell<T>,
+}
+
+pub(super) struct RawMutex {
+    locked: Cell<bool>,
+    poisoned: Cell<bool>,
+    waiters: WaiterList,
+    wakers: Cell<SmallDeque<Waker, INLINE_WAITERS>>,
+    policy: WakePolicy,
+    yield_after: u32,
+    max_waiters: usize,
+    fairness: Fairness,
+    stats: Stats,
+    instrument: Instrument,
+    acquired_at: AcquiredAt,
+    waiting_at: WaitingAt,
+    /// The task that acquired the lock last, and how many times in a row
+    /// it did so without waiting. Only tracked if `yield_after` is set.
+    last_owner: Cell<Option<Waker>>,
+    streak: Cell<u32>,
+}
+
+impl<T: ?Sized> UnwindSafe for Mutex<T> {}
+impl<T: ?Sized> RefUnwindSafe for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(inner: T) -> Self {
+        MutexBuilder::new().build(inner)
+    }
+
+    /// Creates a mutex with the given wake policy; see `WakePolicy`.
+    pub fn with_policy(inner: T, policy: WakePolicy) -> Self {
+        MutexBuilder::new().wake_policy(policy).build(inner)
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { raw, data } = self;
+        let poisoned = raw.poisoned.into_inner();
+        let inner = data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    /// Replaces the data, returning the old value.
+    pub async fn set(&self, value: T) -> LockResult<T> {
+        self.with_lock(|data| mem::replace(data, value)).await
+    }
+
+    /// The same as `set`, named after `Cell::replace`.
+    pub async fn replace(&self, value: T) -> LockResult<T> {
+        self.set(value).await
+    }
+
+    /// Swaps the data of two mutexes.
+    ///
+    /// Both are locked through `lock_both`, so that two tasks swapping the
+    /// same pair in opposite directions cannot deadlock. Swapping a mutex
+    /// with itself does nothing.
+    pub async fn swap(&self, other: &Mutex<T>) -> LockResult<()> {
+        if ptr::eq(self, other) {
+            return Ok(());
+        }
+        poison::map_result(multi::lock_both(self, other).await, |(mut a, mut b)| {
+            mem::swap(&mut *a, &mut *b);
+        })
+    }
+}
+
+impl<T: Clone> Mutex<T> {
+    /// Returns a copy of the data.
+    pub async fn get_cloned(&self) -> LockResult<T> {
+        self.with_lock(|data| data.clone()).await
+    }
+
+    /// Returns a new, unlocked mutex holding a copy of the data.
+    ///
+    /// The copy is not poisoned, even if the original is.
+    pub async fn duplicate(&self) -> LockResult<Mutex<T>> {
+        poison::map_result(self.get_cloned().await, Mutex::new)
+    }
+}
+
+impl Mutex<()> {
+    /// Returns a builder to configure a mutex, the same as
+    /// `MutexBuilder::new()`: `Mutex::builder().fair(false).build(data)`.
+    pub const fn builder() -> MutexBuilder {
+        MutexBuilder::new()
+    }
+}
+
+/// Configures a `Mutex` before creating it.
+#[derive(Debug, Clone, Copy)]
+pub struct MutexBuilder {
+    policy: WakePolicy,
+    yield_after: u32,
+    max_waiters: usize,
+    fair_after: Option<Duration>,
+}
+
+impl MutexBuilder {
+    pub const fn new() -> Self {
+        Self {
+            policy: WakePolicy::Handoff,
+            yield_after: 0,
+            max_waiters: usize::MAX,
+            fair_after: None,
+        }
+    }
+
+    /// Sets whether releasing the lock hands it over to the first waiter.
+    ///
+    /// An unfair mutex instead unlocks and wakes the first waiter, which
+    /// then competes with newcomers. This gives more throughput, as the
+    /// lock never sits idle waiting for the woken task to be polled, but
+    /// a waiter may be overtaken repeatedly. Guards can still hand over
+    /// the lock with `MutexGuard::unlock_fair`, and a fair mutex can be
+    /// released unfairly with `MutexGuard::unlock_unfair`. The default is
+    /// fair.
+    pub const fn fair(self, fair: bool) -> Self {
+        let policy = if fair {
+            WakePolicy::Handoff
+        } else {
+            WakePolicy::WakeOne
+        };
+        self.wake_policy(policy)
+    }
+
+    /// Sets what releasing the lock does with the waiters, generalizing
+    /// `fair`. The default is `WakePolicy::Handoff`.
+    pub const fn wake_policy(self, policy: WakePolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Makes an unfair mutex hand the lock over to the first waiter once
+    /// the waiters have gone `timeout` without any of them getting it.
+    ///
+    /// Newcomers can otherwise overtake a waiter indefinitely. With this
+    /// set, the mutex keeps the throughput of unfair releases while
+    /// bounding how long the queue can stall. It has no effect with
+    /// `WakePolicy::Handoff`. The default never hands over.
+    #[cfg(feature = "std")]
+    pub const fn fair_after(self, timeout: Duration) -> Self {
+        Self {
+            fair_after: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Makes a task that acquires the lock `n` times in a row without
+    /// waiting yield to the executor before acquiring it again.
+    ///
+    /// An uncontended `lock()` completes immediately, so a task locking
+    /// in a loop never returns to a single-threaded executor, and the
+    /// tasks that would contend for the lock never get to run. The
+    /// default, 0, never yields.
+    pub const fn yield_after(self, n: u32) -> Self {
+        Self {
+            yield_after: n,
+            ..self
+        }
+    }
+
+    /// Makes `Mutex::poll_lock_checked`, `lock_checked` and its timed and
+    /// cancellable variants fail with `LockError::QueueFull` rather than
+    /// wait once `n` tasks are waiting for the lock.
+    ///
+    /// This is for shedding load rather than building up a long queue.
+    /// Other acquisitions have no way to report the error, so they wait
+    /// regardless, but count toward the cap. A task that is already waiting
+    /// never gives up its place. The default is unbounded.
+    pub const fn max_waiters(self, n: usize) -> Self {
+        Self {
+            max_waiters: n,
+            ..self
+        }
+    }
+
+    pub const fn build<T>(self, inner: T) -> Mutex<T> {
+        Mutex {
+            raw: RawMutex::new(
+                self.policy,
+                self.yield_after,
+                self.max_waiters,
+                self.fair_after,
+            ),
+            data: UnsafeCell::new(inner),
+        }
+    }
+}
+
+impl Default for MutexBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Waiters are served in FIFO order. Releasing the lock hands it over
+    /// to the first waiter directly, so a woken waiter already owns the
+    /// lock and cannot be overtaken by `try_lock` or by newcomers.
+    /// Unfair mutexes (see `MutexBuilder::fair`) only wake the first waiter,
+    /// and `WakePolicy::WakeAll` wakes every waiter.
+    #[track_caller]
+    pub fn lock(&self) -> MutexAcquire<'_, T> {
+        MutexAcquire {
+            mutex: self,
+            waiter: Waiter::new(),
+            caller: Caller::here(),
+            terminated: false,
+        }
+    }
+    #[track_caller]
+    pub fn lock_owned(self: &Rc<Self>) -> OwnedMutexAcquire<T> {
+        OwnedMutexAcquire {
+            mutex: self.clone(),
+            waiter: Waiter::new(),
+            caller: Caller::here(),
+            terminated: false,
+        }
+    }
+    /// Returns a stream that acquires the lock each time it is polled.
+    ///
+    /// Every acquisition queues up behind the other waiters, so the
+    /// previous guard must be dropped before asking for the next one.
+    #[track_caller]
+    pub fn lock_stream(&self) -> MutexLockStream<'_, T> {
+        MutexLockStream {
+            acquire: self.lock(),
+        }
+    }
+    /// Shares the IO object behind the mutex between tasks.
+    ///
+    /// The lock is taken for each read, write, flush and close, and kept
+    /// until it completes: the IO object wakes only the task it left
+    /// pending. Between operations other tasks may take their turn. A
+    /// poisoned lock is used all the same.
+    #[cfg(feature = "io")]
+    #[track_caller]
+    pub fn as_io(&self) -> MutexIo<'_, T>
+    where
+        T: Unpin,
+    {
+        MutexIo::new(self)
+    }
+    /// Shares the sink behind the mutex between tasks.
+    ///
+    /// The lock is taken from the first `poll_ready` until the
+    /// `start_send` that follows, so no other item can slip in between,
+    /// and from the first `poll_flush` or `poll_close` until it completes.
+    /// It is kept while the sink returns `Pending`, as the sink wakes only
+    /// the task that polled it. A poisoned lock is written through all the
+    /// same.
+    #[cfg(feature = "sink")]
+    #[track_caller]
+    pub fn as_sink(&self) -> MutexSink<'_, T> {
+        MutexSink::new(self)
+    }
+    #[track_caller]
+    pub fn lock_timeout<D>(&self, delay: D) -> Timeout<MutexAcquire<'_, T>, D>
+    where
+        D: Future<Output = ()>,
+    {
+        self.lock().timeout(delay)
+    }
+    /// Tries to acquire the lock until `timeout` has passed.
+    ///
+    /// The crate has no timer of its own: `sleep` turns the timeout into
+    /// a delay future, such as the sleep function of the executor in use.
+    #[track_caller]
+    pub fn try_lock_for<S, D>(
+        &self,
+        timeout: Duration,
+        sleep: S,
+    ) -> TryLockTimeout<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        TryLockTimeout::new(self.lock(), sleep(timeout))
+    }
+    /// Tries to acquire the lock until `deadline`.
+    ///
+    /// `sleep_until` turns the deadline into a delay future,
+    /// as in `try_lock_for`.
+    #[cfg(feature = "std")]
+    #[track_caller]
+    pub fn try_lock_until<S, D>(
+        &self,
+        deadline: Instant,
+        sleep_until: S,
+    ) -> TryLockTimeout<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Instant) -> D,
+        D: Future<Output = ()>,
+    {
+        TryLockTimeout::new(self.lock(), sleep_until(deadline))
+    }
+    /// Like `lock`, but reports poisoning as a `LockError`, and fails with
+    /// `LockError::QueueFull` past `MutexBuilder::max_waiters`.
+    #[track_caller]
+    pub fn lock_checked(&self) -> LockChecked<MutexAcquire<'_, T>, future::Pending<()>> {
+        LockChecked::new(self.lock(), future::pending(), false)
+    }
+    /// Like `try_lock_for`, but fails with `LockError::TimedOut`.
+    #[track_caller]
+    pub fn lock_checked_for<S, D>(
+        &self,
+        timeout: Duration,
+        sleep: S,
+    ) -> LockChecked<MutexAcquire<'_, T>, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        LockChecked::new(self.lock(), sleep(timeout), true)
+    }
+    /// Acquires the lock unless `cancel` completes first,
+    /// in which case it fails with `LockError::Cancelled`.
+    #[track_caller]
+    pub fn lock_cancellable<C>(&self, cancel: C) -> LockChecked<MutexAcquire<'_, T>, C>
+    where
+        C: Future<Output = ()>,
+    {
+        LockChecked::new(self.lock(), cancel, false)
+    }
+    /// Like `lock_cancellable`, with `token.cancelled()` as the cancellation.
+    #[cfg(feature = "std")]
+    #[track_caller]
+    pub fn lock_with_cancel<'a>(
+        &'a self,
+        token: &'a CancellationToken,
+    ) -> LockChecked<MutexAcquire<'a, T>, Cancelled<'a>> {
+        self.lock_cancellable(token.cancelled())
+    }
+    /// Runs `f` on the locked data, releasing the lock as soon as it returns.
+    pub async fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> LockResult<R> {
+        poison::map_result(self.lock().await, |mut guard| f(&mut guard))
+    }
+    /// Acquires the lock, then passes the guard to `f`.
+    ///
+    /// Unlike `with_lock`, this returns a nameable future,
+    /// for use in hand-written futures.
+    #[track_caller]
+    pub fn lock_and_then<F, R>(&self, f: F) -> MutexLockAndThen<'_, T, F>
+    where
+        F: FnOnce(MutexGuard<'_, T>) -> R,
+    {
+        MutexLockAndThen {
+            acquire: self.lock(),
+            f: Some(f),
+        }
+    }
+    /// Acquires the lock, then iterates over copies of the items in the
+    /// data, holding the lock until the iterator is dropped.
+    ///
+    /// The items are cloned, as references to them would outlive the lock.
+    #[track_caller]
+    pub fn lock_iter<'a>(&'a self) -> MutexLockIter<'a, T>
+    where
+        &'a T: IntoIterator,
+    {
+        MutexLockAndThen {
+            acquire: self.lock(),
+            f: Some(MutexIter::new),
+        }
+    }
+    /// Like `with_lock`, but `f` may await while holding the lock.
+    pub async fn with_lock_async<R>(&self, f: impl AsyncFnOnce(&mut T) -> R) -> LockResult<R> {
+        let (mut guard, poisoned) = match self.lock().await {
+            Ok(guard) => (guard, false),
+            Err(err) => (err.into_inner(), true),
+        };
+        let ret = f(&mut guard).await;
+        drop(guard);
+        if poisoned {
+            Err(PoisonError::new(ret))
+        } else {
+            Ok(ret)
+        }
+    }
+    /// Like `with_lock_async`, but poisons the mutex if the future is
+    /// dropped before `f` completes.
+    ///
+    /// A future cancelled halfway, say by losing a `select!`, may leave
+    /// the data as half-updated as a panic would. The lock is released
+    /// either way, after the future of `f` is dropped.
+    pub async fn scope<R>(&self, f: impl AsyncFnOnce(&mut T) -> R) -> LockResult<R> {
+        let (mut guard, poisoned) = match self.lock().await {
+            Ok(guard) => (guard, false),
+            Err(err) => (err.into_inner(), true),
+        };
+        // Dropped before `guard`, while the lock is still held.
+        let mut cancelled = PoisonOnDrop {
+            raw: &self.raw,
+            armed: true,
+        };
+        let ret = f(&mut guard).await;
+        cancelled.armed = false;
+        drop(guard);
+        if poisoned {
+            Err(PoisonError::new(ret))
+        } else {
+            Ok(ret)
+        }
+    }
+    /// Acquires the lock once `predicate` holds for the data.
+    ///
+    /// Each time the predicate fails, the lock is released and the task
+    /// waits for someone else to acquire and release it before checking
+    /// again, as with a condition variable notified on every release.
+    #[track_caller]
+    pub fn lock_when<P>(&self, predicate: P) -> MutexLockWhen<'_, T, P>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        MutexLockWhen {
+            acquire: self.lock(),
+            predicate,
+        }
+    }
+    /// Like `try_lock`, but fails with `WouldBlock` unless `predicate`
+    /// holds for the data. The task is not woken later either way.
+    #[track_caller]
+    pub fn try_lock_when(
+        &self,
+        predicate: impl FnOnce(&T) -> bool,
+    ) -> TryLockResult<MutexGuard<'_, T>> {
+        let result = self.try_lock();
+        let holds = match &result {
+            Ok(guard) => predicate(guard),
+            Err(TryLockError::Poisoned(err)) => predicate(err.get_ref()),
+            Err(TryLockError::WouldBlock) => true,
+        };
+        if holds {
+            result
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+    /// Unlike `lock`, this does not queue up for the lock:
+    /// the task is merely woken whenever the lock is released.
+    #[track_caller]
+    pub fn poll_lock(&self, cx: &mut Context<'_>) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        match self.raw.poll_acquire_unqueued(cx) {
+            Poll::Ready(()) => Poll::Ready(self.guard(Caller::here())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    /// Like `poll_lock`, but fails with `LockError::QueueFull` rather than
+    /// wait behind as many tasks as `MutexBuilder::max_waiters` allows.
+    #[track_caller]
+    pub fn poll_lock_checked(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<MutexGuard<'_, T>, LockError<MutexGuard<'_, T>>>> {
+        match self.raw.poll_acquire_bounded(cx) {
+            Poll::Ready(true) => {
+                Poll::Ready(self.guard(Caller::here()).map_err(LockError::Poisoned))
+            }
+            Poll::Ready(false) => Poll::Ready(Err(LockError::QueueFull)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    /// Takes the lock if it is free, without registering the task
+    /// to be woken.
+    ///
+    /// This is for callers that retry on their own schedule, such as
+    /// with a backoff: unlike `poll_lock`, a pending poll leaves nothing
+    /// behind, and nothing wakes the task when the lock is released.
+    #[track_caller]
+    pub fn poll_lock_unregistered(
+        &self,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        match self.raw.poll_acquire_unregistered(cx) {
+            Poll::Ready(()) => Poll::Ready(self.guard(Caller::here())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+    /// Polls for the lock, keeping the queue position in `token`.
+    ///
+    /// This queues up like `lock`, for futures written by hand.
+    /// The token must have been created by this mutex's `wait_token`.
+    #[track_caller]
+    pub fn poll_lock_with(
+        &self,
+        token: Pin<&mut WaitToken<'_>>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        let token = token.into_ref().get_ref();
+        assert!(
+            ptr::eq(token.raw, &self.raw),
+            "WaitToken used with another mutex"
+        );
+        let waiter = unsafe { Pin::new_unchecked(&token.waiter) };
+        self.poll_acquire(waiter, cx, Caller::here())
+    }
+
+    /// Creates a slot for `poll_lock_with`. Dropping the token leaves the queue.
+    pub fn wait_token(&self) -> WaitToken<'_> {
+        WaitToken {
+            raw: &self.raw,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Releases the lock; the same as dropping the guard.
+    pub fn unlock(guard: MutexGuard<'_, T>) {
+        drop(guard);
+    }
+
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        if !self.raw.try_acquire_untasked() {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = MutexGuard::new(self, Caller::here());
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard).into())
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Like `try_lock`, but returns a guard that holds on to the `Rc`.
+    #[track_caller]
+    pub fn try_lock_owned(self: &Rc<Self>) -> TryLockResult<OwnedMutexGuard<T>> {
+        poison::map_try_result(self.try_lock(), |guard| {
+            OwnedMutexGuard::new(self.clone(), guard)
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.raw.locked.get()
+    }
+
+    /// Returns the contention statistics gathered since the mutex was created.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> MutexStats {
+        self.raw.stats.get()
+    }
+
+    /// Registers a hook called on the lock events of this mutex, after
+    /// the one set with `instrument::set_hook`, replacing the previous one.
+    #[cfg(feature = "instrument")]
+    pub fn set_event_hook(&self, hook: fn(&LockEvent)) {
+        self.raw.instrument.set_hook(Some(hook));
+    }
+
+    /// Unregisters the hook set with `set_event_hook`, if any.
+    #[cfg(feature = "instrument")]
+    pub fn take_event_hook(&self) {
+        self.raw.instrument.set_hook(None);
+    }
+
+    /// Returns the number of tasks queued up in `lock`.
+    ///
+    /// Tasks polling through `poll_lock` are not counted.
+    pub fn waiter_count(&self) -> usize {
+        self.raw.waiters.len()
+    }
+
+    /// Returns where the tasks queued up in `lock` called it, in the
+    /// order they started waiting, to find out what a hung program is
+    /// waiting for.
+    #[cfg(all(feature = "debug-tracking", feature = "std"))]
+    pub fn waiting_locations(&self) -> Vec<&'static Location<'static>> {
+        self.raw.waiting_at.locations()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.poisoned.get()
+    }
+
+    pub fn clear_poison(&self) {
+        self.raw.poisoned.set(false);
+    }
+
+    /// Releases the lock without a guard.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held through a guard that has been leaked or
+    /// forgotten, and the data must no longer be accessed through it.
+    pub unsafe fn force_unlock(&self) {
+        self.raw.unlock();
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let inner = unsafe { &mut *self.data.get() };
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    /// Returns a raw pointer to the data, for passing it to FFI.
+    ///
+    /// The pointer may only be dereferenced while the lock is held,
+    /// and accesses through it must not overlap with those through the guard.
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    fn poll_acquire(
+        &self,
+        waiter: Pin<&Waiter>,
+        cx: &mut Context<'_>,
+        caller: Caller,
+    ) -> Poll<LockResult<MutexGuard<'_, T>>> {
+        match self.poll_acquire_capped(waiter, cx, caller, false) {
+            Poll::Ready(Some(result)) => Poll::Ready(result),
+            Poll::Ready(None) => unreachable!("an uncapped acquisition gave up"),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// With `capped`, gives up with `None` rather than queue up behind as
+    /// many tasks as `MutexBuilder::max_waiters` allows.
+    fn poll_acquire_capped(
+        &self,
+        waiter: Pin<&Waiter>,
+        cx: &mut Context<'_>,
+        caller: Caller,
+        capped: bool,
+    ) -> Poll<Option<LockResult<MutexGuard<'_, T>>>> {
+        let key = waiter.get_ref() as *const Waiter as usize;
+        match self.raw.poll_acquire_capped(waiter, cx, capped) {
+            Poll::Ready(true) => {
+                self.raw.waiting_at.stopped(key);
+                Poll::Ready(Some(self.guard(caller)))
+            }
+            Poll::Ready(false) => Poll::Ready(None),
+            Poll::Pending => {
+                if waiter.is_queued() {
+                    self.raw.waiting_at.started(key, caller);
+                }
+                Poll::Pending
+            }
+        }
+    }
+
+    fn guard(&self, caller: Caller) -> LockResult<MutexGuard<'_, T>> {
+        let guard = MutexGuard::new(self, caller);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Runs `f` on the data if the lock is free, without it counting as an
+    /// acquisition: stats, the event hook and deadlock detection never see
+    /// it, and a panic in `f` does not poison the mutex. For `Debug` and
+    /// serialization, which only look at the data.
+    pub(super) fn peek<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        struct Release<'a>(&'a RawMutex);
+        impl Drop for Release<'_> {
+            fn drop(&mut self) {
+                self.0.release_peek();
+            }
+        }
+
+        if !self.raw.try_acquire() {
+            return None;
+        }
+        let _release = Release(&self.raw);
+        Some(f(unsafe { &*self.data.get() }))
+    }
+}
+
+impl RawMutex {
+    pub(super) const fn new(
+        policy: WakePolicy,
+        yield_after: u32,
+        max_waiters: usize,
+        fair_after: Option<Duration>,
+    ) -> Self {
+        Self {
+            locked: Cell::new(false),
+            poisoned: Cell::new(false),
+            waiters: WaiterList::new(),
+            wakers: Cell::new(SmallDeque::new()),
+            policy,
+            yield_after,
+            max_waiters,
+            fairness: Fairness::new(fair_after),
+            stats: Stats::new(),
+            instrument: Instrument::new(),
+            acquired_at: AcquiredAt::new(),
+            waiting_at: WaitingAt::new(),
+            last_owner: Cell::new(None),
+            streak: Cell::new(0),
+        }
+    }
+
+    pub(super) fn try_acquire(&self) -> bool {
+        !self.locked.replace(true)
+    }
+
+    /// The waiter must be abandoned before it is dropped.
+    pub(super) fn poll_acquire(&self, waiter: Pin<&Waiter>, cx: &mut Context<'_>) -> Poll<()> {
+        self.poll_acquire_capped(waiter, cx, false).map(drop)
+    }
+
+    /// Like `poll_acquire`, but with `capped`, gives up with `false` rather
+    /// than queue up behind `max_waiters` other tasks. A task that is queued
+    /// already keeps its place.
+    fn poll_acquire_capped(
+        &self,
+        waiter: Pin<&Waiter>,
+        cx: &mut Context<'_>,
+        capped: bool,
+    ) -> Poll<bool> {
+        let key = waiter.get_ref() as *const Waiter as usize;
+        self.instrument.requested(self.id(), Some(key), cx.waker());
+        let mut waited = true;
+        let acquired = if waiter.is_queued() {
+            // Only an unfair release leaves the lock free while someone is
+            // queued, in which case the first waiter has been woken, or all
+            // of them with `WakeAll`.
+            (self.policy == WakePolicy::WakeAll || self.waiters.is_first(&waiter))
+                && self.try_acquire()
+                && {
+                    self.waiters.remove(&waiter);
+                    true
+                }
+        } else if self.waiters.take_notified(&waiter) {
+            true
+        } else if self.should_yield(cx.waker()) {
+            return Poll::Pending;
+        } else {
+            // With fair releases, the lock is never left unlocked while
+            // someone is queued, so a newcomer cannot overtake the queue.
+            waited = false;
+            self.try_acquire()
+        };
+        if !acquired {
+            if capped && !waiter.is_queued() && self.queue_full(cx.waker()) {
+                return Poll::Ready(false);
+            }
+            unsafe { self.waiters.register(waiter, cx.waker()) };
+            self.stats.wait_started(key, self.waiters.len());
+            deadlock::wait(self.id(), key, cx.waker());
+            self.instrument.wait_started(self.id(), key, cx.waker());
+            return Poll::Pending;
+        }
+        deadlock::acquired(self.id(), Some(key), Some(cx.waker()));
+        self.instrument.acquired(self.id(), Some(key), Some(cx.waker()));
+        self.stats.acquired(Some(key));
+        if waited {
+            self.fairness.served();
+        }
+        self.count_acquisition(waited, cx.waker());
+        Poll::Ready(true)
+    }
+
+    fn poll_acquire_unqueued(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.should_yield(cx.waker()) {
+            return Poll::Pending;
+        }
+        if self.poll_acquire_unregistered(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        self.push_waker(cx.waker());
+        Poll::Pending
+    }
+
+    /// Like `poll_acquire_unqueued`, but gives up with `false` rather than
+    /// wait behind `max_waiters` other tasks.
+    fn poll_acquire_bounded(&self, cx: &mut Context<'_>) -> Poll<bool> {
+        if self.should_yield(cx.waker()) {
+            return Poll::Pending;
+        }
+        if self.poll_acquire_unregistered(cx).is_ready() {
+            return Poll::Ready(true);
+        }
+        if self.queue_full(cx.waker()) {
+            return Poll::Ready(false);
+        }
+        self.push_waker(cx.waker());
+        Poll::Pending
+    }
+
+    /// Whether `max_waiters` tasks are waiting, not counting the task
+    /// behind `waker` if it is among them.
+    fn queue_full(&self, waker: &Waker) -> bool {
+        let wakers = self.wakers.take();
+        let waiting = wakers.iter().any(|old| old.will_wake(waker));
+        let len = self.waiters.len() + wakers.len();
+        self.wakers.set(wakers);
+        !waiting && len >= self.max_waiters
+    }
+
+    /// Acquires the lock if it is free, outside of any task.
+    ///
+    /// With no waker to identify the caller, the holder is recorded as
+    /// unknown, which still keeps the previous one from being blamed.
+    pub(super) fn try_acquire_untasked(&self) -> bool {
+        if !self.try_acquire() {
+            return false;
+        }
+        deadlock::acquired(self.id(), None, None);
+        self.instrument.acquired(self.id(), None, None);
+        self.stats.acquired(None);
+        true
+    }
+
+    fn poll_acquire_unregistered(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.try_acquire() {
+            return Poll::Pending;
+        }
+        deadlock::acquired(self.id(), None, Some(cx.waker()));
+        self.instrument.acquired(self.id(), None, Some(cx.waker()));
+        self.stats.acquired(None);
+        self.count_acquisition(false, cx.waker());
+        Poll::Ready(())
+    }
+
+    /// Whether the task has used up its acquisitions in a row, in which
+    /// case it is woken to be polled again after the others.
+    fn should_yield(&self, waker: &Waker) -> bool {
+        if self.yield_after == 0 || self.streak.get() < self.yield_after {
+            return false;
+        }
+        let owner = self.last_owner.take();
+        let same_task = owner.as_ref().is_some_and(|owner| owner.will_wake(waker));
+        self.last_owner.set(owner);
+        if same_task {
+            self.streak.set(0);
+            waker.wake_by_ref();
+        }
+        same_task
+    }
+
+    fn count_acquisition(&self, waited: bool, waker: &Waker) {
+        if self.yield_after == 0 {
+            return;
+        }
+        let owner = self
+            .last_owner
+            .take()
+            .filter(|owner| owner.will_wake(waker));
+        let streak = if waited {
+            0
+        } else if owner.is_some() {
+            self.streak.get() + 1
+        } else {
+            1
+        };
+        self.streak.set(streak);
+        self.last_owner
+            .set(Some(owner.unwrap_or_else(|| waker.clone())));
+    }
+
+    pub(super) fn abandon(&self, waiter: &Waiter) {
+        deadlock::cancel(self.id(), waiter as *const Waiter as usize);
+        self.instrument.cancelled(self.id(), waiter as *const Waiter as usize);
+        self.stats.cancelled(waiter as *const Waiter as usize);
+        self.waiting_at.stopped(waiter as *const Waiter as usize);
+        let first = self.waiters.is_first(waiter);
+        if self.waiters.remove(waiter) {
+            // We have been handed the lock; pass it on.
+            self.unlock();
+        } else if first && !self.locked.get() {
+            // We may have been woken by an unfair release; pass that on.
+            if let Some(next) = self.waiters.first_waker() {
+                self.wake(next);
+            }
+        }
+    }
+
+    fn release(&self, panic: &PanicWatch) {
+        self.poison_if_panicked(panic);
+        self.unlock();
+    }
+
+    fn release_fair(&self, panic: &PanicWatch) {
+        self.poison_if_panicked(panic);
+        self.unlock_fair();
+    }
+
+    fn release_unfair(&self, panic: &PanicWatch) {
+        self.poison_if_panicked(panic);
+        self.unlock_unfair();
+    }
+
+    /// Releases the lock like `release`, then sets `waker` aside for the
+    /// next release.
+    fn release_and_wait(&self, panic: &PanicWatch, waker: &Waker) {
+        self.release(panic);
+        self.push_waker(waker);
+    }
+
+    fn push_waker(&self, waker: &Waker) {
+        let mut wakers = self.wakers.take();
+        // A task polling again has already left its waker.
+        if !wakers.iter().any(|old| old.will_wake(waker)) {
+            wakers.push_back(waker.clone());
+        }
+        self.wakers.set(wakers);
+    }
+
+    /// Releases a lock taken by `Mutex::peek`, reporting nothing. Whoever
+    /// queued up in the meantime is woken as by an unfair release.
+    fn release_peek(&self) {
+        self.locked.set(false);
+        if let Some(next) = self.waiters.first_waker() {
+            wake_batch::wake(next);
+        }
+        for waker in self.wakers.take() {
+            wake_batch::wake(waker);
+        }
+    }
+
+    fn poison(&self) {
+        self.poisoned.set(true);
+    }
+
+    fn poison_if_panicked(&self, panic: &PanicWatch) {
+        if panic.panicked() {
+            self.poisoned.set(true);
+        }
+    }
+
+    pub(super) fn unlock(&self) {
+        match self.policy {
+            WakePolicy::Handoff => self.unlock_fair(),
+            // Waiters that have been overtaken for too long get the lock.
+            _ if self.waiters.len() > 0 && self.fairness.overdue() => self.unlock_fair(),
+            WakePolicy::WakeOne => self.unlock_unfair(),
+            WakePolicy::WakeAll => self.unlock_wake_all(),
+        }
+    }
+
+    fn unlock_fair(&self) {
+        self.acquired_at.clear();
+        let next = self.waiters.notify_one();
+        deadlock::released(self.id(), next.as_ref());
+        self.instrument.released(self.id());
+        match next {
+            Some(next) => self.wake(next),
+            None => self.locked.set(false),
+        }
+        for waker in self.wakers.take() {
+            self.wake(waker);
+        }
+    }
+
+    fn unlock_unfair(&self) {
+        self.acquired_at.clear();
+        self.locked.set(false);
+        deadlock::released(self.id(), None);
+        self.instrument.released(self.id());
+        if let Some(next) = self.waiters.first_waker() {
+            self.wake(next);
+        }
+        for waker in self.wakers.take() {
+            self.wake(waker);
+        }
+    }
+
+    fn unlock_wake_all(&self) {
+        self.acquired_at.clear();
+        self.locked.set(false);
+        deadlock::released(self.id(), None);
+        self.instrument.released(self.id());
+        for waker in self.waiters.wakers() {
+            self.wake(waker);
+        }
+        for waker in self.wakers.take() {
+            self.wake(waker);
+        }
+    }
+
+    /// Wakes a task waiting for the lock.
+    fn wake(&self, waker: Waker) {
+        self.instrument.woken(self.id(), &waker);
+        wake_batch::wake(waker);
+    }
+
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(x: T) -> Self {
+        Mutex::new(x)
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = self.peek(|data| f.debug_struct("Mutex").field("data", &data).finish());
+        if let Some(result) = shown {
+            result
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            let mut d = f.debug_struct("Mutex");
+            d.field("data", &LockedPlaceholder);
+            self.raw.acquired_at.fmt_field(&mut d);
+            d.finish()
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+    panic: PanicWatch,
+    locked: bool,
+}
+
+impl<'a, T: ?Sized + 'a> MutexGuard<'a, T> {
+    fn new(mutex: &'a Mutex<T>, caller: Caller) -> Self {
+        mutex.raw.acquired_at.set(caller);
+        Self {
+            mutex,
+            panic: PanicWatch::new(),
+            locked: true,
+        }
+    }
+
+    /// Consumes the guard without releasing the lock.
+    fn forget(this: Self) -> PanicWatch {
+        let this = ManuallyDrop::new(this);
+        unsafe { ptr::read(&this.panic) }
+    }
+
+    /// Turns the guard into a guard of the same mutex as another type,
+    /// typically a trait object:
+    /// `MutexGuard::coerce(guard, |m| m as &Mutex<dyn Trait>)`.
+    ///
+    /// `f` is there to do the unsizing coercion, which generic code cannot
+    /// express on stable Rust. Unlike `map`, the result is still a
+    /// `MutexGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns another mutex.
+    pub fn coerce<U: ?Sized, F>(this: Self, f: F) -> MutexGuard<'a, U>
+    where
+        F: FnOnce(&'a Mutex<T>) -> &'a Mutex<U>,
+    {
+        let mutex = f(this.mutex);
+        assert!(
+            ptr::addr_eq(mutex, this.mutex),
+            "MutexGuard::coerce: the closure returned another mutex"
+        );
+        let locked = this.locked;
+        MutexGuard {
+            mutex,
+            panic: MutexGuard::forget(this),
+            locked,
+        }
+    }
+
+    /// Returns the mutex the guard locks, to lock it again later or
+    /// check it for poisoning.
+    pub fn mutex(this: &Self) -> &'a Mutex<T> {
+        this.mutex
+    }
+
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data() }) as *mut U;
+        let raw = &this.mutex.raw;
+        MappedMutexGuard {
+            raw,
+            data,
+            panic: MutexGuard::forget(this),
+            shared: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Releases the lock, handing it over to the first waiter
+    /// even if the mutex is unfair.
+    pub fn unlock_fair(this: Self) {
+        if this.locked {
+            this.mutex.raw.release_fair(&this.panic);
+        }
+        MutexGuard::forget(this);
+    }
+
+    /// Releases the lock, waking the first waiter without handing it the
+    /// lock, even if the mutex is fair. A task polling `lock()` first can
+    /// then take the lock ahead of the waiter.
+    pub fn unlock_unfair(this: Self) {
+        if this.locked {
+            this.mutex.raw.release_unfair(&this.panic);
+        }
+        MutexGuard::forget(this);
+    }
+
+    /// Releases the lock, and has the next release wake `waker`.
+    fn release_and_wait(this: Self, waker: &Waker) {
+        if this.locked {
+            this.mutex.raw.release_and_wait(&this.panic, waker);
+        }
+        MutexGuard::forget(this);
+    }
+
+    /// Keeps the mutex locked forever, returning a reference to the data
+    /// that lives as long as the mutex.
+    pub fn leak(this: Self) -> &'a mut T {
+        let data = this.data();
+        MutexGuard::forget(this);
+        unsafe { &mut *data }
+    }
+
+    /// Holds the lock for `duration`, then releases it.
+    ///
+    /// This is meant for simulating contention in tests. As in
+    /// `Mutex::try_lock_for`, `sleep` turns the duration into a delay future.
+    pub fn hold_for<S, D>(this: Self, duration: Duration, sleep: S) -> MutexHold<'a, T, D>
+    where
+        S: FnOnce(Duration) -> D,
+        D: Future<Output = ()>,
+    {
+        MutexHold {
+            guard: Some(this),
+            delay: sleep(duration),
+        }
+    }
+
+    /// Releases the lock while `f` runs, then acquires it again.
+    ///
+    /// Poisoning that happens while the lock is released is not reported.
+    /// If the returned future is dropped before the lock is acquired again,
+    /// the guard is left unlocked and any further access through it panics.
+    pub async fn unlocked<R>(this: &mut Self, f: impl Future<Output = R>) -> R {
+        this.mutex.raw.release(&this.panic);
+        this.locked = false;
+        let ret = f.await;
+        let guard = this
+            .mutex
+            .lock()
+            .await
+            .unwrap_or_else(PoisonError::into_inner);
+        this.panic = MutexGuard::forget(guard);
+        this.locked = true;
+        ret
+    }
+
+    fn data(&self) -> *mut T {
+        assert!(self.locked, "MutexGuard used after `unlocked` was cancelled");
+        self.mutex.data.get()
+    }
+}
+
+impl<'a, A: 'a, B: 'a> MutexGuard<'a, (A, B)> {
+    /// Splits the guard into guards of the two fields, which can be used
+    /// and dropped separately. The lock is released once both are dropped.
+    pub fn split(this: Self) -> (MappedMutexGuard<'a, A>, MappedMutexGuard<'a, B>) {
+        MappedMutexGuard::split(MutexGuard::map(this, |data| data))
+    }
+}
+
+impl<'a, T: 'a> MutexGuard<'a, [T]> {
+    /// Like `split`, but splits a slice at `mid` as `<[T]>::split_at_mut`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid > len`.
+    pub fn split_at(
+        this: Self,
+        mid: usize,
+    ) -> (MappedMutexGuard<'a, [T]>, MappedMutexGuard<'a, [T]>) {
+        MappedMutexGuard::split_at(MutexGuard::map(this, |data| data), mid)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> ClearPoison for MutexGuard<'a, T> {
+    fn clear_poison(guard: &Self) {
+        guard.mutex.clear_poison();
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        if self.locked {
+            self.mutex.raw.release(&self.panic);
+        }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("MutexGuard");
+        d.field("data", &(self as &T));
+        self.mutex.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedMutexGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawMutex,
+    data: *mut T,
+    panic: PanicWatch,
+    /// Shared by the parts of a split guard, the last of which releases
+    /// the lock.
+    shared: Option<Rc<()>>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: ?Sized + 'a> MappedMutexGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedMutexGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data }) as *mut U;
+        let this = ManuallyDrop::new(this);
+        MappedMutexGuard {
+            raw: this.raw,
+            data,
+            panic: unsafe { ptr::read(&this.panic) },
+            shared: unsafe { ptr::read(&this.shared) },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Splits the guard into guards of two disjoint parts of the data,
+    /// as returned by `f`.
+    fn split_with<U: ?Sized, V: ?Sized, F>(
+        this: Self,
+        f: F,
+    ) -> (MappedMutexGuard<'a, U>, MappedMutexGuard<'a, V>)
+    where
+        F: FnOnce(&mut T) -> (&mut U, &mut V),
+    {
+        let (first, second) = f(unsafe { &mut *this.data });
+        let (first, second) = (first as *mut U, second as *mut V);
+        let this = ManuallyDrop::new(this);
+        let panic = unsafe { ptr::read(&this.panic) };
+        let shared = unsafe { ptr::read(&this.shared) }.unwrap_or_default();
+        let first = MappedMutexGuard {
+            raw: this.raw,
+            data: first,
+            panic: panic.clone(),
+            shared: Some(shared.clone()),
+            _marker: PhantomData,
+        };
+        let second = MappedMutexGuard {
+            raw: this.raw,
+            data: second,
+            panic,
+            shared: Some(shared),
+            _marker: PhantomData,
+        };
+        (first, second)
+    }
+}
+
+impl<'a, A: 'a, B: 'a> MappedMutexGuard<'a, (A, B)> {
+    /// Like `MutexGuard::split`.
+    pub fn split(this: Self) -> (MappedMutexGuard<'a, A>, MappedMutexGuard<'a, B>) {
+        MappedMutexGuard::split_with(this, |(a, b)| (a, b))
+    }
+}
+
+impl<'a, T: 'a> MappedMutexGuard<'a, [T]> {
+    /// Like `MutexGuard::split_at`.
+    pub fn split_at(
+        this: Self,
+        mid: usize,
+    ) -> (MappedMutexGuard<'a, [T]>, MappedMutexGuard<'a, [T]>) {
+        MappedMutexGuard::split_with(this, |data| data.split_at_mut(mid))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MappedMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        match self.shared.take() {
+            // Another part of a split guard still holds the lock.
+            Some(shared) if Rc::strong_count(&shared) > 1 => {
+                self.raw.poison_if_panicked(&self.panic);
+            }
+            _ => self.raw.release(&self.panic),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("MappedMutexGuard");
+        d.field("data", &(self as &T));
+        self.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+/// The future returned by `Mutex::lock`.
+///
+/// Polling it again after it has completed starts a new acquisition, which
+/// queues up behind the other waiters like a fresh call to `lock` would.
+/// Until then, `is_terminated` returns `true`, so `select!` leaves it alone.
+#[derive(Debug)]
+pub struct MutexAcquire<'a, T: ?Sized + 'a> {
+    mutex: &'a Mutex<T>,
+    waiter: Waiter,
+    caller: Caller,
+    terminated: bool,
+}
+
+impl<'a, T: ?Sized + 'a> MutexAcquire<'a, T> {
+    /// Returns the mutex being acquired.
+    pub fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+
+    pub fn timeout<D>(self, delay: D) -> Timeout<Self, D>
+    where
+        D: Future<Output = ()>,
+    {
+        Timeout::new(self, delay)
+    }
+
+    /// Counts the tasks queued ahead of this one: 0 for the first in line.
+    ///
+    /// Returns `None` unless the future is waiting in the queue, i.e. before
+    /// it is first polled and after it acquires the lock. A caller can
+    /// check this between polls to give up when the queue is too long.
+    pub fn queue_position(&self) -> Option<usize> {
+        self.mutex.raw.waiters.position(&self.waiter)
+    }
+
+    /// Acquires the lock if it is free right now, like `try_lock`, or
+    /// gives the future back to wait for it.
+    ///
+    /// This lets a caller skip boxing or spawning the wait when the lock
+    /// is not contended. A future that is already queued is given back
+    /// as is, as taking the lock ahead of it would jump the queue.
+    pub fn try_now(self) -> Result<LockResult<MutexGuard<'a, T>>, Self> {
+        if self.waiter.is_queued() || !self.mutex.raw.try_acquire_untasked() {
+            return Err(self);
+        }
+        Ok(self.mutex.guard(self.caller))
+    }
+
+    /// Converts this into a futures 0.1 future, which resolves to the guard
+    /// or fails with the poison error.
+    #[cfg(feature = "compat")]
+    pub fn compat(self) -> Compat<Pin<Box<Self>>> {
+        Compat::new(Box::pin(self))
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Future for MutexAcquire<'a, T> {
+    type Output = LockResult<MutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is pinned along with `self`; the flag is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = unsafe { Pin::new_unchecked(&this.waiter) };
+        let poll = this.mutex.poll_acquire(waiter, cx, this.caller);
+        this.terminated = poll.is_ready();
+        poll
+    }
+}
+
+impl<'a, T: ?Sized + 'a> CheckedAcquire for MutexAcquire<'a, T> {
+    type Guard = MutexGuard<'a, T>;
+    fn poll_checked(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Guard, LockError<Self::Guard>>> {
+        // The waiter is pinned along with `self`; the flag is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = unsafe { Pin::new_unchecked(&this.waiter) };
+        let poll = this.mutex.poll_acquire_capped(waiter, cx, this.caller, true);
+        this.terminated = poll.is_ready();
+        poll.map(|result| match result {
+            Some(result) => result.map_err(LockError::Poisoned),
+            None => Err(LockError::QueueFull),
+        })
+    }
+}
+
+impl<'a, T: ?Sized + 'a> FusedFuture for MutexAcquire<'a, T> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MutexAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.raw.abandon(&self.waiter);
+    }
+}
+
+/// Holds a guard until a delay completes; see `MutexGuard::hold_for`.
+#[derive(Debug)]
+pub struct MutexHold<'a, T: ?Sized + 'a, D> {
+    guard: Option<MutexGuard<'a, T>>,
+    delay: D,
+}
+
+impl<'a, T: ?Sized + 'a, D: Future<Output = ()>> Future for MutexHold<'a, T, D> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The delay is pinned along with `self`; the guard is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let delay = unsafe { Pin::new_unchecked(&mut this.delay) };
+        match delay.poll(cx) {
+            Poll::Ready(()) => {
+                this.guard = None;
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MutexLockStream<'a, T: ?Sized + 'a> {
+    acquire: MutexAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Stream for MutexLockStream<'a, T> {
+    type Item = LockResult<MutexGuard<'a, T>>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The acquisition starts over once it has completed.
+        let acquire = unsafe { self.map_unchecked_mut(|this| &mut this.acquire) };
+        acquire.poll(cx).map(Some)
+    }
+}
+
+pub struct MutexLockWhen<'a, T: ?Sized + 'a, P> {
+    acquire: MutexAcquire<'a, T>,
+    predicate: P,
+}
+
+impl<'a, T: ?Sized + 'a, P> Future for MutexLockWhen<'a, T, P>
+where
+    P: FnMut(&T) -> bool,
+{
+    type Output = LockResult<MutexGuard<'a, T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The acquisition is pinned along with `self`; the predicate is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = unsafe { Pin::new_unchecked(&mut this.acquire) };
+        // The acquisition starts over once it has completed.
+        let result = match acquire.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        let holds = match &result {
+            Ok(guard) => (this.predicate)(guard),
+            Err(err) => (this.predicate)(err.get_ref()),
+        };
+        if holds {
+            return Poll::Ready(result);
+        }
+        let guard = result.unwrap_or_else(PoisonError::into_inner);
+        MutexGuard::release_and_wait(guard, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<'a, T: ?Sized + 'a, P> fmt::Debug for MutexLockWhen<'a, T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("MutexLockWhen { .. }")
+    }
+}
+
+pub struct MutexLockAndThen<'a, T: ?Sized + 'a, F> {
+    acquire: MutexAcquire<'a, T>,
+    f: Option<F>,
+}
+
+impl<'a, T: ?Sized + 'a, F, R> Future for MutexLockAndThen<'a, T, F>
+where
+    F: FnOnce(MutexGuard<'a, T>) -> R,
+{
+    type Output = LockResult<R>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The acquisition is pinned along with `self`; `f` is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = unsafe { Pin::new_unchecked(&mut this.acquire) };
+        match acquire.poll(cx) {
+            Poll::Ready(result) => {
+                let f = this.f.take().expect("MutexLockAndThen polled after completion");
+                Poll::Ready(poison::map_result(result, f))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a, F, R> FusedFuture for MutexLockAndThen<'a, T, F>
+where
+    F: FnOnce(MutexGuard<'a, T>) -> R,
+{
+    fn is_terminated(&self) -> bool {
+        self.f.is_none()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a, F> fmt::Debug for MutexLockAndThen<'a, T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexLockAndThen")
+            .field("acquire", &self.acquire)
+            .finish()
+    }
+}
+
+/// The future returned by `Mutex::lock_iter`.
+pub type MutexLockIter<'a, T> = MutexLockAndThen<'a, T, fn(MutexGuard<'a, T>) -> MutexIter<'a, T>>;
+
+/// Iterates over copies of the items in the data of a locked mutex.
+///
+/// Created by `Mutex::lock_iter`.
+pub struct MutexIter<'a, T: ?Sized + 'a>
+where
+    &'a T: IntoIterator,
+{
+    // Borrows the data locked by `guard`, so it must be dropped first.
+    iter: <&'a T as IntoIterator>::IntoIter,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> MutexIter<'a, T>
+where
+    &'a T: IntoIterator,
+{
+    fn new(guard: MutexGuard<'a, T>) -> Self {
+        // The data stays locked, and only shared, as long as `guard` lives.
+        let data = unsafe { &*guard.data() };
+        Self {
+            iter: data.into_iter(),
+            guard,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a, U: Clone + 'a> Iterator for MutexIter<'a, T>
+where
+    &'a T: IntoIterator<Item = &'a U>,
+{
+    type Item = U;
+    fn next(&mut self) -> Option<U> {
+        self.iter.next().cloned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MutexIter<'a, T>
+where
+    &'a T: IntoIterator,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexIter")
+            .field("guard", &self.guard)
+            .finish()
+    }
+}
+
+pub struct OwnedMutexGuard<T: ?Sized> {
+    mutex: Rc<Mutex<T>>,
+    panic: PanicWatch,
+}
+
+impl<T: ?Sized> OwnedMutexGuard<T> {
+    fn new(mutex: Rc<Mutex<T>>, guard: MutexGuard<'_, T>) -> Self {
+        Self {
+            mutex,
+            panic: MutexGuard::forget(guard),
+        }
+    }
+
+    pub fn mutex(this: &Self) -> &Rc<Mutex<T>> {
+        &this.mutex
+    }
+}
+
+impl<T: ?Sized> ClearPoison for OwnedMutexGuard<T> {
+    fn clear_poison(guard: &Self) {
+        guard.mutex.clear_poison();
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedMutexGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedMutexGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexGuard<T> {
+    fn drop(&mut self) {
+        self.mutex.raw.release(&self.panic);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("OwnedMutexGuard");
+        d.field("data", &(self as &T));
+        self.mutex.raw.acquired_at.fmt_field(&mut d);
+        d.finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedMutexGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+/// The future returned by `Mutex::lock_owned`.
+///
+/// Like `MutexAcquire`, it starts over if polled after completion.
+#[derive(Debug)]
+pub struct OwnedMutexAcquire<T: ?Sized> {
+    mutex: Rc<Mutex<T>>,
+    waiter: Waiter,
+    caller: Caller,
+    terminated: bool,
+}
+
+impl<T: ?Sized> OwnedMutexAcquire<T> {
+    pub fn mutex(&self) -> &Rc<Mutex<T>> {
+        &self.mutex
+    }
+
+    /// Like `MutexAcquire::queue_position`.
+    pub fn queue_position(&self) -> Option<usize> {
+        self.mutex.raw.waiters.position(&self.waiter)
+    }
+}
+
+impl<T: ?Sized> Future for OwnedMutexAcquire<T> {
+    type Output = LockResult<OwnedMutexGuard<T>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The waiter is pinned along with `self`; the flag is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let mutex = &this.mutex;
+        let waiter = unsafe { Pin::new_unchecked(&this.waiter) };
+        let result = match mutex.poll_acquire(waiter, cx, this.caller) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                this.terminated = false;
+                return Poll::Pending;
+            }
+        };
+        let result = poison::map_result(result, |guard| {
+            OwnedMutexGuard::new(mutex.clone(), guard)
+        });
+        this.terminated = true;
+        Poll::Ready(result)
+    }
+}
+
+impl<T: ?Sized> FusedFuture for OwnedMutexAcquire<T> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedMutexAcquire<T> {
+    fn drop(&mut self) {
+        self.mutex.raw.abandon(&self.waiter);
+    }
+}
+
+/// Poisons the mutex when dropped armed; see `Mutex::scope`.
+struct PoisonOnDrop<'a> {
+    raw: &'a RawMutex,
+    armed: bool,
+}
+
+impl<'a> Drop for PoisonOnDrop<'a> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.raw.poison();
+        }
+    }
+}
+
+/// A queue position for `Mutex::poll_lock_with`.
+///
+/// The token must be pinned while it is in use.
+pub struct WaitToken<'a> {
+    raw: &'a RawMutex,
+    waiter: Waiter,
+}
+
+impl<'a> Drop for WaitToken<'a> {
+    fn drop(&mut self) {
+        self.raw.abandon(&self.waiter);
+    }
+}
+
+impl<'a> fmt::Debug for WaitToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("WaitToken { .. }")
+    }
+}
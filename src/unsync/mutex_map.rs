@@ -0,0 +1,202 @@
+use std::cell::{RefCell, RefMut};
+use std::collections::hash_map::{HashMap, RandomState};
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::LockResult;
+use std::task::{Context, Poll};
+
+use super::mutex::{Mutex, OwnedMutexAcquire, OwnedMutexGuard};
+use crate::poison;
+
+/// A map that locks each key separately.
+///
+/// The mutex of a key is created on the first `lock` of the key. An entry
+/// lives as long as some task holds or waits for its lock; once the last
+/// one is done, the entry is removed along with its value, and the next
+/// `lock` starts over from a new value. Poisoning goes with it.
+pub struct MutexMap<K, V, S = RandomState> {
+    entries: RefCell<HashMap<K, Rc<Mutex<V>>, S>>,
+}
+
+impl<K, V> MutexMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S> MutexMap<K, V, S> {
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            entries: RefCell::new(HashMap::with_hasher(hasher)),
+        }
+    }
+
+    /// Counts the keys that are locked or waited for.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entries(&self) -> RefMut<'_, HashMap<K, Rc<Mutex<V>>, S>> {
+        self.entries.borrow_mut()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> MutexMap<K, V, S> {
+    /// Acquires the lock of `key`, starting from `V::default()`
+    /// if the key has no entry.
+    pub fn lock(&self, key: K) -> MutexMapAcquire<'_, K, V, S>
+    where
+        V: Default,
+    {
+        self.lock_with(key, V::default)
+    }
+
+    /// Like `lock`, but starts from `init()` if the key has no entry.
+    pub fn lock_with(&self, key: K, init: impl FnOnce() -> V) -> MutexMapAcquire<'_, K, V, S> {
+        let existing = self.entries().get(&key).cloned();
+        // `init` runs without the map borrowed, in case it uses the map.
+        let mutex = existing.unwrap_or_else(|| {
+            let mutex = Rc::new(Mutex::new(init()));
+            self.entries().entry(key.clone()).or_insert(mutex).clone()
+        });
+        MutexMapAcquire {
+            map: self,
+            key: Some(key),
+            acquire: Some(mutex.lock_owned()),
+        }
+    }
+
+    /// Removes the entry of `key` unless someone else is using it.
+    fn release(&self, key: &K) {
+        let mut entries = self.entries();
+        let idle = entries
+            .get(key)
+            .is_some_and(|mutex| Rc::strong_count(mutex) == 1);
+        let removed = if idle { entries.remove(key) } else { None };
+        // The value is dropped without the map borrowed.
+        drop(entries);
+        drop(removed);
+    }
+}
+
+impl<K, V> Default for MutexMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: fmt::Debug, V, S> fmt::Debug for MutexMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.entries();
+        f.debug_struct("MutexMap")
+            .field("keys", &entries.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+pub struct MutexMapGuard<'a, K: Eq + Hash + Clone, V, S: BuildHasher = RandomState> {
+    map: &'a MutexMap<K, V, S>,
+    key: K,
+    guard: ManuallyDrop<OwnedMutexGuard<V>>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> MutexMapGuard<'a, K, V, S> {
+    pub fn key(this: &Self) -> &K {
+        &this.key
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> Deref for MutexMapGuard<'a, K, V, S> {
+    type Target = V;
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> DerefMut for MutexMapGuard<'a, K, V, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> Drop for MutexMapGuard<'a, K, V, S> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        self.map.release(&self.key);
+    }
+}
+
+impl<'a, K, V, S> fmt::Debug for MutexMapGuard<'a, K, V, S>
+where
+    K: Eq + Hash + Clone + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexMapGuard")
+            .field("key", &self.key)
+            .field("data", &*self.guard)
+            .finish()
+    }
+}
+
+pub struct MutexMapAcquire<'a, K: Eq + Hash + Clone, V, S: BuildHasher = RandomState> {
+    map: &'a MutexMap<K, V, S>,
+    /// `None` once the lock has been acquired.
+    key: Option<K>,
+    acquire: Option<OwnedMutexAcquire<V>>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> Future for MutexMapAcquire<'a, K, V, S> {
+    type Output = LockResult<MutexMapGuard<'a, K, V, S>>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The acquisition is pinned along with `self`; the key is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let acquire = this
+            .acquire
+            .as_mut()
+            .expect("MutexMapAcquire polled after completion");
+        let result = match unsafe { Pin::new_unchecked(acquire) }.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.acquire = None;
+        let key = this.key.take().unwrap();
+        let map = this.map;
+        Poll::Ready(poison::map_result(result, |guard| MutexMapGuard {
+            map,
+            key,
+            guard: ManuallyDrop::new(guard),
+        }))
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> Drop for MutexMapAcquire<'a, K, V, S> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.acquire = None;
+            self.map.release(&key);
+        }
+    }
+}
+
+impl<'a, K, V, S> fmt::Debug for MutexMapAcquire<'a, K, V, S>
+where
+    K: Eq + Hash + Clone + fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexMapAcquire")
+            .field("key", &self.key)
+            .finish()
+    }
+}
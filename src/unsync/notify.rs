@@ -0,0 +1,127 @@
+use core::cell::Cell;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::waiter::WaitQueue;
+
+/// Wakes up waiting tasks, without protecting any data.
+///
+/// A `notify_one` with nobody waiting is stored as a permit, which
+/// completes the next `notified` future immediately. At most one permit
+/// is stored at a time.
+pub struct Notify {
+    permit: Cell<bool>,
+    generation: Cell<usize>,
+    waiters: Cell<WaitQueue>,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Self {
+            permit: Cell::new(false),
+            generation: Cell::new(0),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            generation: self.generation.get(),
+            key: None,
+        }
+    }
+
+    /// Wakes the first waiting task, or stores a permit if there is none.
+    pub fn notify_one(&self) {
+        match self.with_waiters(|waiters| waiters.notify_one()) {
+            Some(waker) => waker.wake(),
+            None => self.permit.set(true),
+        }
+    }
+
+    /// Wakes all `Notified` futures created before this call.
+    ///
+    /// Unlike `notify_one`, this does not store a permit.
+    pub fn notify_waiters(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+        for waker in self.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn poll_notified(
+        &self,
+        generation: usize,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<()> {
+        let notified = match *key {
+            Some(key) => self.with_waiters(|waiters| waiters.take_notified(key)),
+            None => self.permit.replace(false),
+        };
+        if notified {
+            *key = None;
+            return Poll::Ready(());
+        }
+        if generation != self.generation.get() {
+            self.abandon(key);
+            return Poll::Ready(());
+        }
+        self.with_waiters(|waiters| waiters.register(key, cx.waker()));
+        Poll::Pending
+    }
+
+    fn abandon(&self, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            if self.with_waiters(|waiters| waiters.remove(key)) {
+                // We have consumed a notification; pass it on.
+                self.notify_one();
+            }
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Notify::new()
+    }
+}
+
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Notify")
+            .field("permit", &self.permit.get())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    generation: usize,
+    key: Option<usize>,
+}
+
+impl<'a> Future for Notified<'a> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.notify.poll_notified(this.generation, &mut this.key, cx)
+    }
+}
+
+impl<'a> Drop for Notified<'a> {
+    fn drop(&mut self) {
+        self.notify.abandon(&mut self.key);
+    }
+}
@@ -0,0 +1,152 @@
+use core::cell::Cell;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::poison;
+use crate::waiter::WaitQueue;
+
+/// Runs an asynchronous initialization once.
+///
+/// Unlike `OnceCell`, this is for initializations run for their side
+/// effects, such as opening a connection or spawning a background task.
+pub struct Once {
+    state: Cell<State>,
+    waiters: Cell<WaitQueue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Incomplete,
+    Running,
+    Complete,
+    Poisoned,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: Cell::new(State::Incomplete),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.get() == State::Complete
+    }
+
+    /// Whether an initializer panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.state.get() == State::Poisoned
+    }
+
+    /// Runs `f` unless an earlier call has completed.
+    ///
+    /// Only one initializer runs at a time; other callers wait for it to
+    /// finish. If the running initializer is cancelled, one of the waiting
+    /// callers runs its own initializer instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an initializer has panicked, in this call or an earlier one.
+    pub async fn call_once<F, Fut>(&self, f: F)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        loop {
+            match self.state.get() {
+                State::Complete => return,
+                State::Poisoned => panic!("Once instance has previously been poisoned"),
+                State::Incomplete => break,
+                State::Running => {}
+            }
+            OnceWait {
+                once: self,
+                key: None,
+            }
+            .await;
+        }
+
+        self.state.set(State::Running);
+        let guard = InitGuard { once: self };
+        f().await;
+        self.state.set(State::Complete);
+        drop(guard);
+    }
+
+    fn wake_all(&self) {
+        for waker in self.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Once::new()
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("state", &self.state.get())
+            .finish()
+    }
+}
+
+struct InitGuard<'a> {
+    once: &'a Once,
+}
+
+impl<'a> Drop for InitGuard<'a> {
+    fn drop(&mut self) {
+        // Unless the initializer has completed, it either panicked or was
+        // cancelled, in which case someone else has to take over.
+        let state = match self.once.state.get() {
+            State::Running if poison::panicking() => State::Poisoned,
+            State::Running => State::Incomplete,
+            state => state,
+        };
+        self.once.state.set(state);
+        self.once.wake_all();
+    }
+}
+
+struct OnceWait<'a> {
+    once: &'a Once,
+    key: Option<usize>,
+}
+
+impl<'a> Future for OnceWait<'a> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.once.state.get() == State::Running {
+            this.once
+                .with_waiters(|waiters| waiters.register(&mut this.key, cx.waker()));
+            return Poll::Pending;
+        }
+        if let Some(key) = this.key.take() {
+            this.once.with_waiters(|waiters| waiters.remove(key));
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<'a> Drop for OnceWait<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.once.with_waiters(|waiters| waiters.remove(key));
+        }
+    }
+}
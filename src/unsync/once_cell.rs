@@ -0,0 +1,160 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::waiter::WaitQueue;
+
+pub struct OnceCell<T> {
+    value: UnsafeCell<Option<T>>,
+    initializing: Cell<bool>,
+    waiters: Cell<WaitQueue>,
+}
+
+impl<T> OnceCell<T> {
+    pub fn new() -> Self {
+        Self {
+            value: UnsafeCell::new(None),
+            initializing: Cell::new(false),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        unsafe { &*self.value.get() }.as_ref()
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        unsafe { &mut *self.value.get() }.as_mut()
+    }
+
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self.initializing.get() || self.get().is_some() {
+            return Err(value);
+        }
+        unsafe {
+            *self.value.get() = Some(value);
+        }
+        self.wake_all();
+        Ok(())
+    }
+
+    /// Gets the contents of the cell, initializing it with `f` if needed.
+    ///
+    /// Only one initializer runs at a time; other callers wait for it to
+    /// finish. If the running initializer is cancelled, one of the waiting
+    /// callers runs its own initializer instead.
+    pub async fn get_or_init<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            if !self.initializing.get() {
+                break;
+            }
+            OnceCellWait {
+                cell: self,
+                key: None,
+            }
+            .await;
+        }
+
+        self.initializing.set(true);
+        let guard = InitGuard { cell: self };
+        let value = f().await;
+        unsafe {
+            *self.value.get() = Some(value);
+        }
+        drop(guard);
+        self.get().unwrap()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    fn wake_all(&self) {
+        for waker in self.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        OnceCell::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    fn from(x: T) -> Self {
+        Self {
+            value: UnsafeCell::new(Some(x)),
+            initializing: Cell::new(false),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+struct InitGuard<'a, T> {
+    cell: &'a OnceCell<T>,
+}
+
+impl<'a, T> Drop for InitGuard<'a, T> {
+    fn drop(&mut self) {
+        // Either the cell is now set, or the initializer was cancelled
+        // and someone else has to take over.
+        self.cell.initializing.set(false);
+        self.cell.wake_all();
+    }
+}
+
+struct OnceCellWait<'a, T> {
+    cell: &'a OnceCell<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T> Future for OnceCellWait<'a, T> {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.cell.initializing.get() {
+            this.cell
+                .with_waiters(|waiters| waiters.register(&mut this.key, cx.waker()));
+            return Poll::Pending;
+        }
+        if let Some(key) = this.key.take() {
+            this.cell.with_waiters(|waiters| waiters.remove(key));
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<'a, T> Drop for OnceCellWait<'a, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.cell.with_waiters(|waiters| waiters.remove(key));
+        }
+    }
+}
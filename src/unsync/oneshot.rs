@@ -0,0 +1,119 @@
+//! A channel for sending a single value.
+
+use alloc::rc::Rc;
+use core::cell::Cell;
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        value: Cell::new(None),
+        sender_gone: Cell::new(false),
+        receiver_gone: Cell::new(false),
+        waker: Cell::new(None),
+    });
+    let sender = Sender {
+        shared: shared.clone(),
+    };
+    (sender, Receiver { shared })
+}
+
+struct Shared<T> {
+    value: Cell<Option<T>>,
+    sender_gone: Cell<bool>,
+    receiver_gone: Cell<bool>,
+    waker: Cell<Option<Waker>>,
+}
+
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Completes the channel with `value`.
+    ///
+    /// Returns the value back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        if self.shared.receiver_gone.get() {
+            return Err(value);
+        }
+        self.shared.value.set(Some(value));
+        Ok(())
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.shared.receiver_gone.get()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_gone.set(true);
+        if let Some(waker) = self.shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Sender { .. }")
+    }
+}
+
+/// Resolves to the sent value, or to `Canceled` if the sender is dropped
+/// without sending.
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<Option<T>, Canceled> {
+        match self.shared.value.take() {
+            Some(value) => Ok(Some(value)),
+            None if self.shared.sender_gone.get() => Err(Canceled(())),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, Canceled>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.try_recv() {
+            Ok(Some(value)) => Poll::Ready(Ok(value)),
+            Ok(None) => {
+                self.shared.waker.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_gone.set(true);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Receiver { .. }")
+    }
+}
+
+/// The sender was dropped without sending a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Canceled(());
+
+impl fmt::Display for Canceled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("oneshot canceled")
+    }
+}
+
+impl Error for Canceled {}
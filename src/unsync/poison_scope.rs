@@ -0,0 +1,112 @@
+#[cfg(feature = "std")]
+mod imp {
+    use std::cell::{Cell, RefCell};
+    use std::future::Future;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll};
+    use std::thread;
+
+    thread_local! {
+        /// The flag of the `PoisonScope` being polled, if any.
+        static CURRENT: RefCell<Option<Rc<Cell<bool>>>> = const { RefCell::new(None) };
+    }
+
+    /// Runs `future` as a task of its own for the purpose of poisoning.
+    ///
+    /// Outside a scope, a `Mutex` is poisoned if its guard is dropped while the
+    /// thread is panicking. That misses guards dropped after the executor has
+    /// caught the panic, and catches guards of other tasks dropped while some
+    /// unrelated task unwinds. Guards created inside a scope instead poison the
+    /// mutex exactly if the scope's future panicked, whenever they are dropped.
+    pub fn poison_scope<F: Future>(future: F) -> PoisonScope<F> {
+        PoisonScope {
+            inner: future,
+            panicked: Rc::new(Cell::new(false)),
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct PoisonScope<F> {
+        inner: F,
+        panicked: Rc<Cell<bool>>,
+    }
+
+    impl<F: Future> Future for PoisonScope<F> {
+        type Output = F::Output;
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            let panicked = this.panicked.clone();
+            let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+            let outer = CURRENT.with(|current| current.replace(Some(panicked)));
+            let result = panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx)));
+            CURRENT.with(|current| current.replace(outer));
+            match result {
+                Ok(poll) => poll,
+                Err(payload) => {
+                    this.panicked.set(true);
+                    panic::resume_unwind(payload)
+                }
+            }
+        }
+    }
+
+    /// Tells whether the task holding a guard panicked.
+    #[derive(Debug, Clone)]
+    pub(crate) struct PanicWatch {
+        was_panicking: bool,
+        scope: Option<Rc<Cell<bool>>>,
+    }
+
+    impl PanicWatch {
+        pub(crate) fn new() -> Self {
+            Self {
+                was_panicking: thread::panicking(),
+                scope: CURRENT.with(|current| current.borrow().clone()),
+            }
+        }
+
+        pub(crate) fn panicked(&self) -> bool {
+            let unwinding = !self.was_panicking && thread::panicking();
+            match &self.scope {
+                None => unwinding,
+                // A panic of the thread counts only while the scope is polled,
+                // i.e. it is the scope that is unwinding.
+                Some(scope) => scope.get() || (unwinding && is_current(scope)),
+            }
+        }
+    }
+
+    fn is_current(scope: &Rc<Cell<bool>>) -> bool {
+        CURRENT.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .is_some_and(|current| Rc::ptr_eq(current, scope))
+        })
+    }
+}
+
+/// Without `std` a panic cannot be detected, so guards never poison.
+#[cfg(not(feature = "std"))]
+mod imp {
+    #[derive(Debug, Clone)]
+    pub(crate) struct PanicWatch;
+
+    impl PanicWatch {
+        #[inline]
+        pub(crate) fn new() -> Self {
+            PanicWatch
+        }
+
+        #[inline]
+        pub(crate) fn panicked(&self) -> bool {
+            false
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::imp::{poison_scope, PoisonScope};
+pub(super) use self::imp::PanicWatch;
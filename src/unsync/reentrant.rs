@@ -0,0 +1,193 @@
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::future::Future;
+use core::ops::Deref;
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll};
+
+use super::list::Waiter;
+use super::mutex::RawMutex;
+use crate::wake_policy::WakePolicy;
+
+/// Identifies the owner of a `ReentrantMutex`.
+///
+/// Tasks have no identity of their own, so a task that wants to lock a
+/// mutex recursively creates a token once and passes it to every `lock`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OwnerId(usize);
+
+impl OwnerId {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        OwnerId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for OwnerId {
+    fn default() -> Self {
+        OwnerId::new()
+    }
+}
+
+pub struct ReentrantMutex<T: ?Sized> {
+    raw: RawMutex,
+    owner: Cell<Option<OwnerId>>,
+    count: Cell<usize>,
+    data: UnsafeCell<T>,
+}
+
+impl<T: ?Sized> UnwindSafe for ReentrantMutex<T> {}
+impl<T: ?Sized> RefUnwindSafe for ReentrantMutex<T> {}
+
+impl<T> ReentrantMutex<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            raw: RawMutex::new(WakePolicy::Handoff, 0, usize::MAX, None),
+            owner: Cell::new(None),
+            count: Cell::new(0),
+            data: UnsafeCell::new(inner),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> ReentrantMutex<T> {
+    pub fn lock(&self, owner: &OwnerId) -> ReentrantMutexAcquire<'_, T> {
+        ReentrantMutexAcquire {
+            mutex: self,
+            owner: *owner,
+            waiter: Waiter::new(),
+        }
+    }
+
+    pub fn try_lock(&self, owner: &OwnerId) -> Option<ReentrantMutexGuard<'_, T>> {
+        if self.owner.get() != Some(*owner) && !self.raw.try_acquire_untasked() {
+            return None;
+        }
+        Some(ReentrantMutexGuard::new(self, *owner))
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn poll_acquire(
+        &self,
+        owner: OwnerId,
+        waiter: Pin<&Waiter>,
+        cx: &mut Context<'_>,
+    ) -> Poll<ReentrantMutexGuard<'_, T>> {
+        if self.owner.get() == Some(owner) {
+            self.raw.abandon(&waiter);
+            return Poll::Ready(ReentrantMutexGuard::new(self, owner));
+        }
+        match self.raw.poll_acquire(waiter, cx) {
+            Poll::Ready(()) => Poll::Ready(ReentrantMutexGuard::new(self, owner)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> From<T> for ReentrantMutex<T> {
+    fn from(x: T) -> Self {
+        ReentrantMutex::new(x)
+    }
+}
+
+impl<T: Default> Default for ReentrantMutex<T> {
+    fn default() -> Self {
+        ReentrantMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ReentrantMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.raw.try_acquire() {
+            let guard = ReentrantMutexGuard::new(self, OwnerId::new());
+            f.debug_struct("ReentrantMutex")
+                .field("data", &(&guard as &T))
+                .finish()
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            f.debug_struct("ReentrantMutex")
+                .field("data", &LockedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+pub struct ReentrantMutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a ReentrantMutex<T>,
+}
+
+impl<'a, T: ?Sized + 'a> ReentrantMutexGuard<'a, T> {
+    fn new(mutex: &'a ReentrantMutex<T>, owner: OwnerId) -> Self {
+        mutex.owner.set(Some(owner));
+        mutex.count.set(mutex.count.get() + 1);
+        Self { mutex }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for ReentrantMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for ReentrantMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        let count = self.mutex.count.get() - 1;
+        self.mutex.count.set(count);
+        if count == 0 {
+            self.mutex.owner.set(None);
+            self.mutex.raw.unlock();
+        }
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for ReentrantMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReentrantMutexGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for ReentrantMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReentrantMutexAcquire<'a, T: ?Sized + 'a> {
+    mutex: &'a ReentrantMutex<T>,
+    owner: OwnerId,
+    waiter: Waiter,
+}
+
+impl<'a, T: ?Sized + 'a> Future for ReentrantMutexAcquire<'a, T> {
+    type Output = ReentrantMutexGuard<'a, T>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.into_ref().get_ref();
+        let waiter = unsafe { Pin::new_unchecked(&this.waiter) };
+        this.mutex.poll_acquire(this.owner, waiter, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for ReentrantMutexAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.raw.abandon(&self.waiter);
+    }
+}
@@ -0,0 +1,1112 @@
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::panic::{RefUnwindSafe, UnwindSafe};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::poison::{self, LockResult, PoisonError, TryLockError, TryLockResult};
+use crate::waiter::WaitQueue;
+
+pub struct RwLock<T: ?Sized> {
+    raw: RawRwLock,
+    data: UnsafeCell<T>,
+}
+
+/// The state of a `RwLock`, apart from the data it protects.
+struct RawRwLock {
+    readers: Cell<usize>,
+    writer: Cell<bool>,
+    upgradable: Cell<bool>,
+    poisoned: Cell<bool>,
+    /// One queue per `Access`, so that releasing the lock only wakes
+    /// the waiters that can make progress.
+    waiters: [Cell<WaitQueue>; 4],
+    /// Queued writers and upgrades, which block new readers
+    /// if writers are preferred.
+    waiting_writers: Cell<usize>,
+    prefer_writers: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Upgradable,
+    Write,
+    Upgrade,
+}
+
+impl Access {
+    /// Whether waiting for this access holds back new readers
+    /// if writers are preferred.
+    fn is_exclusive(self) -> bool {
+        match self {
+            Access::Read | Access::Upgradable => false,
+            Access::Write | Access::Upgrade => true,
+        }
+    }
+}
+
+impl<T: ?Sized> UnwindSafe for RwLock<T> {}
+impl<T: ?Sized> RefUnwindSafe for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub fn new(inner: T) -> Self {
+        RwLockBuilder::new().build(inner)
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { raw, data } = self;
+        let poisoned = raw.poisoned.into_inner();
+        let inner = data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+}
+
+/// Configures a `RwLock` before creating it.
+#[derive(Debug, Clone, Copy)]
+pub struct RwLockBuilder {
+    prefer_writers: bool,
+}
+
+impl RwLockBuilder {
+    pub const fn new() -> Self {
+        Self {
+            prefer_writers: true,
+        }
+    }
+
+    /// Sets whether a queued writer blocks new readers.
+    ///
+    /// Preferring writers keeps a steady stream of readers from starving
+    /// writers, but a task that takes a second read lock while holding one
+    /// deadlocks if a writer queues up in between, unless it uses
+    /// `RwLock::read_recursive`. The default is to prefer writers.
+    pub const fn prefer_writers(self, prefer_writers: bool) -> Self {
+        Self { prefer_writers }
+    }
+
+    pub const fn build<T>(self, inner: T) -> RwLock<T> {
+        RwLock {
+            raw: RawRwLock {
+                readers: Cell::new(0),
+                writer: Cell::new(false),
+                upgradable: Cell::new(false),
+                poisoned: Cell::new(false),
+                waiters: [
+                    Cell::new(WaitQueue::new()),
+                    Cell::new(WaitQueue::new()),
+                    Cell::new(WaitQueue::new()),
+                    Cell::new(WaitQueue::new()),
+                ],
+                waiting_writers: Cell::new(0),
+                prefer_writers: self.prefer_writers,
+            },
+            data: UnsafeCell::new(inner),
+        }
+    }
+}
+
+impl Default for RwLockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> RwLockReadAcquire<'_, T> {
+        RwLockReadAcquire {
+            rwlock: self,
+            key: None,
+            recursive: false,
+        }
+    }
+    /// Like `read`, but gets past queued writers while the lock is
+    /// already shared, so that a task holding a read guard can take
+    /// another one even if writers are preferred.
+    ///
+    /// Tasks have no identity, so this lets in any reader while some
+    /// task holds the lock for reading. Use it only where the task
+    /// already holds a read guard, or writers may starve.
+    pub fn read_recursive(&self) -> RwLockReadAcquire<'_, T> {
+        RwLockReadAcquire {
+            rwlock: self,
+            key: None,
+            recursive: true,
+        }
+    }
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<LockResult<RwLockReadGuard<'_, T>>> {
+        if self.raw.readers_blocked() {
+            self.raw.with_waiters(Access::Read, |waiters| {
+                waiters.push_waker(cx.waker())
+            });
+            return Poll::Pending;
+        }
+
+        Poll::Ready(self.read_guard())
+    }
+
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if self.raw.readers_blocked() {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = RwLockReadGuard::new(self);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard).into())
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Like `try_read`, but gets past queued writers as `read_recursive` does.
+    pub fn try_read_recursive(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if self.raw.recursive_readers_blocked() {
+            return Err(TryLockError::WouldBlock);
+        }
+        self.read_guard().map_err(TryLockError::from)
+    }
+
+    /// Acquires shared access that can later be upgraded to exclusive access.
+    ///
+    /// An upgradable reader coexists with plain readers, but excludes
+    /// writers and other upgradable readers.
+    pub fn upgradable_read(&self) -> RwLockUpgradableReadAcquire<'_, T> {
+        RwLockUpgradableReadAcquire {
+            rwlock: self,
+            key: None,
+        }
+    }
+
+    pub fn try_upgradable_read(&self) -> TryLockResult<RwLockUpgradableReadGuard<'_, T>> {
+        if self.raw.readers_blocked() || self.raw.upgradable.get() {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = RwLockUpgradableReadGuard::new(self);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard).into())
+        } else {
+            Ok(guard)
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteAcquire<'_, T> {
+        RwLockWriteAcquire {
+            rwlock: self,
+            key: None,
+        }
+    }
+    pub fn poll_write(&self, cx: &mut Context<'_>) -> Poll<LockResult<RwLockWriteGuard<'_, T>>> {
+        if self.raw.writer.get() || self.raw.upgradable.get() || self.raw.readers.get() > 0 {
+            self.raw.with_waiters(Access::Write, |waiters| {
+                waiters.push_waker(cx.waker())
+            });
+            return Poll::Pending;
+        }
+
+        Poll::Ready(self.write_guard())
+    }
+
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        if self.raw.writer.get() || self.raw.upgradable.get() || self.raw.readers.get() > 0 {
+            return Err(TryLockError::WouldBlock);
+        }
+
+        let guard = RwLockWriteGuard::new(self);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard).into())
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Acquires shared access through an `Rc`, so that the guard has no
+    /// lifetime and can be stored away.
+    pub fn read_owned(self: &Rc<Self>) -> OwnedRwLockReadAcquire<T> {
+        OwnedRwLockReadAcquire {
+            rwlock: self.clone(),
+            key: None,
+        }
+    }
+
+    pub fn try_read_owned(self: &Rc<Self>) -> TryLockResult<OwnedRwLockReadGuard<T>> {
+        poison::map_try_result(self.try_read(), |guard| {
+            OwnedRwLockReadGuard::new(self.clone(), guard)
+        })
+    }
+
+    /// Acquires exclusive access through an `Rc`, like `read_owned`.
+    pub fn write_owned(self: &Rc<Self>) -> OwnedRwLockWriteAcquire<T> {
+        OwnedRwLockWriteAcquire {
+            rwlock: self.clone(),
+            key: None,
+        }
+    }
+
+    pub fn try_write_owned(self: &Rc<Self>) -> TryLockResult<OwnedRwLockWriteGuard<T>> {
+        poison::map_try_result(self.try_write(), |guard| {
+            OwnedRwLockWriteGuard::new(self.clone(), guard)
+        })
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.poisoned.get()
+    }
+
+    pub fn clear_poison(&self) {
+        self.raw.poisoned.set(false);
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let inner = unsafe { &mut *self.data.get() };
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(inner))
+        } else {
+            Ok(inner)
+        }
+    }
+
+    fn poll_acquire_read(
+        &self,
+        key: &mut Option<usize>,
+        recursive: bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockReadGuard<'_, T>>> {
+        let blocked = if recursive {
+            self.raw.recursive_readers_blocked()
+        } else {
+            self.raw.readers_blocked()
+        };
+        if blocked {
+            self.raw.register(Access::Read, key, cx);
+            return Poll::Pending;
+        }
+        self.raw.abandon(Access::Read, key);
+
+        Poll::Ready(self.read_guard())
+    }
+
+    fn poll_acquire_write(
+        &self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockWriteGuard<'_, T>>> {
+        if self.raw.writer.get() || self.raw.upgradable.get() || self.raw.readers.get() > 0 {
+            self.raw.register(Access::Write, key, cx);
+            return Poll::Pending;
+        }
+        self.raw.abandon(Access::Write, key);
+
+        Poll::Ready(self.write_guard())
+    }
+
+    fn poll_acquire_upgradable(
+        &self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<LockResult<RwLockUpgradableReadGuard<'_, T>>> {
+        if self.raw.readers_blocked() || self.raw.upgradable.get() {
+            self.raw.register(Access::Upgradable, key, cx);
+            return Poll::Pending;
+        }
+        self.raw.abandon(Access::Upgradable, key);
+
+        let guard = RwLockUpgradableReadGuard::new(self);
+        if self.raw.poisoned.get() {
+            Poll::Ready(Err(PoisonError::new(guard)))
+        } else {
+            Poll::Ready(Ok(guard))
+        }
+    }
+
+    /// Waits for the remaining readers to leave.
+    /// Only the holder of the upgradable read may call this.
+    fn poll_upgrade(&self, key: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.raw.readers.get() > 0 {
+            self.raw.register(Access::Upgrade, key, cx);
+            return Poll::Pending;
+        }
+        self.raw.abandon(Access::Upgrade, key);
+
+        Poll::Ready(())
+    }
+
+    fn read_guard(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let guard = RwLockReadGuard::new(self);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    fn write_guard(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let guard = RwLockWriteGuard::new(self);
+        if self.raw.poisoned.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+}
+
+impl RawRwLock {
+    fn readers_blocked(&self) -> bool {
+        self.writer.get() || (self.prefer_writers && self.waiting_writers.get() > 0)
+    }
+
+    /// Queued writers only block recursive readers while nobody else
+    /// shares the lock.
+    fn recursive_readers_blocked(&self) -> bool {
+        let shared = self.readers.get() > 0 || self.upgradable.get();
+        self.writer.get() || (!shared && self.readers_blocked())
+    }
+
+    fn register(&self, access: Access, key: &mut Option<usize>, cx: &mut Context<'_>) {
+        if key.is_none() && access.is_exclusive() {
+            self.waiting_writers.set(self.waiting_writers.get() + 1);
+        }
+        self.with_waiters(access, |waiters| waiters.register(key, cx.waker()));
+    }
+
+    fn abandon(&self, access: Access, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            self.with_waiters(access, |waiters| waiters.remove(key));
+            if access.is_exclusive() {
+                self.waiting_writers.set(self.waiting_writers.get() - 1);
+            }
+        }
+    }
+
+    /// Withdraws a waiter that gives up waiting.
+    fn cancel(&self, access: Access, key: &mut Option<usize>) {
+        if key.is_some() {
+            self.abandon(access, key);
+            // Only plain readers are all woken at once; anyone else may
+            // have been the single waiter woken, or may be holding back
+            // the readers.
+            if access != Access::Read {
+                self.wake_ready();
+            }
+        }
+    }
+
+    /// Wakes the waiters that can make progress in the current state:
+    /// the first writer or upgrade, or all readers and the first
+    /// upgradable reader.
+    fn wake_ready(&self) {
+        let mut wakers = Vec::new();
+        let readers = self.readers.get();
+        if self.upgradable.get() && readers == 0 {
+            wakers.extend(self.with_waiters(Access::Upgrade, |waiters| waiters.first_waker()));
+        }
+        if !self.writer.get() && !self.upgradable.get() && readers == 0 {
+            self.with_waiters(Access::Write, |waiters| {
+                wakers.extend(waiters.take_wakers());
+                wakers.extend(waiters.first_waker());
+            });
+        }
+        if !self.readers_blocked() {
+            wakers.extend(self.with_waiters(Access::Read, |waiters| waiters.wake_all()));
+            if !self.upgradable.get() {
+                let first = self.with_waiters(Access::Upgradable, |waiters| waiters.first_waker());
+                wakers.extend(first);
+            }
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, access: Access, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let queue = &self.waiters[access as usize];
+        let mut waiters = queue.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        queue.replace(waiters);
+        ret
+    }
+
+    fn read_unlock(&self) {
+        let readers = self.readers.get() - 1;
+        self.readers.set(readers);
+        if readers == 0 {
+            self.wake_ready();
+        }
+    }
+
+    fn write_unlock(&self, is_panicking: bool) {
+        self.writer.set(false);
+        if !is_panicking && poison::panicking() {
+            self.poisoned.set(true);
+        }
+        self.wake_ready();
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(x: T) -> Self {
+        RwLock::new(x)
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let guard = match self.try_read() {
+            Ok(guard) => Ok(guard),
+            Err(TryLockError::Poisoned(err)) => Ok(err.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(()),
+        };
+        if let Ok(guard) = guard {
+            f.debug_struct("RwLock")
+                .field("data", &(&guard as &T))
+                .finish()
+        } else {
+            struct LockedPlaceholder;
+            impl fmt::Debug for LockedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<locked>")
+                }
+            }
+            f.debug_struct("RwLock")
+                .field("data", &LockedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+impl<'a, T: ?Sized + 'a> RwLockReadGuard<'a, T> {
+    fn new(rwlock: &'a RwLock<T>) -> Self {
+        rwlock.raw.readers.set(rwlock.raw.readers.get() + 1);
+        Self { rwlock }
+    }
+
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(unsafe { &*this.rwlock.data.get() }) as *const U;
+        let guard = MappedRwLockReadGuard {
+            raw: &this.rwlock.raw,
+            data,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(unsafe { &*this.rwlock.data.get() }) {
+            Some(data) => {
+                let data = data as *const U;
+                let guard = MappedRwLockReadGuard {
+                    raw: &this.rwlock.raw,
+                    data,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    is_panicking: bool,
+}
+
+impl<'a, T: ?Sized + 'a> RwLockWriteGuard<'a, T> {
+    fn new(rwlock: &'a RwLock<T>) -> Self {
+        rwlock.raw.writer.set(true);
+        Self {
+            rwlock,
+            is_panicking: poison::panicking(),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> RwLockWriteGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.rwlock.data.get() }) as *mut U;
+        let guard = MappedRwLockWriteGuard {
+            raw: &this.rwlock.raw,
+            data,
+            is_panicking: this.is_panicking,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *this.rwlock.data.get() }) {
+            Some(data) => {
+                let data = data as *mut U;
+                let guard = MappedRwLockWriteGuard {
+                    raw: &this.rwlock.raw,
+                    data,
+                    is_panicking: this.is_panicking,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+
+    /// Turns exclusive access into shared access without releasing the lock.
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let rwlock = this.rwlock;
+        if !this.is_panicking && poison::panicking() {
+            rwlock.raw.poisoned.set(true);
+        }
+        mem::forget(this);
+        rwlock.raw.writer.set(false);
+        let guard = RwLockReadGuard::new(rwlock);
+        // Let the other readers in.
+        rwlock.raw.wake_ready();
+        guard
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct RwLockUpgradableReadGuard<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+}
+
+impl<'a, T: ?Sized + 'a> RwLockUpgradableReadGuard<'a, T> {
+    fn new(rwlock: &'a RwLock<T>) -> Self {
+        rwlock.raw.upgradable.set(true);
+        Self { rwlock }
+    }
+
+    /// Waits for the other readers to leave, then turns the guard into a
+    /// write guard. No writer can get in between.
+    pub fn upgrade(this: Self) -> RwLockUpgrade<'a, T> {
+        RwLockUpgrade {
+            guard: Some(this),
+            key: None,
+        }
+    }
+
+    pub fn try_upgrade(this: Self) -> Result<RwLockWriteGuard<'a, T>, Self> {
+        if this.rwlock.raw.readers.get() > 0 {
+            return Err(this);
+        }
+        Ok(Self::into_write_guard(this))
+    }
+
+    pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T> {
+        let guard = RwLockReadGuard::new(this.rwlock);
+        drop(this);
+        guard
+    }
+
+    fn into_write_guard(this: Self) -> RwLockWriteGuard<'a, T> {
+        let rwlock = this.rwlock;
+        mem::forget(this);
+        rwlock.raw.upgradable.set(false);
+        RwLockWriteGuard::new(rwlock)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for RwLockUpgradableReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgradableReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.upgradable.set(false);
+        self.rwlock.raw.wake_ready();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for RwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RwLockUpgradableReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for RwLockUpgradableReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedRwLockReadGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawRwLock,
+    data: *const T,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized + 'a> MappedRwLockReadGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockReadGuard<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        let data = f(unsafe { &*this.data }) as *const U;
+        let guard = MappedRwLockReadGuard {
+            raw: this.raw,
+            data,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockReadGuard<'a, U>, Self>
+    where
+        F: FnOnce(&T) -> Option<&U>,
+    {
+        match f(unsafe { &*this.data }) {
+            Some(data) => {
+                let data = data as *const U;
+                let guard = MappedRwLockReadGuard {
+                    raw: this.raw,
+                    data,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedRwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedRwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.raw.read_unlock();
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedRwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedRwLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct MappedRwLockWriteGuard<'a, T: ?Sized + 'a> {
+    raw: &'a RawRwLock,
+    data: *mut T,
+    is_panicking: bool,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: ?Sized + 'a> MappedRwLockWriteGuard<'a, T> {
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRwLockWriteGuard<'a, U>
+    where
+        F: FnOnce(&mut T) -> &mut U,
+    {
+        let data = f(unsafe { &mut *this.data }) as *mut U;
+        let guard = MappedRwLockWriteGuard {
+            raw: this.raw,
+            data,
+            is_panicking: this.is_panicking,
+            _marker: PhantomData,
+        };
+        mem::forget(this);
+        guard
+    }
+
+    /// Like `map`, but gives the guard back if `f` returns `None`.
+    pub fn try_map<U: ?Sized, F>(this: Self, f: F) -> Result<MappedRwLockWriteGuard<'a, U>, Self>
+    where
+        F: FnOnce(&mut T) -> Option<&mut U>,
+    {
+        match f(unsafe { &mut *this.data }) {
+            Some(data) => {
+                let data = data as *mut U;
+                let guard = MappedRwLockWriteGuard {
+                    raw: this.raw,
+                    data,
+                    is_panicking: this.is_panicking,
+                    _marker: PhantomData,
+                };
+                mem::forget(this);
+                Ok(guard)
+            }
+            None => Err(this),
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Deref for MappedRwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> DerefMut for MappedRwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.data }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for MappedRwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug + 'a> fmt::Debug for MappedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MappedRwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display + 'a> fmt::Display for MappedRwLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockReadAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+    recursive: bool,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockReadAcquire<'a, T> {
+    type Output = LockResult<RwLockReadGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_read(&mut this.key, this.recursive, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockReadAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Read, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockWriteAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockWriteAcquire<'a, T> {
+    type Output = LockResult<RwLockWriteGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_write(&mut this.key, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockWriteAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Write, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockUpgradableReadAcquire<'a, T: ?Sized + 'a> {
+    rwlock: &'a RwLock<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockUpgradableReadAcquire<'a, T> {
+    type Output = LockResult<RwLockUpgradableReadGuard<'a, T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.rwlock.poll_acquire_upgradable(&mut this.key, cx)
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgradableReadAcquire<'a, T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Upgradable, &mut self.key);
+    }
+}
+
+/// Dropping the future before it completes releases the upgradable read.
+#[derive(Debug)]
+pub struct RwLockUpgrade<'a, T: ?Sized + 'a> {
+    guard: Option<RwLockUpgradableReadGuard<'a, T>>,
+    key: Option<usize>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for RwLockUpgrade<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = this
+            .guard
+            .as_ref()
+            .expect("RwLockUpgrade polled after completion")
+            .rwlock;
+        match rwlock.poll_upgrade(&mut this.key, cx) {
+            Poll::Ready(()) => {
+                let guard = this.guard.take().unwrap();
+                Poll::Ready(RwLockUpgradableReadGuard::into_write_guard(guard))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, T: ?Sized + 'a> Drop for RwLockUpgrade<'a, T> {
+    fn drop(&mut self) {
+        if let Some(guard) = &self.guard {
+            guard.rwlock.raw.cancel(Access::Upgrade, &mut self.key);
+        }
+    }
+}
+
+pub struct OwnedRwLockReadGuard<T: ?Sized> {
+    rwlock: Rc<RwLock<T>>,
+}
+
+impl<T: ?Sized> OwnedRwLockReadGuard<T> {
+    fn new(rwlock: Rc<RwLock<T>>, guard: RwLockReadGuard<'_, T>) -> Self {
+        mem::forget(guard);
+        Self { rwlock }
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedRwLockReadGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockReadGuard<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.read_unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedRwLockReadGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedRwLockReadGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+pub struct OwnedRwLockWriteGuard<T: ?Sized> {
+    rwlock: Rc<RwLock<T>>,
+    is_panicking: bool,
+}
+
+impl<T: ?Sized> OwnedRwLockWriteGuard<T> {
+    fn new(rwlock: Rc<RwLock<T>>, guard: RwLockWriteGuard<'_, T>) -> Self {
+        let is_panicking = guard.is_panicking;
+        mem::forget(guard);
+        Self {
+            rwlock,
+            is_panicking,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for OwnedRwLockWriteGuard<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for OwnedRwLockWriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.rwlock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockWriteGuard<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.write_unlock(self.is_panicking);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for OwnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedRwLockWriteGuard")
+            .field("data", &(self as &T))
+            .finish()
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for OwnedRwLockWriteGuard<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        <T as fmt::Display>::fmt(self, f)
+    }
+}
+
+#[derive(Debug)]
+pub struct OwnedRwLockReadAcquire<T: ?Sized> {
+    rwlock: Rc<RwLock<T>>,
+    key: Option<usize>,
+}
+
+impl<T: ?Sized> Future for OwnedRwLockReadAcquire<T> {
+    type Output = LockResult<OwnedRwLockReadGuard<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = &this.rwlock;
+        match rwlock.poll_acquire_read(&mut this.key, false, cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                OwnedRwLockReadGuard::new(rwlock.clone(), guard)
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockReadAcquire<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Read, &mut self.key);
+    }
+}
+
+#[derive(Debug)]
+pub struct OwnedRwLockWriteAcquire<T: ?Sized> {
+    rwlock: Rc<RwLock<T>>,
+    key: Option<usize>,
+}
+
+impl<T: ?Sized> Future for OwnedRwLockWriteAcquire<T> {
+    type Output = LockResult<OwnedRwLockWriteGuard<T>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let rwlock = &this.rwlock;
+        match rwlock.poll_acquire_write(&mut this.key, cx) {
+            Poll::Ready(result) => Poll::Ready(poison::map_result(result, |guard| {
+                OwnedRwLockWriteGuard::new(rwlock.clone(), guard)
+            })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for OwnedRwLockWriteAcquire<T> {
+    fn drop(&mut self) {
+        self.rwlock.raw.cancel(Access::Write, &mut self.key);
+    }
+}
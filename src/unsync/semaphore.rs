@@ -0,0 +1,158 @@
+use core::cell::Cell;
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::waiter::WaitQueue;
+
+pub struct Semaphore {
+    permits: Cell<usize>,
+    waiters: Cell<WaitQueue>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Cell::new(permits),
+            waiters: Cell::new(WaitQueue::new()),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.permits.get()
+    }
+
+    pub fn acquire(&self, n: usize) -> SemaphoreAcquire<'_> {
+        SemaphoreAcquire {
+            semaphore: self,
+            permits: n,
+            key: None,
+        }
+    }
+
+    pub fn try_acquire(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        // Queued waiters take precedence over newcomers.
+        if !self.with_waiters(|waiters| waiters.is_empty()) || self.permits.get() < n {
+            return None;
+        }
+        self.permits.set(self.permits.get() - n);
+        Some(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        self.permits.set(self.permits.get() + n);
+        self.wake_first();
+    }
+
+    fn poll_acquire(
+        &self,
+        key: &mut Option<usize>,
+        n: usize,
+        cx: &mut Context<'_>,
+    ) -> Poll<SemaphorePermit<'_>> {
+        let first = self.with_waiters(|waiters| match *key {
+            None => waiters.is_empty(),
+            Some(k) => waiters.is_first(k),
+        });
+        if !first || self.permits.get() < n {
+            self.with_waiters(|waiters| waiters.register(key, cx.waker()));
+            return Poll::Pending;
+        }
+        self.permits.set(self.permits.get() - n);
+        if let Some(key) = key.take() {
+            self.with_waiters(|waiters| waiters.remove(key));
+            // The next waiter may be satisfied by the remaining permits.
+            self.wake_first();
+        }
+
+        Poll::Ready(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    fn abandon(&self, key: usize) {
+        let first = self.with_waiters(|waiters| {
+            let first = waiters.is_first(key);
+            waiters.remove(key);
+            first
+        });
+        if first {
+            self.wake_first();
+        }
+    }
+
+    fn wake_first(&self) {
+        if let Some(waker) = self.with_waiters(|waiters| waiters.first_waker()) {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.permits.get())
+            .finish()
+    }
+}
+
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl<'a> SemaphorePermit<'a> {
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl<'a> Drop for SemaphorePermit<'a> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+impl<'a> fmt::Debug for SemaphorePermit<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct SemaphoreAcquire<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+    key: Option<usize>,
+}
+
+impl<'a> Future for SemaphoreAcquire<'a> {
+    type Output = SemaphorePermit<'a>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.semaphore.poll_acquire(&mut this.key, this.permits, cx)
+    }
+}
+
+impl<'a> Drop for SemaphoreAcquire<'a> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.semaphore.abandon(key);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use super::mutex::{Mutex, MutexAcquire};
+
+/// A fixed set of mutexes, picked by hashing a key.
+///
+/// Splitting data that is locked by key over several mutexes lets tasks
+/// that need different keys proceed concurrently. Keys that hash to the
+/// same shard still contend for it.
+pub struct ShardedMutex<T, S = RandomState> {
+    shards: Box<[Mutex<T>]>,
+    hasher: S,
+}
+
+impl<T> ShardedMutex<T> {
+    /// Creates `shards` mutexes, initializing each with `f(index)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize, f: impl FnMut(usize) -> T) -> Self {
+        Self::with_hasher(shards, f, RandomState::new())
+    }
+}
+
+impl<T, S: BuildHasher> ShardedMutex<T, S> {
+    /// Like `new`, but hashes keys with `hasher`.
+    pub fn with_hasher(shards: usize, f: impl FnMut(usize) -> T, hasher: S) -> Self {
+        assert!(shards > 0, "ShardedMutex: no shards");
+        Self {
+            shards: (0..shards).map(f).map(Mutex::new).collect(),
+            hasher,
+        }
+    }
+
+    /// Returns the index of the shard responsible for `key`.
+    pub fn shard_index<K: Hash + ?Sized>(&self, key: &K) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    /// Returns the mutex responsible for `key`.
+    pub fn shard_for<K: Hash + ?Sized>(&self, key: &K) -> &Mutex<T> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// Acquires the lock of the shard responsible for `key`.
+    #[track_caller]
+    pub fn lock_for<K: Hash + ?Sized>(&self, key: &K) -> MutexAcquire<'_, T> {
+        self.shard_for(key).lock()
+    }
+}
+
+impl<T, S> ShardedMutex<T, S> {
+    pub fn shards(&self) -> &[Mutex<T>] {
+        &self.shards
+    }
+
+    pub fn into_shards(self) -> Vec<Mutex<T>> {
+        self.shards.into_vec()
+    }
+}
+
+impl<T: fmt::Debug, S> fmt::Debug for ShardedMutex<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedMutex")
+            .field("shards", &self.shards)
+            .finish()
+    }
+}
@@ -0,0 +1,135 @@
+use core::fmt;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::rwlock::{
+    RwLock, RwLockReadAcquire, RwLockReadGuard, RwLockWriteAcquire, RwLockWriteGuard,
+};
+use crate::poison::{PoisonError, TryLockError};
+
+/// An async `RefCell`: borrowing waits instead of panicking.
+///
+/// Any number of shared borrows may coexist, while a mutable borrow waits
+/// for exclusive access. This is an `RwLock` that ignores poisoning.
+pub struct SharedCell<T: ?Sized> {
+    inner: RwLock<T>,
+}
+
+impl<T> SharedCell<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: RwLock::new(inner),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replaces the value, returning the old one.
+    pub async fn replace(&self, value: T) -> T {
+        mem::replace(&mut *self.borrow_mut().await, value)
+    }
+}
+
+impl<T: ?Sized> SharedCell<T> {
+    pub fn borrow(&self) -> SharedCellBorrow<'_, T> {
+        SharedCellBorrow {
+            inner: self.inner.read(),
+        }
+    }
+
+    pub fn borrow_mut(&self) -> SharedCellBorrowMut<'_, T> {
+        SharedCellBorrowMut {
+            inner: self.inner.write(),
+        }
+    }
+
+    pub fn try_borrow(&self) -> Option<RwLockReadGuard<'_, T>> {
+        match self.inner.try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    pub fn try_borrow_mut(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        match self.inner.try_write() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> From<T> for SharedCell<T> {
+    fn from(x: T) -> Self {
+        SharedCell::new(x)
+    }
+}
+
+impl<T: Default> Default for SharedCell<T> {
+    fn default() -> Self {
+        SharedCell::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SharedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(guard) = self.try_borrow() {
+            f.debug_struct("SharedCell")
+                .field("value", &(&guard as &T))
+                .finish()
+        } else {
+            struct BorrowedPlaceholder;
+            impl fmt::Debug for BorrowedPlaceholder {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("<borrowed>")
+                }
+            }
+            f.debug_struct("SharedCell")
+                .field("value", &BorrowedPlaceholder)
+                .finish()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SharedCellBorrow<'a, T: ?Sized + 'a> {
+    inner: RwLockReadAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for SharedCellBorrow<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.inner).poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result.unwrap_or_else(PoisonError::into_inner)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SharedCellBorrowMut<'a, T: ?Sized + 'a> {
+    inner: RwLockWriteAcquire<'a, T>,
+}
+
+impl<'a, T: ?Sized + 'a> Future for SharedCellBorrowMut<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.inner).poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result.unwrap_or_else(PoisonError::into_inner)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_sink::Sink;
+
+use super::mutex::{Mutex, MutexAcquire, MutexGuard};
+use crate::poison::PoisonError;
+
+/// A sink that writes through a mutex; see `Mutex::as_sink`.
+pub struct MutexSink<'a, S: ?Sized + 'a> {
+    acquire: MutexAcquire<'a, S>,
+    guard: Option<MutexGuard<'a, S>>,
+}
+
+impl<'a, S: ?Sized + 'a> MutexSink<'a, S> {
+    pub(super) fn new(mutex: &'a Mutex<S>) -> Self {
+        Self {
+            acquire: mutex.lock(),
+            guard: None,
+        }
+    }
+
+    /// Acquires the lock unless it is already held.
+    fn poll_guard(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<&mut MutexGuard<'a, S>> {
+        // The acquisition is pinned along with `self`; the guard is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.guard.is_none() {
+            let acquire = unsafe { Pin::new_unchecked(&mut this.acquire) };
+            match acquire.poll(cx) {
+                Poll::Ready(result) => {
+                    this.guard = Some(result.unwrap_or_else(PoisonError::into_inner));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(this.guard.as_mut().unwrap())
+    }
+
+    fn release(self: Pin<&mut Self>) {
+        unsafe { self.get_unchecked_mut() }.guard = None;
+    }
+}
+
+impl<'a, S, Item> Sink<Item> for MutexSink<'a, S>
+where
+    S: Sink<Item> + Unpin + ?Sized + 'a,
+{
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let guard = match self.as_mut().poll_guard(cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => return Poll::Pending,
+        };
+        let result = Pin::new(&mut **guard).poll_ready(cx);
+        // The lock is kept for the `start_send` to follow, and while the
+        // sink has no room: the sink only wakes the task that found it
+        // full, so another task must not take its turn in the meantime.
+        if let Poll::Ready(Err(_)) = result {
+            self.release();
+        }
+        result
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), S::Error> {
+        let this = unsafe { self.as_mut().get_unchecked_mut() };
+        let guard = this
+            .guard
+            .as_mut()
+            .expect("MutexSink::start_send called without poll_ready");
+        let result = Pin::new(&mut **guard).start_send(item);
+        self.release();
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let guard = match self.as_mut().poll_guard(cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => return Poll::Pending,
+        };
+        let result = Pin::new(&mut **guard).poll_flush(cx);
+        if result.is_ready() {
+            self.release();
+        }
+        result
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        let guard = match self.as_mut().poll_guard(cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => return Poll::Pending,
+        };
+        let result = Pin::new(&mut **guard).poll_close(cx);
+        if result.is_ready() {
+            self.release();
+        }
+        result
+    }
+}
+
+impl<'a, S: ?Sized + 'a> fmt::Debug for MutexSink<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexSink")
+            .field("locked", &self.guard.is_some())
+            .finish()
+    }
+}
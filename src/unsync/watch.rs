@@ -0,0 +1,237 @@
+//! A single-producer, multi-consumer channel that only keeps the latest value.
+
+use alloc::rc::Rc;
+use core::cell::Cell;
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::rwlock::{RwLock, RwLockBuilder, RwLockReadGuard};
+use crate::poison::{PoisonError, TryLockError};
+use crate::waiter::WaitQueue;
+
+pub use super::mpsc::SendError;
+
+/// Creates a channel holding `init`.
+///
+/// The initial value counts as seen by the receiver.
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        // Borrowing must not wait for a queued `send`.
+        value: RwLockBuilder::new().prefer_writers(false).build(init),
+        version: Cell::new(0),
+        closed: Cell::new(false),
+        receivers: Cell::new(1),
+        waiters: Cell::new(WaitQueue::new()),
+    });
+    let receiver = Receiver {
+        shared: shared.clone(),
+        seen: 0,
+    };
+    (Sender { shared }, receiver)
+}
+
+struct Shared<T> {
+    value: RwLock<T>,
+    version: Cell<usize>,
+    closed: Cell<bool>,
+    receivers: Cell<usize>,
+    waiters: Cell<WaitQueue>,
+}
+
+impl<T> Shared<T> {
+    fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        // The value is only written to within `send`, which never awaits
+        // while holding the write lock.
+        match self.value.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => unreachable!("watch value borrowed during send"),
+        }
+    }
+
+    fn abandon(&self, key: &mut Option<usize>) {
+        if let Some(key) = key.take() {
+            self.with_waiters(|waiters| waiters.remove(key));
+        }
+    }
+
+    fn wake_all(&self) {
+        for waker in self.with_waiters(|waiters| waiters.wake_all()) {
+            waker.wake();
+        }
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut WaitQueue) -> R) -> R {
+        let mut waiters = self.waiters.replace(WaitQueue::new());
+        let ret = f(&mut waiters);
+        self.waiters.replace(waiters);
+        ret
+    }
+}
+
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Replaces the value and notifies the receivers.
+    ///
+    /// This waits for the receivers to drop their read guards.
+    /// Fails if there are no receivers left.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let shared = &self.shared;
+        if shared.receivers.get() == 0 {
+            return Err(SendError(value));
+        }
+        let mut guard = shared
+            .value
+            .write()
+            .await
+            .unwrap_or_else(PoisonError::into_inner);
+        *guard = value;
+        drop(guard);
+        shared.version.set(shared.version.get().wrapping_add(1));
+        shared.wake_all();
+        Ok(())
+    }
+
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.borrow()
+    }
+
+    /// Creates a new receiver that has seen the current value.
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.shared.version.get(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.closed.set(true);
+        self.shared.wake_all();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("value", &*self.borrow())
+            .finish()
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+    seen: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for a value that this receiver has not seen yet.
+    ///
+    /// Fails once the sender is gone and the latest value has been seen.
+    pub fn changed(&mut self) -> ReceiverChanged<'_, T> {
+        ReceiverChanged {
+            receiver: self,
+            key: None,
+        }
+    }
+
+    /// Borrows the latest value, without marking it as seen.
+    ///
+    /// Holding the guard across an await delays the sender.
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.shared.borrow()
+    }
+
+    /// Borrows the latest value and marks it as seen.
+    pub fn borrow_and_update(&mut self) -> RwLockReadGuard<'_, T> {
+        self.seen = self.shared.version.get();
+        self.shared.borrow()
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.seen != self.shared.version.get()
+    }
+
+    fn poll_changed(
+        &mut self,
+        key: &mut Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), RecvError>> {
+        let shared = &self.shared;
+        if self.seen != shared.version.get() {
+            shared.abandon(key);
+            self.seen = shared.version.get();
+            return Poll::Ready(Ok(()));
+        }
+        if shared.closed.get() {
+            shared.abandon(key);
+            return Poll::Ready(Err(RecvError(())));
+        }
+        shared.with_waiters(|waiters| waiters.register(key, cx.waker()));
+        Poll::Pending
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Self {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.set(self.shared.receivers.get() - 1);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("value", &*self.borrow())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct ReceiverChanged<'a, T> {
+    receiver: &'a mut Receiver<T>,
+    key: Option<usize>,
+}
+
+impl<'a, T> Future for ReceiverChanged<'a, T> {
+    type Output = Result<(), RecvError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.receiver.poll_changed(&mut this.key, cx)
+    }
+}
+
+impl<'a, T> Drop for ReceiverChanged<'a, T> {
+    fn drop(&mut self) {
+        self.receiver.shared.abandon(&mut self.key);
+    }
+}
+
+/// The sender is gone, and no unseen value is left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError(());
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl Error for RecvError {}
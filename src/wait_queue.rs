@@ -0,0 +1,117 @@
+use alloc::vec::Vec;
+use core::task::{Context, Waker};
+
+use crate::poison;
+use crate::waiter;
+
+/// The FIFO queue of tasks behind the mutexes, for building other
+/// primitives.
+///
+/// The queue does no locking of its own: keep it wherever the state it
+/// guards lives, such as in a `RefCell` or a `std::sync::Mutex`. Each
+/// waiting future owns a `WaitKey`, which remembers its queue position
+/// across polls.
+///
+/// Waking a task may poll it right away, on executors that poll inline,
+/// and the task would then deadlock on the lock guarding the queue. So
+/// the queue never wakes anyone itself: `notify_one`, `notify_all` and
+/// `cancel` return the wakers, to be woken once that lock is released.
+///
+/// A waiter that is woken and then gives up must hand its wakeup over, or
+/// the next waiter would keep waiting for a wakeup that has already been
+/// used. `cancel` does so, and must be called before a queued key is
+/// dropped.
+#[derive(Debug)]
+pub struct WaitQueue {
+    inner: waiter::WaitQueue,
+}
+
+/// A queue position in a `WaitQueue`.
+///
+/// A new key is not queued. A key must only be used with one queue, and
+/// leave it through `take_woken` or `cancel` before it is dropped; debug
+/// builds check this.
+#[derive(Debug, Default)]
+pub struct WaitKey(Option<usize>);
+
+impl Drop for WaitKey {
+    fn drop(&mut self) {
+        // Its slot would stay queued, and take a wakeup meant for others.
+        debug_assert!(
+            self.0.is_none() || poison::panicking(),
+            "WaitKey dropped while queued; call WaitQueue::cancel first"
+        );
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            inner: waiter::WaitQueue::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Counts the queued waiters, including the woken ones that have not
+    /// left the queue through `take_woken` yet.
+    pub fn len(&self) -> usize {
+        self.inner.queued()
+    }
+
+    /// Counts the waiters that have not been woken yet.
+    pub fn pending_len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Queues up the task, or updates its waker if it is already queued.
+    pub fn register(&mut self, key: &mut WaitKey, cx: &Context<'_>) {
+        self.inner.register(&mut key.0, cx.waker());
+    }
+
+    /// Leaves the queue if the waiter has been woken by `notify_one` or
+    /// `notify_all`, returning whether it had.
+    pub fn take_woken(&mut self, key: &mut WaitKey) -> bool {
+        match key.0 {
+            Some(k) if self.inner.take_notified(k) => {
+                key.0 = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Leaves the queue. If the waiter had been woken, the wakeup passes
+    /// on to the next waiter, whose waker is returned.
+    #[must_use = "the returned waker must be woken"]
+    pub fn cancel(&mut self, key: &mut WaitKey) -> Option<Waker> {
+        let k = key.0.take()?;
+        if self.inner.remove(k) {
+            self.notify_one()
+        } else {
+            None
+        }
+    }
+
+    /// Marks the first waiter that has not been woken yet as woken,
+    /// returning its waker.
+    #[must_use = "the returned waker must be woken"]
+    pub fn notify_one(&mut self) -> Option<Waker> {
+        self.inner.notify_one()
+    }
+
+    /// Marks all waiters as woken, returning the wakers of those that had
+    /// not been yet.
+    #[must_use = "the returned wakers must be woken"]
+    pub fn notify_all(&mut self) -> Vec<Waker> {
+        self.inner.notify_all()
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
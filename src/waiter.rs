@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+use core::mem;
+use core::task::Waker;
+
+use crate::small_deque::SmallDeque;
+
+/// How many waiters are kept inline before the queue allocates.
+pub(crate) const INLINE_WAITERS: usize = 4;
+
+/// A FIFO queue of tasks waiting for a lock.
+///
+/// Queued waiters are identified by a key, which lets them refresh their
+/// waker on every poll and leave the queue when they are cancelled. Releasing
+/// the lock notifies the head of the queue only, handing the lock over to it.
+///
+/// Callers that cannot keep a key (such as the bare `poll_lock`) are kept
+/// aside as plain wakers. They are woken on every release and then race for
+/// the lock, without taking part in the queue.
+#[derive(Debug)]
+pub(crate) struct WaitQueue {
+    queue: SmallDeque<Waiter, INLINE_WAITERS>,
+    wakers: SmallDeque<Waker, INLINE_WAITERS>,
+    next_key: usize,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    key: usize,
+    waker: Waker,
+    notified: bool,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            queue: SmallDeque::new(),
+            wakers: SmallDeque::new(),
+            next_key: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Whether there are neither queued waiters nor plain wakers.
+    #[cfg(feature = "std")]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.queue.is_empty() && self.wakers.is_empty()
+    }
+
+    /// Counts the queued waiters, notified or not.
+    pub(crate) fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Counts the waiters that have not been notified yet.
+    pub(crate) fn len(&self) -> usize {
+        self.queue.iter().filter(|waiter| !waiter.notified).count()
+    }
+
+    pub(crate) fn is_first(&self, key: usize) -> bool {
+        self.queue.front().is_some_and(|waiter| waiter.key == key)
+    }
+
+    /// Counts the waiters queued ahead of `key`.
+    #[cfg(feature = "std")]
+    pub(crate) fn position(&self, key: usize) -> Option<usize> {
+        self.queue.iter().position(|waiter| waiter.key == key)
+    }
+
+    pub(crate) fn first_waker(&self) -> Option<Waker> {
+        self.queue.front().map(|waiter| waiter.waker.clone())
+    }
+
+    /// Enqueues a new waiter, or updates the waker of an existing one.
+    pub(crate) fn register(&mut self, key: &mut Option<usize>, waker: &Waker) {
+        if let Some(key) = *key {
+            if let Some(waiter) = self.queue.iter_mut().find(|waiter| waiter.key == key) {
+                if !waiter.waker.will_wake(waker) {
+                    waiter.waker = waker.clone();
+                }
+                return;
+            }
+        }
+        let new_key = self.next_key;
+        self.next_key = self.next_key.wrapping_add(1);
+        self.queue.push_back(Waiter {
+            key: new_key,
+            waker: waker.clone(),
+            notified: false,
+        });
+        *key = Some(new_key);
+    }
+
+    /// Removes the waiter if it has been notified.
+    pub(crate) fn take_notified(&mut self, key: usize) -> bool {
+        let pos = self
+            .queue
+            .iter()
+            .position(|waiter| waiter.key == key && waiter.notified);
+        match pos {
+            Some(pos) => {
+                self.queue.remove(pos);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the waiter, returning whether it had been notified.
+    pub(crate) fn remove(&mut self, key: usize) -> bool {
+        let pos = self.queue.iter().position(|waiter| waiter.key == key);
+        match pos {
+            Some(pos) => self.queue.remove(pos).unwrap().notified,
+            None => false,
+        }
+    }
+
+    /// Notifies the first waiter that has not been notified yet,
+    /// returning its waker.
+    pub(crate) fn notify_one(&mut self) -> Option<Waker> {
+        let waiter = self.queue.iter_mut().find(|waiter| !waiter.notified)?;
+        waiter.notified = true;
+        Some(waiter.waker.clone())
+    }
+
+    /// Notifies all waiters, returning their wakers.
+    pub(crate) fn notify_all(&mut self) -> Vec<Waker> {
+        self.queue
+            .iter_mut()
+            .filter(|waiter| !waiter.notified)
+            .map(|waiter| {
+                waiter.notified = true;
+                waiter.waker.clone()
+            })
+            .collect()
+    }
+
+    /// Collects the wakers of all waiters, leaving them queued.
+    pub(crate) fn wake_all(&mut self) -> Vec<Waker> {
+        let queued = self.queue.iter().map(|waiter| waiter.waker.clone());
+        let wakers = mem::take(&mut self.wakers);
+        wakers.into_iter().chain(queued).collect()
+    }
+
+    /// Sets aside a plain waker, unless the task has already left one.
+    pub(crate) fn push_waker(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|old| old.will_wake(waker)) {
+            self.wakers.push_back(waker.clone());
+        }
+    }
+
+    pub(crate) fn take_wakers(&mut self) -> SmallDeque<Waker, INLINE_WAITERS> {
+        mem::take(&mut self.wakers)
+    }
+}
@@ -0,0 +1,96 @@
+//! Deferred wakeups, coalesced across mutexes; see `WakeBatch`.
+
+#[cfg(feature = "std")]
+mod imp {
+    use std::cell::RefCell;
+    use std::marker::PhantomData;
+    use std::task::Waker;
+
+    thread_local! {
+        /// The wakeups deferred by the outermost batch of the thread.
+        static BATCH: RefCell<Option<Vec<Waker>>> = const { RefCell::new(None) };
+    }
+
+    /// Defers the wakeups of mutex releases on this thread until it is
+    /// dropped, and then wakes each task once.
+    ///
+    /// A task releasing several locks in one poll, like a philosopher
+    /// putting down both forks, otherwise wakes the next waiter of each
+    /// lock right away, and a task woken twice may be polled twice. Under
+    /// a batch, the wakeups are collected and the duplicates dropped.
+    ///
+    /// Batches nest; the outermost one wakes the tasks. Blocking the
+    /// thread on a lock while a batch is alive may deadlock, as the task
+    /// that would release it may not have been woken yet.
+    #[derive(Debug)]
+    pub struct WakeBatch {
+        outermost: bool,
+        _not_send: PhantomData<*const ()>,
+    }
+
+    impl WakeBatch {
+        pub fn new() -> Self {
+            let outermost = BATCH.with(|batch| {
+                let mut batch = batch.borrow_mut();
+                let outermost = batch.is_none();
+                if outermost {
+                    *batch = Some(Vec::new());
+                }
+                outermost
+            });
+            Self {
+                outermost,
+                _not_send: PhantomData,
+            }
+        }
+    }
+
+    impl Default for WakeBatch {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Drop for WakeBatch {
+        fn drop(&mut self) {
+            if !self.outermost {
+                return;
+            }
+            let wakers = BATCH.with(|batch| batch.borrow_mut().take());
+            for waker in wakers.into_iter().flatten() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Wakes the task now, or when the current batch ends.
+    pub(crate) fn wake(waker: Waker) {
+        let mut waker = Some(waker);
+        // While the thread is torn down there is no batch to defer to.
+        let _ = BATCH.try_with(|batch| {
+            if let Some(wakers) = &mut *batch.borrow_mut() {
+                let waker = waker.take().unwrap();
+                if !wakers.iter().any(|old| old.will_wake(&waker)) {
+                    wakers.push(waker);
+                }
+            }
+        });
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    use core::task::Waker;
+
+    #[inline]
+    pub(crate) fn wake(waker: Waker) {
+        waker.wake();
+    }
+}
+
+pub(crate) use self::imp::wake;
+#[cfg(feature = "std")]
+pub use self::imp::WakeBatch;
@@ -0,0 +1,21 @@
+/// What releasing a contended lock does with the queued waiters.
+///
+/// The policy only matters under contention. `Handoff` keeps waiters
+/// from being overtaken; the other two leave the lock free for whoever
+/// gets to it first, which keeps it busy when the woken task is slow to
+/// be polled, at the cost of fairness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WakePolicy {
+    /// Hands the lock over to the first waiter, which owns it by the time
+    /// it is polled. This is what a fair mutex does.
+    #[default]
+    Handoff,
+    /// Unlocks and wakes the first waiter, which then competes with
+    /// newcomers. This is what an unfair mutex does.
+    WakeOne,
+    /// Unlocks and wakes every waiter, all of which compete for the lock.
+    ///
+    /// This suits executors that poll woken tasks in parallel, but wakes
+    /// many tasks only for one of them to succeed.
+    WakeAll,
+}
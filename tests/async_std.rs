@@ -0,0 +1,74 @@
+//! The mutexes on async-std: `unsync` driven by `block_on` on one thread,
+//! `sync` on spawned tasks that migrate between worker threads.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::task;
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use futures_mutex::{sync, unsync};
+
+#[test]
+fn unsync_counter() {
+    let mutex = Rc::new(unsync::Mutex::new(0));
+    // Unlike `join_all`, this gives each future a waker of its own.
+    let tasks = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    task::yield_now().await;
+                    *guard = value + 1;
+                }
+            }
+        })
+        .collect::<FuturesUnordered<_>>();
+    task::block_on(tasks.collect::<Vec<_>>());
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[test]
+fn sync_counter() {
+    let mutex = Arc::new(sync::Mutex::new(0));
+    let handles = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            task::spawn(async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    task::yield_now().await;
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    task::block_on(join_all(handles));
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[test]
+fn timed_out_waiters_leave_the_queue() {
+    let mutex = Arc::new(sync::Mutex::new(0));
+    let guard = mutex.try_lock().unwrap();
+    let handles = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            task::spawn(async move {
+                timeout(Duration::from_millis(10), mutex.lock())
+                    .await
+                    .is_err()
+            })
+        })
+        .collect::<Vec<_>>();
+    assert!(task::block_on(join_all(handles)).into_iter().all(|timed_out| timed_out));
+    assert_eq!(mutex.waiter_count(), 0);
+    drop(guard);
+    assert!(mutex.try_lock().is_ok());
+}
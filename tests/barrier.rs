@@ -0,0 +1,86 @@
+//! `unsync::Barrier`: releasing, reuse, and withdrawn arrivals.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures::executor::LocalPool;
+use futures::task::LocalSpawnExt;
+use futures_test::task::new_count_waker;
+
+use futures_mutex::unsync::{Barrier, BarrierWaitResult};
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn released_by_the_last_arrival() {
+    let barrier = Barrier::new(3);
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut first = Box::pin(barrier.wait());
+    let mut second = Box::pin(barrier.wait());
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+
+    let mut last = Box::pin(barrier.wait());
+    let leader = match poll(&mut last) {
+        Poll::Ready(result) => result,
+        Poll::Pending => panic!("the last arrival must release the barrier"),
+    };
+    assert!(leader.is_leader());
+    assert_eq!(count.get(), 2);
+    for wait in [&mut first, &mut second] {
+        match wait.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => assert!(!result.is_leader()),
+            Poll::Pending => panic!("a waiter was woken but not released"),
+        }
+    }
+}
+
+#[test]
+fn a_barrier_of_one_never_waits() {
+    let barrier = Barrier::new(1);
+    let mut wait = Box::pin(barrier.wait());
+    assert!(matches!(poll(&mut wait), Poll::Ready(result) if result.is_leader()));
+}
+
+#[test]
+fn dropped_wait_withdraws_its_arrival() {
+    let barrier = Barrier::new(2);
+    let mut withdrawn = Box::pin(barrier.wait());
+    assert!(poll(&mut withdrawn).is_pending());
+    drop(withdrawn);
+
+    let mut first = Box::pin(barrier.wait());
+    assert!(poll(&mut first).is_pending());
+    let mut second = Box::pin(barrier.wait());
+    assert!(poll(&mut second).is_ready());
+    assert!(poll(&mut first).is_ready());
+}
+
+#[test]
+fn reused_across_rounds() {
+    let barrier = Rc::new(Barrier::new(4));
+    let leaders = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut pool = LocalPool::new();
+    for _ in 0..4 {
+        let barrier = barrier.clone();
+        let leaders = leaders.clone();
+        pool.spawner()
+            .spawn_local(async move {
+                for round in 0..5 {
+                    let result: BarrierWaitResult = barrier.wait().await;
+                    if result.is_leader() {
+                        leaders.borrow_mut().push(round);
+                    }
+                }
+            })
+            .unwrap();
+    }
+    pool.run();
+    assert_eq!(*leaders.borrow(), (0..5).collect::<Vec<_>>());
+}
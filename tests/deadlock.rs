@@ -0,0 +1,66 @@
+//! Deadlock detection: a task waiting for a mutex it holds is reported,
+//! and ordinary contention between threads never is.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures::future::join_all;
+use futures_test::task::new_count_waker;
+use smol::{future, Executor};
+
+use futures_mutex::{sync, unsync};
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>, cx: &mut Context<'_>) -> Poll<F::Output> {
+    future.as_mut().poll(cx)
+}
+
+#[test]
+fn relocking_is_reported() {
+    let mutex = unsync::Mutex::new(0);
+    let (waker, _) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut first = Box::pin(mutex.lock());
+    let _guard = match poll(&mut first, &mut cx) {
+        Poll::Ready(guard) => guard.unwrap(),
+        Poll::Pending => panic!("the mutex is free"),
+    };
+    let mut second = Box::pin(mutex.lock());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| poll(&mut second, &mut cx)));
+    assert!(result.is_err());
+}
+
+#[test]
+fn contention_between_threads_is_not_reported() {
+    let ex = Arc::new(Executor::new());
+    let mutex = Arc::new(sync::Mutex::new(0));
+    let tasks = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            ex.spawn(async move {
+                for _ in 0..2000 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    future::yield_now().await;
+                    *guard += 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let (stop, stopped) = smol::channel::unbounded::<()>();
+    let workers = (0..4)
+        .map(|_| {
+            let ex = ex.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || future::block_on(ex.run(stopped.recv())))
+        })
+        .collect::<Vec<_>>();
+    future::block_on(join_all(tasks));
+    drop(stop);
+    for worker in workers {
+        let _ = worker.join().unwrap();
+    }
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 2000);
+}
@@ -0,0 +1,220 @@
+//! `unsync::Mutex` on hand-written executors that poll tasks in different
+//! orders, so that nothing depends on how one particular executor
+//! schedules wakeups.
+//!
+//! A task is only polled again once its waker is called (except on the
+//! spurious executor), so a lost wakeup shows up as tasks left pending.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::task::{Context, Wake, Waker};
+
+use futures::executor::LocalPool;
+use futures::future::{select, Either};
+use futures::task::LocalSpawnExt;
+use futures_test::future::FutureTestExt;
+
+use futures_mutex::unsync::{Mutex, MutexBuilder};
+use futures_mutex::WakePolicy;
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+#[derive(Debug, Clone, Copy)]
+enum Order {
+    /// Polls woken tasks in the order they were woken.
+    Fifo,
+    /// Polls the most recently woken task first.
+    Lifo,
+    /// Polls every pending task in each round, woken or not.
+    Spurious,
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<StdMutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.lock().unwrap().push_back(self.id);
+    }
+}
+
+/// Runs `tasks` to completion, panicking if some are left waiting without
+/// having been woken.
+fn run(order: Order, tasks: Vec<Task>) {
+    let queue = Arc::new(StdMutex::new((0..tasks.len()).collect::<VecDeque<_>>()));
+    let wakers = (0..tasks.len())
+        .map(|id| {
+            Waker::from(Arc::new(TaskWaker {
+                id,
+                queue: queue.clone(),
+            }))
+        })
+        .collect::<Vec<_>>();
+    let mut tasks = tasks.into_iter().map(Some).collect::<Vec<_>>();
+    let mut rounds = 0;
+    loop {
+        let next = match order {
+            Order::Fifo => queue.lock().unwrap().pop_front(),
+            Order::Lifo => queue.lock().unwrap().pop_back(),
+            Order::Spurious => {
+                queue.lock().unwrap().clear();
+                rounds += 1;
+                assert!(rounds < 1_000_000, "tasks made no progress");
+                tasks.iter().position(Option::is_some)
+            }
+        };
+        let ids = match (order, next) {
+            (_, None) => break,
+            (Order::Spurious, Some(_)) => (0..tasks.len()).collect(),
+            (_, Some(id)) => vec![id],
+        };
+        for id in ids {
+            if let Some(task) = &mut tasks[id] {
+                let mut cx = Context::from_waker(&wakers[id]);
+                if task.as_mut().poll(&mut cx).is_ready() {
+                    tasks[id] = None;
+                }
+            }
+        }
+    }
+    let pending = tasks.iter().filter(|task| task.is_some()).count();
+    assert_eq!(pending, 0, "tasks left pending without a wakeup");
+}
+
+fn run_local_pool(tasks: Vec<Task>) {
+    let mut pool = LocalPool::new();
+    let spawner = pool.spawner();
+    for task in tasks {
+        spawner.spawn_local(task).unwrap();
+    }
+    pool.run();
+}
+
+/// Runs the scenario built by `make` on every executor.
+fn on_all_executors(make: impl Fn() -> (Vec<Task>, Box<dyn FnOnce()>)) {
+    for &order in &[Order::Fifo, Order::Lifo, Order::Spurious] {
+        let (tasks, check) = make();
+        run(order, tasks);
+        check();
+    }
+    let (tasks, check) = make();
+    run_local_pool(tasks);
+    check();
+}
+
+fn counter(mutex: Mutex<i32>) -> (Vec<Task>, Box<dyn FnOnce()>) {
+    let mutex = Rc::new(mutex);
+    let tasks = (0..5)
+        .map(|_| {
+            let mutex = mutex.clone();
+            Box::pin(async move {
+                for _ in 0..50 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    async {}.pending_once().await;
+                    *guard = value + 1;
+                }
+            }) as Task
+        })
+        .collect();
+    let check = Box::new(move || assert_eq!(*mutex.try_lock().unwrap(), 5 * 50));
+    (tasks, check)
+}
+
+#[test]
+fn counter_fair() {
+    on_all_executors(|| counter(MutexBuilder::new().fair(true).build(0)));
+}
+
+#[test]
+fn counter_unfair() {
+    on_all_executors(|| counter(MutexBuilder::new().fair(false).build(0)));
+}
+
+#[test]
+fn counter_handoff() {
+    on_all_executors(|| counter(Mutex::with_policy(0, WakePolicy::Handoff)));
+}
+
+#[test]
+fn counter_wake_one() {
+    on_all_executors(|| counter(Mutex::with_policy(0, WakePolicy::WakeOne)));
+}
+
+#[test]
+fn counter_wake_all() {
+    on_all_executors(|| counter(Mutex::with_policy(0, WakePolicy::WakeAll)));
+}
+
+#[test]
+fn every_waiter_is_served() {
+    on_all_executors(|| {
+        let mutex = Rc::new(Mutex::new(Vec::new()));
+        let holder = {
+            let mutex = mutex.clone();
+            Box::pin(async move {
+                let guard = mutex.lock().await.unwrap();
+                // Give the others time to queue up behind us.
+                for _ in 0..10 {
+                    async {}.pending_once().await;
+                }
+                drop(guard);
+            }) as Task
+        };
+        let waiters = (0..5).map(|i| {
+            let mutex = mutex.clone();
+            Box::pin(async move {
+                mutex.lock().await.unwrap().push(i);
+            }) as Task
+        });
+        let tasks = Some(holder).into_iter().chain(waiters).collect();
+        let check = Box::new(move || {
+            let mut order = mutex.try_lock().unwrap().clone();
+            order.sort_unstable();
+            assert_eq!(order, (0..5).collect::<Vec<_>>());
+        });
+        (tasks, check)
+    });
+}
+
+#[test]
+fn abandoned_acquisitions_do_not_lose_wakeups() {
+    on_all_executors(|| {
+        let mutex = Rc::new(Mutex::new(0));
+        let acquired = Rc::new(RefCell::new(0));
+        let tasks = (0..6)
+            .map(|i| {
+                let mutex = mutex.clone();
+                let acquired = acquired.clone();
+                Box::pin(async move {
+                    for _ in 0..20 {
+                        let acquire = Box::pin(mutex.lock());
+                        // Every other task gives up after a few polls.
+                        let patience = if i % 2 == 0 { 1 } else { 1000 };
+                        let timeout = Box::pin(async move {
+                            for _ in 0..patience {
+                                async {}.pending_once().await;
+                            }
+                        });
+                        if let Either::Left((guard, _)) = select(acquire, timeout).await {
+                            let mut guard = guard.unwrap();
+                            async {}.pending_once().await;
+                            *guard += 1;
+                            *acquired.borrow_mut() += 1;
+                        }
+                    }
+                }) as Task
+            })
+            .collect();
+        let check = Box::new(move || {
+            assert_eq!(*mutex.try_lock().unwrap(), *acquired.borrow());
+        });
+        (tasks, check)
+    });
+}
@@ -0,0 +1,56 @@
+//! Looking at the data through `Debug` or `Serialize` is not an
+//! acquisition, so it must not reach the event hook.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_mutex::instrument::LockEvent;
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::Mutex;
+
+    static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count(_: &LockEvent) {
+        EVENTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn diagnostics_are_not_reported() {
+        let mutex = Mutex::new(vec![1, 2]);
+        mutex.set_event_hook(count);
+        assert_eq!(format!("{:?}", mutex), "Mutex { data: [1, 2] }");
+        #[cfg(feature = "serde")]
+        assert_eq!(serde_json::to_string(&mutex).unwrap(), "[1,2]");
+        assert_eq!(EVENTS.load(Ordering::SeqCst), 0);
+
+        drop(mutex.try_lock().unwrap());
+        assert!(EVENTS.load(Ordering::SeqCst) > 0);
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use futures_mutex::sync::Mutex;
+
+    static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+    fn count(_: &LockEvent) {
+        EVENTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn diagnostics_are_not_reported() {
+        let mutex = Mutex::new(vec![1, 2]);
+        mutex.set_event_hook(count);
+        assert_eq!(format!("{:?}", mutex), "Mutex { data: [1, 2] }");
+        #[cfg(feature = "serde")]
+        assert_eq!(serde_json::to_string(&mutex).unwrap(), "[1,2]");
+        assert_eq!(EVENTS.load(Ordering::SeqCst), 0);
+
+        drop(mutex.try_lock().unwrap());
+        assert!(EVENTS.load(Ordering::SeqCst) > 0);
+    }
+}
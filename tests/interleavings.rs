@@ -0,0 +1,220 @@
+//! Explores interleavings of tasks locking, holding and abandoning an
+//! `unsync::Mutex`, on an executor that polls woken tasks in an order
+//! picked by a seeded random number generator, and sometimes polls tasks
+//! that were not woken at all.
+//!
+//! Every run checks that the lock was never held twice, that no task was
+//! left waiting without a wakeup, and that the mutex kept no wakers once
+//! all the tasks were done. A failing run reports its seed, which
+//! `run_seed` replays.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use futures_test::future::FutureTestExt;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use futures_mutex::unsync::MutexBuilder;
+use futures_mutex::WakePolicy;
+
+const SEEDS: u64 = 1000;
+const TASKS: usize = 4;
+const OPS: usize = 3;
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    /// Waits for the lock, then holds it across `hold` yields.
+    Lock { hold: usize },
+    /// Like `Lock`, but gives up after being polled `patience` times.
+    LockImpatiently { patience: usize, hold: usize },
+    /// Takes the lock only if it is free.
+    TryLock { hold: usize },
+}
+
+impl Op {
+    fn random(rng: &mut StdRng) -> Self {
+        let hold = rng.gen_range(0..3);
+        match rng.gen_range(0..3) {
+            0 => Op::Lock { hold },
+            1 => Op::LockImpatiently {
+                patience: rng.gen_range(1..4),
+                hold,
+            },
+            _ => Op::TryLock { hold },
+        }
+    }
+}
+
+/// Gives up on `inner` when polled again after `patience` polls, without
+/// polling `inner` that last time, like a timeout that fires just as the
+/// lock is handed over.
+struct Patience<F> {
+    inner: Pin<Box<F>>,
+    patience: usize,
+}
+
+impl<F: Future> Future for Patience<F> {
+    type Output = Option<F::Output>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.patience == 0 {
+            return Poll::Ready(None);
+        }
+        self.patience -= 1;
+        self.inner.as_mut().poll(cx).map(Some)
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    woken: Arc<StdMutex<Vec<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut woken = self.woken.lock().unwrap();
+        if !woken.contains(&self.id) {
+            woken.push(self.id);
+        }
+    }
+}
+
+/// Runs `tasks` to completion, picking the next task to poll with `rng`,
+/// and sometimes polling a task that was not woken if `spurious` is set.
+///
+/// Panics if some tasks are left waiting without having been woken, or
+/// if anything still holds on to their wakers afterwards.
+fn run(rng: &mut StdRng, spurious: bool, tasks: Vec<Task>) {
+    let woken = Arc::new(StdMutex::new((0..tasks.len()).collect::<Vec<_>>()));
+    let wakers = (0..tasks.len())
+        .map(|id| {
+            Arc::new(TaskWaker {
+                id,
+                woken: woken.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut tasks = tasks.into_iter().map(Some).collect::<Vec<_>>();
+    loop {
+        let pending = (0..tasks.len())
+            .filter(|&id| tasks[id].is_some())
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            break;
+        }
+        let id = {
+            let mut woken = woken.lock().unwrap();
+            if woken.is_empty() {
+                // Dropping the stuck tasks may trip assertions in the mutex
+                // while we unwind, so leak them instead.
+                drop(woken);
+                mem::forget(tasks);
+                panic!("tasks {:?} left pending without a wakeup", pending);
+            }
+            if spurious && rng.gen_ratio(1, 8) {
+                // A spurious poll, which futures must put up with.
+                pending[rng.gen_range(0..pending.len())]
+            } else {
+                let index = rng.gen_range(0..woken.len());
+                woken.swap_remove(index)
+            }
+        };
+        if let Some(task) = &mut tasks[id] {
+            let waker = Waker::from(wakers[id].clone());
+            if task.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                tasks[id] = None;
+            }
+        }
+    }
+    for waker in &wakers {
+        assert_eq!(
+            Arc::strong_count(waker),
+            1,
+            "the waker of task {} was leaked",
+            waker.id
+        );
+    }
+}
+
+/// Runs the tasks and checks generated from `seed`.
+fn run_seed(policy: WakePolicy, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mutex = Rc::new(MutexBuilder::new().wake_policy(policy).build(0));
+    let held = Rc::new(Cell::new(false));
+    let acquired = Rc::new(Cell::new(0));
+    let tasks = (0..TASKS)
+        .map(|_| {
+            let ops = (0..OPS).map(|_| Op::random(&mut rng)).collect::<Vec<_>>();
+            let mutex = mutex.clone();
+            let held = held.clone();
+            let acquired = acquired.clone();
+            Box::pin(async move {
+                for op in ops {
+                    let (guard, hold) = match op {
+                        Op::Lock { hold } => (Some(mutex.lock().await.unwrap()), hold),
+                        Op::LockImpatiently { patience, hold } => {
+                            let acquire = Patience {
+                                inner: Box::pin(mutex.lock()),
+                                patience,
+                            };
+                            (acquire.await.map(Result::unwrap), hold)
+                        }
+                        Op::TryLock { hold } => (mutex.try_lock().ok(), hold),
+                    };
+                    if let Some(mut guard) = guard {
+                        assert!(!held.replace(true), "the lock was held twice");
+                        for _ in 0..hold {
+                            async {}.pending_once().await;
+                        }
+                        *guard += 1;
+                        held.set(false);
+                        acquired.set(acquired.get() + 1);
+                    }
+                }
+            }) as Task
+        })
+        .collect();
+    // Spurious polls can make up for lost wakeups, so only half of the
+    // runs have them.
+    run(&mut rng, seed % 2 == 1, tasks);
+    assert_eq!(mutex.waiter_count(), 0);
+    assert_eq!(*mutex.try_lock().unwrap(), acquired.get());
+}
+
+fn explore(policy: WakePolicy) {
+    for seed in 0..SEEDS {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run_seed(policy, seed)));
+        if let Err(err) = result {
+            eprintln!("failed with {:?} and seed {}", policy, seed);
+            panic::resume_unwind(err);
+        }
+    }
+}
+
+#[test]
+fn handoff() {
+    explore(WakePolicy::Handoff);
+}
+
+#[test]
+fn wake_one() {
+    explore(WakePolicy::WakeOne);
+}
+
+#[test]
+fn wake_all() {
+    explore(WakePolicy::WakeAll);
+}
+
@@ -0,0 +1,124 @@
+//! `Mutex::as_io` with tasks contending on a reader that has no input.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::task::{Context, Poll, Waker};
+
+use futures::io::AsyncRead;
+use futures_test::task::new_count_waker;
+
+/// A reader of single bytes, fed through a clone. Like most IO objects, it
+/// only remembers the last task that found it empty.
+#[derive(Clone, Default)]
+struct OneByte {
+    state: Arc<StdMutex<(Option<u8>, Option<Waker>)>>,
+}
+
+impl OneByte {
+    fn put(&self, byte: u8) {
+        let mut state = self.state.lock().unwrap();
+        assert!(state.0.replace(byte).is_none());
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl AsyncRead for OneByte {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock().unwrap();
+        match state.0.take() {
+            Some(byte) => {
+                buf[0] = byte;
+                Poll::Ready(Ok(1))
+            }
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::Mutex;
+
+    #[test]
+    fn readers_waiting_for_input_are_all_woken() {
+        let input = OneByte::default();
+        let mutex = Mutex::new(input.clone());
+        let (waker1, count1) = new_count_waker();
+        let (waker2, count2) = new_count_waker();
+        let mut cx1 = Context::from_waker(&waker1);
+        let mut cx2 = Context::from_waker(&waker2);
+        let mut io1 = Box::pin(mutex.as_io());
+        let mut io2 = Box::pin(mutex.as_io());
+        let mut buf = [0];
+        assert!(io1.as_mut().poll_read(&mut cx1, &mut buf).is_pending());
+        // The first reader keeps the lock while it waits for input, so the
+        // second cannot replace it as the task the reader wakes.
+        assert!(io2.as_mut().poll_read(&mut cx2, &mut buf).is_pending());
+
+        input.put(1);
+        assert_eq!(count1.get(), 1);
+        match io1.as_mut().poll_read(&mut cx1, &mut buf) {
+            Poll::Ready(Ok(1)) => assert_eq!(buf, [1]),
+            _ => panic!("the input is ready"),
+        }
+        assert_eq!(count2.get(), 1);
+        assert!(io2.as_mut().poll_read(&mut cx2, &mut buf).is_pending());
+
+        input.put(2);
+        assert_eq!(count2.get(), 2);
+        match io2.as_mut().poll_read(&mut cx2, &mut buf) {
+            Poll::Ready(Ok(1)) => assert_eq!(buf, [2]),
+            _ => panic!("the input is ready"),
+        }
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use futures_mutex::sync::Mutex;
+
+    #[test]
+    fn readers_waiting_for_input_are_all_woken() {
+        let input = OneByte::default();
+        let mutex = Mutex::new(input.clone());
+        let (waker1, count1) = new_count_waker();
+        let (waker2, count2) = new_count_waker();
+        let mut cx1 = Context::from_waker(&waker1);
+        let mut cx2 = Context::from_waker(&waker2);
+        let mut io1 = Box::pin(mutex.as_io());
+        let mut io2 = Box::pin(mutex.as_io());
+        let mut buf = [0];
+        assert!(io1.as_mut().poll_read(&mut cx1, &mut buf).is_pending());
+        // The first reader keeps the lock while it waits for input, so the
+        // second cannot replace it as the task the reader wakes.
+        assert!(io2.as_mut().poll_read(&mut cx2, &mut buf).is_pending());
+
+        input.put(1);
+        assert_eq!(count1.get(), 1);
+        match io1.as_mut().poll_read(&mut cx1, &mut buf) {
+            Poll::Ready(Ok(1)) => assert_eq!(buf, [1]),
+            _ => panic!("the input is ready"),
+        }
+        assert_eq!(count2.get(), 1);
+        assert!(io2.as_mut().poll_read(&mut cx2, &mut buf).is_pending());
+
+        input.put(2);
+        assert_eq!(count2.get(), 2);
+        match io2.as_mut().poll_read(&mut cx2, &mut buf) {
+            Poll::Ready(Ok(1)) => assert_eq!(buf, [2]),
+            _ => panic!("the input is ready"),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Model checks for the `sync` primitives.
+//!
+//! They run with
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --test loom
+//! ```
+#![cfg(loom)]
+
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_mutex::sync::{Mutex, MutexBuilder};
+use loom::future::block_on;
+use loom::sync::atomic::{AtomicBool, Ordering};
+use loom::thread;
+
+fn mutex<T>(fair: bool, inner: T) -> Arc<Mutex<T>> {
+    Arc::new(MutexBuilder::new().spin_limit(0).fair(fair).build(inner))
+}
+
+fn lock_unlock(fair: bool) {
+    loom::model(move || {
+        let mutex = mutex(fair, 0);
+        let handles = (0..2)
+            .map(|_| {
+                let mutex = mutex.clone();
+                thread::spawn(move || {
+                    *block_on(mutex.lock()).unwrap() += 1;
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*mutex.try_lock().unwrap(), 2);
+    });
+}
+
+#[test]
+fn lock_unlock_fair() {
+    lock_unlock(true);
+}
+
+#[test]
+fn lock_unlock_unfair() {
+    lock_unlock(false);
+}
+
+#[test]
+fn poll_lock_is_woken() {
+    loom::model(|| {
+        let mutex = mutex(true, ());
+        let guard = mutex.try_lock().unwrap();
+        let handle = {
+            let mutex = mutex.clone();
+            thread::spawn(move || {
+                block_on(futures::future::poll_fn(|cx| {
+                    mutex.poll_lock(cx).map(|guard| drop(guard.unwrap()))
+                }));
+            })
+        };
+        drop(guard);
+        handle.join().unwrap();
+        assert!(!mutex.is_locked());
+    });
+}
+
+#[test]
+fn cancelled_acquire_passes_the_lock_on() {
+    loom::model(|| {
+        let mutex = mutex(true, ());
+        let guard = mutex.try_lock().unwrap();
+        let handle = {
+            let mutex = mutex.clone();
+            thread::spawn(move || {
+                let mut acquire = mutex.lock();
+                let waker = futures::task::noop_waker();
+                let mut cx = Context::from_waker(&waker);
+                if let Poll::Ready(guard) = Pin::new(&mut acquire).poll(&mut cx) {
+                    drop(guard.unwrap());
+                }
+            })
+        };
+        drop(guard);
+        handle.join().unwrap();
+        assert!(!mutex.is_locked());
+        assert_eq!(mutex.waiter_count(), 0);
+    });
+}
+
+// Loom runs every model thread on one OS thread, so a panicking thread
+// would poison loom's own locks for the others. `scope` poisons the mutex
+// without a panic when it is cancelled instead.
+#[test]
+fn cancelled_scope_poisons() {
+    loom::model(|| {
+        let mutex = mutex(true, ());
+        let entered = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let mutex = mutex.clone();
+            let entered = entered.clone();
+            thread::spawn(move || {
+                let mut scope = Box::pin(mutex.scope(async |_: &mut ()| {
+                    entered.store(true, Ordering::Relaxed);
+                    future::pending::<()>().await
+                }));
+                let waker = futures::task::noop_waker();
+                let mut cx = Context::from_waker(&waker);
+                assert!(scope.as_mut().poll(&mut cx).is_pending());
+            })
+        };
+        drop(block_on(mutex.lock()));
+        handle.join().unwrap();
+        let entered = entered.load(Ordering::Relaxed);
+        assert_eq!(mutex.is_poisoned(), entered);
+        assert_eq!(block_on(mutex.lock()).is_err(), entered);
+    });
+}
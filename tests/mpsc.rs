@@ -0,0 +1,120 @@
+//! `unsync::mpsc`: ordering, backpressure, closing and sender drops.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::unsync::mpsc::{self, TrySendError};
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn values_arrive_in_order() {
+    let (tx, mut rx) = mpsc::channel(4);
+    for i in 0..3 {
+        tx.try_send(i).unwrap();
+    }
+    assert_eq!(rx.try_recv(), Ok(Some(0)));
+    assert_eq!(rx.try_recv(), Ok(Some(1)));
+    assert_eq!(rx.try_recv(), Ok(Some(2)));
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn full_channel_holds_senders_back() {
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(0).unwrap();
+    assert!(matches!(tx.try_send(1), Err(TrySendError::Full(1))));
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut send = Box::pin(tx.send(1));
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+
+    assert_eq!(rx.try_recv(), Ok(Some(0)));
+    assert_eq!(count.get(), 1);
+    assert!(matches!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    assert_eq!(rx.try_recv(), Ok(Some(1)));
+}
+
+#[test]
+fn receiver_is_woken_by_a_send() {
+    let (tx, mut rx) = mpsc::channel(1);
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    {
+        let mut recv = Box::pin(rx.recv());
+        assert!(recv.as_mut().poll(&mut cx).is_pending());
+        tx.try_send(7).unwrap();
+        assert_eq!(count.get(), 1);
+        assert_eq!(recv.as_mut().poll(&mut cx), Poll::Ready(Some(7)));
+    }
+}
+
+#[test]
+fn dropping_every_sender_ends_the_stream() {
+    let (tx, mut rx) = mpsc::channel(2);
+    let tx2 = tx.clone();
+    tx.try_send(0).unwrap();
+    drop(tx);
+    assert!(rx.try_recv().is_ok());
+    assert!(rx.try_recv().is_err());
+
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut recv = Box::pin(rx.recv());
+    assert!(recv.as_mut().poll(&mut cx).is_pending());
+    drop(tx2);
+    assert_eq!(count.get(), 1);
+    assert_eq!(recv.as_mut().poll(&mut cx), Poll::Ready(None));
+}
+
+#[test]
+fn close_drains_then_ends_the_stream() {
+    let (tx, mut rx) = mpsc::channel(2);
+    tx.try_send(0).unwrap();
+    tx.try_send(1).unwrap();
+    rx.close();
+    assert!(tx.is_closed());
+    assert!(matches!(tx.try_send(2), Err(TrySendError::Closed(2))));
+    // The sender is still alive, but nothing more can arrive.
+    assert_eq!(rx.try_recv(), Ok(Some(0)));
+    let mut recv = Box::pin(rx.recv());
+    assert_eq!(poll(&mut recv), Poll::Ready(Some(1)));
+    drop(recv);
+    assert_eq!(rx.try_recv(), Ok(None));
+    let mut recv = Box::pin(rx.recv());
+    assert_eq!(poll(&mut recv), Poll::Ready(None));
+}
+
+#[test]
+fn close_fails_blocked_senders() {
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(0).unwrap();
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut send = Box::pin(tx.send(1));
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+
+    rx.close();
+    assert_eq!(count.get(), 1);
+    match send.as_mut().poll(&mut cx) {
+        Poll::Ready(Err(err)) => assert_eq!(err.0, 1),
+        _ => panic!("a send on a closed channel must fail"),
+    }
+    assert_eq!(rx.try_recv(), Ok(Some(0)));
+    assert_eq!(rx.try_recv(), Ok(None));
+}
+
+#[test]
+fn dropping_the_receiver_closes_the_channel() {
+    let (tx, rx) = mpsc::channel::<i32>(1);
+    drop(rx);
+    assert!(tx.is_closed());
+    let mut send = Box::pin(tx.send(0));
+    assert!(matches!(poll(&mut send), Poll::Ready(Err(_))));
+}
@@ -0,0 +1,122 @@
+//! `Mutex::builder` and the explicit fair and unfair releases, in both
+//! flavors, and the cap on waiters of the unsync mutex.
+
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::{LockError, TryLockError};
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::{Mutex, MutexGuard};
+
+    #[test]
+    fn builder_builds() {
+        let mutex = Mutex::builder().fair(false).build(1);
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn unlock_unfair_lets_a_newcomer_barge_in() {
+        let mutex = Mutex::builder().fair(true).build(0);
+        let guard = mutex.try_lock().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut waiter = Box::pin(mutex.lock());
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+
+        MutexGuard::unlock_unfair(guard);
+        assert_eq!(count.get(), 1);
+        let barging = mutex.try_lock().unwrap();
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+        drop(barging);
+        assert!(waiter.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn unlock_fair_hands_over_on_an_unfair_mutex() {
+        let mutex = Mutex::builder().fair(false).build(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut waiter = Box::pin(mutex.lock());
+        assert!(poll(&mut waiter).is_pending());
+
+        MutexGuard::unlock_fair(guard);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        assert!(poll(&mut waiter).is_ready());
+    }
+
+    #[test]
+    fn checked_acquisitions_fail_past_max_waiters() {
+        let mutex = Mutex::builder().max_waiters(1).build(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut first = Box::pin(mutex.lock_checked());
+        assert!(poll(&mut first).is_pending());
+
+        let mut checked = Box::pin(mutex.lock_checked());
+        assert!(matches!(poll(&mut checked), Poll::Ready(Err(LockError::QueueFull))));
+        let sleep = |_| future::pending();
+        let mut timed = Box::pin(mutex.lock_checked_for(Duration::from_secs(1), sleep));
+        assert!(matches!(poll(&mut timed), Poll::Ready(Err(LockError::QueueFull))));
+        let mut cancellable = Box::pin(mutex.lock_cancellable(future::pending()));
+        assert!(matches!(poll(&mut cancellable), Poll::Ready(Err(LockError::QueueFull))));
+        // Plain acquisitions cannot fail, so they wait past the cap.
+        let mut plain = Box::pin(mutex.lock());
+        assert!(poll(&mut plain).is_pending());
+
+        // The task that was waiting already keeps its place.
+        assert!(poll(&mut first).is_pending());
+        drop(guard);
+        assert!(matches!(poll(&mut first), Poll::Ready(Ok(_))));
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use futures_mutex::sync::{Mutex, MutexGuard};
+
+    #[test]
+    fn builder_builds() {
+        let mutex = Mutex::builder().fair(false).spin_limit(0).build(1);
+        assert_eq!(*mutex.try_lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn unlock_unfair_lets_a_newcomer_barge_in() {
+        let mutex = Mutex::builder().fair(true).build(0);
+        let guard = mutex.try_lock().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut waiter = Box::pin(mutex.lock());
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+
+        MutexGuard::unlock_unfair(guard);
+        assert_eq!(count.get(), 1);
+        let barging = mutex.try_lock().unwrap();
+        assert!(waiter.as_mut().poll(&mut cx).is_pending());
+        drop(barging);
+        assert!(waiter.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn unlock_fair_hands_over_on_an_unfair_mutex() {
+        let mutex = Mutex::builder().fair(false).build(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut waiter = Box::pin(mutex.lock());
+        assert!(poll(&mut waiter).is_pending());
+
+        MutexGuard::unlock_fair(guard);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        assert!(poll(&mut waiter).is_ready());
+    }
+}
@@ -0,0 +1,91 @@
+//! `unsync::Notify`: permits, FIFO wakeups, broadcasts and cancellation.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::unsync::Notify;
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn notify_before_wait_stores_a_permit() {
+    let notify = Notify::new();
+    notify.notify_one();
+    let mut notified = Box::pin(notify.notified());
+    assert!(poll(&mut notified).is_ready());
+    // The permit has been used up.
+    let mut notified = Box::pin(notify.notified());
+    assert!(poll(&mut notified).is_pending());
+}
+
+#[test]
+fn permits_do_not_pile_up() {
+    let notify = Notify::new();
+    notify.notify_one();
+    notify.notify_one();
+    let mut first = Box::pin(notify.notified());
+    let mut second = Box::pin(notify.notified());
+    assert!(poll(&mut first).is_ready());
+    assert!(poll(&mut second).is_pending());
+}
+
+#[test]
+fn notify_one_wakes_the_first_waiter() {
+    let notify = Notify::new();
+    let (waker1, count1) = new_count_waker();
+    let (waker2, count2) = new_count_waker();
+    let mut first = Box::pin(notify.notified());
+    let mut second = Box::pin(notify.notified());
+    assert!(first.as_mut().poll(&mut Context::from_waker(&waker1)).is_pending());
+    assert!(second.as_mut().poll(&mut Context::from_waker(&waker2)).is_pending());
+
+    notify.notify_one();
+    assert_eq!((count1.get(), count2.get()), (1, 0));
+    assert!(first.as_mut().poll(&mut Context::from_waker(&waker1)).is_ready());
+    assert!(second.as_mut().poll(&mut Context::from_waker(&waker2)).is_pending());
+    notify.notify_one();
+    assert_eq!(count2.get(), 1);
+    assert!(second.as_mut().poll(&mut Context::from_waker(&waker2)).is_ready());
+}
+
+#[test]
+fn notify_waiters_wakes_earlier_futures_only() {
+    let notify = Notify::new();
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut polled = Box::pin(notify.notified());
+    assert!(polled.as_mut().poll(&mut cx).is_pending());
+    // Created before the call, but not polled yet.
+    let mut unpolled = Box::pin(notify.notified());
+
+    notify.notify_waiters();
+    assert_eq!(count.get(), 1);
+    assert!(polled.as_mut().poll(&mut cx).is_ready());
+    assert!(unpolled.as_mut().poll(&mut cx).is_ready());
+    // No permit is left behind for later futures.
+    let mut later = Box::pin(notify.notified());
+    assert!(later.as_mut().poll(&mut cx).is_pending());
+}
+
+#[test]
+fn dropped_waiter_passes_its_notification_on() {
+    let notify = Notify::new();
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut first = Box::pin(notify.notified());
+    let mut second = Box::pin(notify.notified());
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+
+    notify.notify_one();
+    assert_eq!(count.get(), 1);
+    drop(first);
+    assert_eq!(count.get(), 2);
+    assert!(second.as_mut().poll(&mut cx).is_ready());
+}
@@ -0,0 +1,88 @@
+//! `unsync::Once`: one initializer at a time, handed over on cancellation
+//! and poisoned by a panic.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures_test::task::new_count_waker;
+
+use futures_mutex::unsync::Once;
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn runs_only_once() {
+    let once = Once::new();
+    let runs = Cell::new(0);
+    for _ in 0..3 {
+        block_on(once.call_once(|| async { runs.set(runs.get() + 1) }));
+    }
+    assert_eq!(runs.get(), 1);
+    assert!(once.is_completed());
+}
+
+#[test]
+fn callers_wait_for_the_running_initializer() {
+    let once = Once::new();
+    let runs = Cell::new(0);
+    let (tx, rx) = oneshot::channel::<()>();
+    let mut first = Box::pin(once.call_once(|| async {
+        runs.set(runs.get() + 1);
+        rx.await.unwrap();
+    }));
+    assert!(poll(&mut first).is_pending());
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut second = Box::pin(once.call_once(|| async { runs.set(runs.get() + 1) }));
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+
+    tx.send(()).unwrap();
+    assert!(poll(&mut first).is_ready());
+    assert_eq!(count.get(), 1);
+    assert!(second.as_mut().poll(&mut cx).is_ready());
+    assert_eq!(runs.get(), 1);
+}
+
+#[test]
+fn cancelled_initializer_hands_over() {
+    let once = Once::new();
+    let runs = Cell::new(0);
+    let mut first = Box::pin(once.call_once(|| async {
+        runs.set(runs.get() + 1);
+        futures::future::pending::<()>().await;
+    }));
+    assert!(poll(&mut first).is_pending());
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut second = Box::pin(once.call_once(|| async { runs.set(runs.get() + 1) }));
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+
+    drop(first);
+    assert!(!once.is_completed());
+    assert_eq!(count.get(), 1);
+    assert!(second.as_mut().poll(&mut cx).is_ready());
+    assert_eq!(runs.get(), 2);
+    assert!(once.is_completed());
+}
+
+#[test]
+fn panicking_initializer_poisons() {
+    let once = Once::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        block_on(once.call_once(|| async { panic!("poison") }));
+    }));
+    assert!(result.is_err());
+    assert!(once.is_poisoned());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        block_on(once.call_once(|| async {}));
+    }));
+    assert!(result.is_err());
+}
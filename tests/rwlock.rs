@@ -0,0 +1,450 @@
+//! `RwLock` in both flavors, polled by hand so that each test controls
+//! exactly when the lock is released and which waiters are woken.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::TryLockError;
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::{
+        RwLock, RwLockBuilder, RwLockUpgradableReadGuard, RwLockWriteGuard,
+    };
+
+    #[test]
+    fn readers_share_and_writers_exclude() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let r2 = lock.try_read().unwrap();
+        assert_eq!(*r1 + *r2, 0);
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop((r1, r2));
+
+        let mut w = lock.try_write().unwrap();
+        *w = 1;
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop(w);
+        assert_eq!(*lock.try_read().unwrap(), 1);
+    }
+
+    #[test]
+    fn writer_is_woken_when_the_last_reader_leaves() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let r2 = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+
+        drop(r1);
+        assert_eq!(count.get(), 0);
+        drop(r2);
+        assert_eq!(count.get(), 1);
+        *match write.as_mut().poll(&mut cx) {
+            Poll::Ready(guard) => guard.unwrap(),
+            Poll::Pending => panic!("the writer was woken but cannot write"),
+        } += 1;
+        drop(write);
+        assert_eq!(*lock.try_read().unwrap(), 1);
+    }
+
+    #[test]
+    fn readers_are_all_woken_when_the_writer_leaves() {
+        let lock = RwLock::new(0);
+        let w = lock.try_write().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut reads = (0..3).map(|_| Box::pin(lock.read())).collect::<Vec<_>>();
+        for read in &mut reads {
+            assert!(read.as_mut().poll(&mut cx).is_pending());
+        }
+
+        drop(w);
+        assert_eq!(count.get(), 3);
+        let guards = reads
+            .iter_mut()
+            .map(|read| match read.as_mut().poll(&mut cx) {
+                Poll::Ready(guard) => guard.unwrap(),
+                Poll::Pending => panic!("a reader was woken but cannot read"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(guards.len(), 3);
+    }
+
+    #[test]
+    fn cancelled_writer_lets_blocked_readers_in() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let mut write = Box::pin(lock.write());
+        assert!(poll(&mut write).is_pending());
+        // The queued writer holds back new readers.
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut read = Box::pin(lock.read());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        drop(write);
+        assert_eq!(count.get(), 1);
+        assert!(read.as_mut().poll(&mut cx).is_ready());
+        drop(r1);
+    }
+
+    #[test]
+    fn cancelled_reader_leaves_no_waiter_behind() {
+        let lock = RwLock::new(0);
+        let w = lock.try_write().unwrap();
+        let mut read = Box::pin(lock.read());
+        assert!(poll(&mut read).is_pending());
+        drop(read);
+        drop(w);
+        assert!(lock.try_write().is_ok());
+    }
+
+    #[test]
+    fn downgrade_lets_readers_in() {
+        let lock = RwLock::new(0);
+        let mut w = lock.try_write().unwrap();
+        *w = 1;
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut read = Box::pin(lock.read());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        let r = RwLockWriteGuard::downgrade(w);
+        assert_eq!(*r, 1);
+        assert_eq!(count.get(), 1);
+        assert!(read.as_mut().poll(&mut cx).is_ready());
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+    }
+
+    #[test]
+    fn upgrade_waits_for_readers() {
+        let lock = RwLock::new(0);
+        let upgradable = lock.try_upgradable_read().unwrap();
+        // An upgradable read shares the lock with plain readers only.
+        let r = lock.try_read().unwrap();
+        assert!(matches!(lock.try_upgradable_read(), Err(TryLockError::WouldBlock)));
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut upgrade = Box::pin(RwLockUpgradableReadGuard::upgrade(upgradable));
+        assert!(upgrade.as_mut().poll(&mut cx).is_pending());
+        drop(r);
+        assert_eq!(count.get(), 1);
+        let mut w = match upgrade.as_mut().poll(&mut cx) {
+            Poll::Ready(guard) => guard,
+            Poll::Pending => panic!("the upgrade was woken but cannot write"),
+        };
+        *w = 1;
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        drop(w);
+        assert_eq!(*lock.try_read().unwrap(), 1);
+    }
+
+    #[test]
+    fn try_upgrade_fails_while_shared() {
+        let lock = RwLock::new(0);
+        let upgradable = lock.try_upgradable_read().unwrap();
+        let r = lock.try_read().unwrap();
+        let upgradable = RwLockUpgradableReadGuard::try_upgrade(upgradable).unwrap_err();
+        drop(r);
+        let w = RwLockUpgradableReadGuard::try_upgrade(upgradable).unwrap();
+        drop(w);
+        assert!(lock.try_write().is_ok());
+    }
+
+    #[test]
+    fn panicking_writer_poisons() {
+        let lock = RwLock::new(0);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _w = lock.try_write().unwrap();
+            panic!("poison");
+        }));
+        assert!(lock.is_poisoned());
+        assert!(matches!(lock.try_read(), Err(TryLockError::Poisoned(_))));
+        lock.clear_poison();
+        assert!(lock.try_read().is_ok());
+    }
+
+    #[test]
+    fn queued_writer_holds_back_new_readers() {
+        let lock = RwLock::new(0);
+        let r = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        let mut read = Box::pin(lock.read());
+        assert!(poll(&mut read).is_pending());
+
+        // The writer goes first, then the reader that queued behind it.
+        drop(r);
+        assert_eq!(count.get(), 1);
+        match write.as_mut().poll(&mut cx) {
+            Poll::Ready(guard) => *guard.unwrap() = 1,
+            Poll::Pending => panic!("the writer was woken but cannot write"),
+        }
+        drop(write);
+        match poll(&mut read) {
+            Poll::Ready(guard) => assert_eq!(*guard.unwrap(), 1),
+            Poll::Pending => panic!("the reader must follow the writer"),
+        };
+    }
+
+    #[test]
+    fn readers_overtake_a_queued_writer_if_not_preferred() {
+        let lock = RwLockBuilder::new().prefer_writers(false).build(0);
+        let r1 = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let r2 = lock.try_read().unwrap();
+        let mut read = Box::pin(lock.read());
+        assert!(poll(&mut read).is_ready());
+        drop(read);
+
+        drop(r1);
+        assert_eq!(count.get(), 0);
+        drop(r2);
+        assert_eq!(count.get(), 1);
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn read_recursive_gets_past_a_queued_writer() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        let r2 = lock.try_read_recursive().unwrap();
+        let r3 = match poll(&mut Box::pin(lock.read_recursive())) {
+            Poll::Ready(guard) => guard.unwrap(),
+            Poll::Pending => panic!("the lock is shared"),
+        };
+
+        // The writer still goes next once every reader has left.
+        drop(r1);
+        drop(r2);
+        assert_eq!(count.get(), 0);
+        drop(r3);
+        assert_eq!(count.get(), 1);
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn read_recursive_waits_for_a_writer() {
+        let lock = RwLock::new(0);
+        let w = lock.try_write().unwrap();
+        assert!(matches!(lock.try_read_recursive(), Err(TryLockError::WouldBlock)));
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut read = Box::pin(lock.read_recursive());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        drop(w);
+        assert_eq!(count.get(), 1);
+        assert!(read.as_mut().poll(&mut cx).is_ready());
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use futures_mutex::sync::{RwLock, RwLockBuilder, RwLockUpgradableReadGuard, RwLockWriteGuard};
+
+    #[test]
+    fn readers_share_and_writers_exclude() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let r2 = lock.try_read().unwrap();
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop((r1, r2));
+        let w = lock.try_write().unwrap();
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        drop(w);
+    }
+
+    #[test]
+    fn writers_and_readers_on_threads() {
+        let lock = Arc::new(RwLock::new(0));
+        let writers = (0..4)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        *block_on(lock.write()).unwrap() += 1;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let readers = (0..4)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..100 {
+                        let value = *block_on(lock.read()).unwrap();
+                        assert!(value >= last);
+                        last = value;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+        assert_eq!(*lock.try_read().unwrap(), 400);
+    }
+
+    #[test]
+    fn cancelled_writer_lets_blocked_readers_in() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let mut write = Box::pin(lock.write());
+        assert!(poll(&mut write).is_pending());
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut read = Box::pin(lock.read());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        drop(write);
+        assert_eq!(count.get(), 1);
+        assert!(read.as_mut().poll(&mut cx).is_ready());
+        drop(r1);
+    }
+
+    #[test]
+    fn downgrade_and_upgrade() {
+        let lock = RwLock::new(0);
+        let mut w = lock.try_write().unwrap();
+        *w = 1;
+        let r = RwLockWriteGuard::downgrade(w);
+        assert!(lock.try_read().is_ok());
+        drop(r);
+
+        let upgradable = lock.try_upgradable_read().unwrap();
+        let r = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut upgrade = Box::pin(RwLockUpgradableReadGuard::upgrade(upgradable));
+        assert!(upgrade.as_mut().poll(&mut cx).is_pending());
+        drop(r);
+        assert_eq!(count.get(), 1);
+        match upgrade.as_mut().poll(&mut cx) {
+            Poll::Ready(mut w) => *w = 2,
+            Poll::Pending => panic!("the upgrade was woken but cannot write"),
+        }
+        drop(upgrade);
+        assert_eq!(*lock.try_read().unwrap(), 2);
+    }
+
+    #[test]
+    fn panicking_writer_poisons() {
+        let lock = Arc::new(RwLock::new(0));
+        let cloned = lock.clone();
+        let _ = thread::spawn(move || {
+            let _w = cloned.try_write().unwrap();
+            panic!("poison");
+        })
+        .join();
+        assert!(lock.is_poisoned());
+        assert!(block_on(lock.read()).is_err());
+    }
+
+    #[test]
+    fn queued_writer_holds_back_new_readers() {
+        let lock = RwLock::new(0);
+        let r = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+
+        drop(r);
+        assert_eq!(count.get(), 1);
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+        drop(write);
+        assert!(lock.try_read().is_ok());
+    }
+
+    #[test]
+    fn readers_overtake_a_queued_writer_if_not_preferred() {
+        let lock = RwLockBuilder::new().prefer_writers(false).build(0);
+        let r1 = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let r2 = lock.try_read().unwrap();
+
+        drop(r1);
+        assert_eq!(count.get(), 0);
+        drop(r2);
+        assert_eq!(count.get(), 1);
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn read_recursive_gets_past_a_queued_writer() {
+        let lock = RwLock::new(0);
+        let r1 = lock.try_read().unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut write = Box::pin(lock.write());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        let r2 = lock.try_read_recursive().unwrap();
+        let r3 = match poll(&mut Box::pin(lock.read_recursive())) {
+            Poll::Ready(guard) => guard.unwrap(),
+            Poll::Pending => panic!("the lock is shared"),
+        };
+
+        // The writer still goes next once every reader has left.
+        drop(r1);
+        drop(r2);
+        assert_eq!(count.get(), 0);
+        drop(r3);
+        assert_eq!(count.get(), 1);
+        assert!(write.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn read_recursive_waits_for_a_writer() {
+        let lock = RwLock::new(0);
+        let w = lock.try_write().unwrap();
+        assert!(matches!(lock.try_read_recursive(), Err(TryLockError::WouldBlock)));
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut read = Box::pin(lock.read_recursive());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        drop(w);
+        assert_eq!(count.get(), 1);
+        assert!(read.as_mut().poll(&mut cx).is_ready());
+    }
+}
@@ -0,0 +1,156 @@
+//! `Semaphore` in both flavors: permit accounting, FIFO order and
+//! cancellation.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_test::task::new_count_waker;
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::Semaphore;
+
+    #[test]
+    fn permits_are_returned_on_drop() {
+        let semaphore = Semaphore::new(3);
+        let two = semaphore.try_acquire(2).unwrap();
+        assert_eq!(semaphore.available_permits(), 1);
+        assert!(semaphore.try_acquire(2).is_none());
+        let one = semaphore.try_acquire(1).unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+        drop((two, one));
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+
+    #[test]
+    fn forgotten_permits_are_gone() {
+        let semaphore = Semaphore::new(2);
+        semaphore.try_acquire(1).unwrap().forget();
+        assert_eq!(semaphore.available_permits(), 1);
+        semaphore.add_permits(2);
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+
+    #[test]
+    fn waiter_is_woken_once_enough_permits_are_back() {
+        let semaphore = Semaphore::new(2);
+        let first = semaphore.try_acquire(1).unwrap();
+        let second = semaphore.try_acquire(1).unwrap();
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut acquire = Box::pin(semaphore.acquire(2));
+        assert!(acquire.as_mut().poll(&mut cx).is_pending());
+
+        drop(first);
+        assert!(acquire.as_mut().poll(&mut cx).is_pending());
+        drop(second);
+        assert!(count.get() >= 1);
+        assert!(matches!(acquire.as_mut().poll(&mut cx), Poll::Ready(_)));
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn large_waiter_is_not_overtaken() {
+        let semaphore = Semaphore::new(1);
+        let mut large = Box::pin(semaphore.acquire(2));
+        assert!(poll(&mut large).is_pending());
+        // One permit is free, but the queued waiter comes first.
+        assert!(semaphore.try_acquire(1).is_none());
+        let mut small = Box::pin(semaphore.acquire(1));
+        assert!(poll(&mut small).is_pending());
+
+        semaphore.add_permits(1);
+        let permit = match poll(&mut large) {
+            Poll::Ready(permit) => permit,
+            Poll::Pending => panic!("two permits are available"),
+        };
+        assert!(poll(&mut small).is_pending());
+        drop(permit);
+        assert!(poll(&mut small).is_ready());
+    }
+
+    #[test]
+    fn cancelled_head_wakes_the_next_waiter() {
+        let semaphore = Semaphore::new(1);
+        let mut large = Box::pin(semaphore.acquire(2));
+        assert!(poll(&mut large).is_pending());
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut small = Box::pin(semaphore.acquire(1));
+        assert!(small.as_mut().poll(&mut cx).is_pending());
+
+        drop(large);
+        assert_eq!(count.get(), 1);
+        assert!(small.as_mut().poll(&mut cx).is_ready());
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use futures::executor::block_on;
+
+    use futures_mutex::sync::Semaphore;
+
+    #[test]
+    fn large_waiter_is_not_overtaken() {
+        let semaphore = Semaphore::new(1);
+        let mut large = Box::pin(semaphore.acquire(2));
+        assert!(poll(&mut large).is_pending());
+        assert!(semaphore.try_acquire(1).is_none());
+
+        semaphore.add_permits(1);
+        assert!(poll(&mut large).is_ready());
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn cancelled_head_wakes_the_next_waiter() {
+        let semaphore = Semaphore::new(1);
+        let mut large = Box::pin(semaphore.acquire(2));
+        assert!(poll(&mut large).is_pending());
+        let (waker, count) = new_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut small = Box::pin(semaphore.acquire(1));
+        assert!(small.as_mut().poll(&mut cx).is_pending());
+
+        drop(large);
+        assert_eq!(count.get(), 1);
+        assert!(small.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn limits_concurrency_across_threads() {
+        let semaphore = Arc::new(Semaphore::new(3));
+        let inside = Arc::new(AtomicUsize::new(0));
+        let handles = (0..8)
+            .map(|_| {
+                let semaphore = semaphore.clone();
+                let inside = inside.clone();
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let _permit = block_on(semaphore.acquire(1));
+                        assert!(inside.fetch_add(1, Ordering::SeqCst) < 3);
+                        thread::yield_now();
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+}
@@ -0,0 +1,123 @@
+//! `Mutex::as_sink` with tasks contending on a sink that is full.
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::task::{Context, Poll, Waker};
+
+use futures::sink::Sink;
+use futures_test::task::new_count_waker;
+
+/// A sink with room for one item, taken out through a clone. Like most
+/// sinks, it only remembers the last task that found it full.
+#[derive(Clone, Default)]
+struct OneSlot {
+    state: Arc<StdMutex<(Option<i32>, Option<Waker>)>>,
+}
+
+impl OneSlot {
+    fn full(item: i32) -> Self {
+        let slot = Self::default();
+        slot.state.lock().unwrap().0 = Some(item);
+        slot
+    }
+
+    fn take(&self) -> Option<i32> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+        state.0.take()
+    }
+}
+
+impl Sink<i32> for OneSlot {
+    type Error = ();
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        let mut state = self.state.lock().unwrap();
+        if state.0.is_some() {
+            state.1 = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
+    fn start_send(self: Pin<&mut Self>, item: i32) -> Result<(), ()> {
+        assert!(self.state.lock().unwrap().0.replace(item).is_none());
+        Ok(())
+    }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::Mutex;
+
+    #[test]
+    fn senders_waiting_for_room_are_all_woken() {
+        let slot = OneSlot::full(0);
+        let mutex = Mutex::new(slot.clone());
+        let (waker1, count1) = new_count_waker();
+        let (waker2, count2) = new_count_waker();
+        let mut cx1 = Context::from_waker(&waker1);
+        let mut cx2 = Context::from_waker(&waker2);
+        let mut sink1 = Box::pin(mutex.as_sink());
+        let mut sink2 = Box::pin(mutex.as_sink());
+        assert!(sink1.as_mut().poll_ready(&mut cx1).is_pending());
+        // The first sender keeps the lock while it waits for room, so the
+        // second cannot replace it as the task the sink wakes.
+        assert!(sink2.as_mut().poll_ready(&mut cx2).is_pending());
+
+        assert_eq!(slot.take(), Some(0));
+        assert_eq!(count1.get(), 1);
+        assert_eq!(sink1.as_mut().poll_ready(&mut cx1), Poll::Ready(Ok(())));
+        sink1.as_mut().start_send(1).unwrap();
+        assert_eq!(count2.get(), 1);
+        assert!(sink2.as_mut().poll_ready(&mut cx2).is_pending());
+
+        assert_eq!(slot.take(), Some(1));
+        assert_eq!(count2.get(), 2);
+        assert_eq!(sink2.as_mut().poll_ready(&mut cx2), Poll::Ready(Ok(())));
+        sink2.as_mut().start_send(2).unwrap();
+        assert_eq!(slot.take(), Some(2));
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use futures_mutex::sync::Mutex;
+
+    #[test]
+    fn senders_waiting_for_room_are_all_woken() {
+        let slot = OneSlot::full(0);
+        let mutex = Mutex::new(slot.clone());
+        let (waker1, count1) = new_count_waker();
+        let (waker2, count2) = new_count_waker();
+        let mut cx1 = Context::from_waker(&waker1);
+        let mut cx2 = Context::from_waker(&waker2);
+        let mut sink1 = Box::pin(mutex.as_sink());
+        let mut sink2 = Box::pin(mutex.as_sink());
+        assert!(sink1.as_mut().poll_ready(&mut cx1).is_pending());
+        // The first sender keeps the lock while it waits for room, so the
+        // second cannot replace it as the task the sink wakes.
+        assert!(sink2.as_mut().poll_ready(&mut cx2).is_pending());
+
+        assert_eq!(slot.take(), Some(0));
+        assert_eq!(count1.get(), 1);
+        assert_eq!(sink1.as_mut().poll_ready(&mut cx1), Poll::Ready(Ok(())));
+        sink1.as_mut().start_send(1).unwrap();
+        assert_eq!(count2.get(), 1);
+        assert!(sink2.as_mut().poll_ready(&mut cx2).is_pending());
+
+        assert_eq!(slot.take(), Some(1));
+        assert_eq!(count2.get(), 2);
+        assert_eq!(sink2.as_mut().poll_ready(&mut cx2), Poll::Ready(Ok(())));
+        sink2.as_mut().start_send(2).unwrap();
+        assert_eq!(slot.take(), Some(2));
+    }
+}
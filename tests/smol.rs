@@ -0,0 +1,80 @@
+//! The mutexes on smol: `unsync` on a `LocalExecutor`, `sync` on an
+//! `Executor` run by several threads.
+
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures::future::join_all;
+use smol::{future, Executor, LocalExecutor, Timer};
+
+use futures_mutex::{sync, unsync};
+
+#[test]
+fn unsync_counter() {
+    let ex = LocalExecutor::new();
+    let mutex = Rc::new(unsync::Mutex::new(0));
+    let tasks = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            ex.spawn(async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    future::yield_now().await;
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    future::block_on(ex.run(join_all(tasks)));
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[test]
+fn sync_counter() {
+    let ex = Arc::new(Executor::new());
+    let mutex = Arc::new(sync::Mutex::new(0));
+    let tasks = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            ex.spawn(async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    future::yield_now().await;
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let (stop, stopped) = smol::channel::unbounded::<()>();
+    let workers = (0..4)
+        .map(|_| {
+            let ex = ex.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || future::block_on(ex.run(stopped.recv())))
+        })
+        .collect::<Vec<_>>();
+    future::block_on(join_all(tasks));
+    drop(stop);
+    for worker in workers {
+        let _ = worker.join().unwrap();
+    }
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[test]
+fn try_lock_for_with_smol_timer() {
+    let mutex = sync::Mutex::new(0);
+    let guard = mutex.try_lock().unwrap();
+    let sleep = |duration| async move {
+        Timer::after(duration).await;
+    };
+    let timed_out = future::block_on(mutex.try_lock_for(Duration::from_millis(10), sleep));
+    assert!(timed_out.is_err());
+    assert_eq!(mutex.waiter_count(), 0);
+    drop(guard);
+    assert!(future::block_on(mutex.try_lock_for(Duration::from_secs(10), sleep)).is_ok());
+}
@@ -0,0 +1,29 @@
+//! What counts as an acquisition in `Mutex::stats`.
+
+mod unsync {
+    use futures_mutex::unsync::Mutex;
+
+    #[test]
+    fn debug_is_not_an_acquisition() {
+        let mutex = Mutex::new(0);
+        let _ = format!("{:?}", mutex);
+        assert_eq!(mutex.stats().acquisitions, 0);
+        drop(mutex.try_lock().unwrap());
+        let _ = format!("{:?}", mutex);
+        assert_eq!(mutex.stats().acquisitions, 1);
+    }
+}
+
+mod sync {
+    use futures_mutex::sync::Mutex;
+
+    #[test]
+    fn debug_is_not_an_acquisition() {
+        let mutex = Mutex::new(0);
+        let _ = format!("{:?}", mutex);
+        assert_eq!(mutex.stats().acquisitions, 0);
+        drop(mutex.try_lock().unwrap());
+        let _ = format!("{:?}", mutex);
+        assert_eq!(mutex.stats().acquisitions, 1);
+    }
+}
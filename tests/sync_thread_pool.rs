@@ -0,0 +1,143 @@
+//! `sync::Mutex` on a multi-threaded executor, where tasks migrate
+//! between worker threads across their polls.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::thread;
+
+use futures::channel::oneshot;
+use futures::executor::{block_on, ThreadPool};
+use futures::future::{join_all, FutureExt, RemoteHandle};
+use futures::task::SpawnExt;
+use futures_test::future::FutureTestExt;
+
+use futures_mutex::sync::{Mutex, MutexBuilder};
+use futures_mutex::LockError;
+
+fn spawn<F>(pool: &ThreadPool, future: F) -> RemoteHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send,
+{
+    pool.spawn_with_handle(future).unwrap()
+}
+
+fn wait_for_waiters<T>(mutex: &Mutex<T>, count: usize) {
+    while mutex.waiter_count() < count {
+        thread::yield_now();
+    }
+}
+
+fn counter(fair: bool) {
+    let pool = ThreadPool::new().unwrap();
+    let mutex = Arc::new(MutexBuilder::new().fair(fair).build(0));
+    let handles = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            spawn(&pool, async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    async {}.pending_once().await;
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    block_on(join_all(handles));
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[test]
+fn counter_fair() {
+    counter(true);
+}
+
+#[test]
+fn counter_unfair() {
+    counter(false);
+}
+
+#[test]
+fn waiters_are_served_in_order() {
+    let pool = ThreadPool::new().unwrap();
+    let mutex = Arc::new(Mutex::new(Vec::new()));
+    let guard = mutex.try_lock().unwrap();
+    let handles = (0..8)
+        .map(|i| {
+            let mutex2 = mutex.clone();
+            let handle = spawn(&pool, async move {
+                mutex2.lock().await.unwrap().push(i);
+            });
+            wait_for_waiters(&mutex, i + 1);
+            handle
+        })
+        .collect::<Vec<_>>();
+    drop(guard);
+    block_on(join_all(handles));
+    assert_eq!(*mutex.try_lock().unwrap(), (0..8).collect::<Vec<_>>());
+}
+
+#[test]
+fn panic_poisons() {
+    let pool = ThreadPool::new().unwrap();
+    let mutex = Arc::new(Mutex::new(0));
+    let guard = mutex.try_lock().unwrap();
+    let panicking = {
+        let mutex = mutex.clone();
+        spawn(
+            &pool,
+            AssertUnwindSafe(async move {
+                let _guard = mutex.lock().await.unwrap();
+                async {}.pending_once().await;
+                panic!("poison");
+            })
+            .catch_unwind(),
+        )
+    };
+    wait_for_waiters(&mutex, 1);
+    let waiting = {
+        let mutex = mutex.clone();
+        spawn(&pool, async move {
+            matches!(mutex.lock_checked().await, Err(LockError::Poisoned(_)))
+        })
+    };
+    wait_for_waiters(&mutex, 2);
+    drop(guard);
+    assert!(block_on(panicking).is_err());
+    assert!(block_on(waiting));
+    assert!(mutex.is_poisoned());
+}
+
+#[test]
+fn cancelled_waiters_pass_the_lock_on() {
+    let pool = ThreadPool::new().unwrap();
+    let mutex = Arc::new(Mutex::new(0));
+    for _ in 0..200 {
+        let guard = mutex.try_lock().unwrap();
+        let (cancel, cancelled) = oneshot::channel::<()>();
+        let cancelling = {
+            let mutex = mutex.clone();
+            spawn(&pool, async move {
+                let cancelled = cancelled.map(|_| ());
+                mutex.lock_cancellable(cancelled).await.is_ok()
+            })
+        };
+        let waiting = {
+            let mutex = mutex.clone();
+            spawn(&pool, async move {
+                *mutex.lock().await.unwrap() += 1;
+            })
+        };
+        wait_for_waiters(&mutex, 1);
+        // Race the release against the cancellation.
+        drop(cancel);
+        drop(guard);
+        block_on(cancelling);
+        block_on(waiting);
+        assert!(!mutex.is_locked());
+        assert_eq!(mutex.waiter_count(), 0);
+    }
+    assert_eq!(*mutex.try_lock().unwrap(), 200);
+}
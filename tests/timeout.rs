@@ -0,0 +1,142 @@
+//! Acquisitions bounded by a delay, in both flavors. The delays here are
+//! plain futures that are either ready or not, standing in for timers.
+
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::{Elapsed, TryLockError};
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+mod unsync {
+    use super::*;
+
+    use futures_mutex::unsync::Mutex;
+
+    #[test]
+    fn acquires_before_the_delay() {
+        let mutex = Mutex::new(0);
+        let mut timeout = Box::pin(mutex.lock_timeout(future::pending()));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Ok(Ok(_)))));
+    }
+
+    #[test]
+    fn elapsed_acquisition_leaves_the_queue() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut timeout = Box::pin(mutex.lock_timeout(future::ready(())));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(Elapsed { .. }))));
+        // As in a `select!` loop, the timed-out future is still alive when
+        // the lock is released, and must not be handed the lock.
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+        drop(timeout);
+    }
+
+    #[test]
+    fn try_lock_for_gives_up_after_the_timeout() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut timeout = Box::pin(mutex.try_lock_for(Duration::from_millis(10), |timeout| {
+            assert_eq!(timeout, Duration::from_millis(10));
+            future::ready(())
+        }));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(TryLockError::WouldBlock))));
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[test]
+    fn try_lock_until_gives_up_at_the_deadline() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let deadline = Instant::now();
+        let mut timeout = Box::pin(mutex.try_lock_until(deadline, |at| {
+            assert_eq!(at, deadline);
+            future::ready(())
+        }));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(TryLockError::WouldBlock))));
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[test]
+    fn try_lock_for_acquires_a_free_lock() {
+        let mutex = Mutex::new(0);
+        let timeout = mutex.try_lock_for(Duration::from_secs(1), |_| future::pending());
+        let mut timeout = Box::pin(timeout);
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Ok(_))));
+    }
+}
+
+mod sync {
+    use super::*;
+
+    use futures_mutex::sync::Mutex;
+
+    #[test]
+    fn acquires_before_the_delay() {
+        let mutex = Mutex::new(0);
+        let mut timeout = Box::pin(mutex.lock_timeout(future::pending()));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Ok(Ok(_)))));
+    }
+
+    #[test]
+    fn elapsed_acquisition_leaves_the_queue() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut timeout = Box::pin(mutex.lock_timeout(future::ready(())));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(Elapsed { .. }))));
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+        drop(timeout);
+    }
+
+    #[test]
+    fn try_lock_for_gives_up_after_the_timeout() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let mut timeout = Box::pin(mutex.try_lock_for(Duration::from_millis(10), |timeout| {
+            assert_eq!(timeout, Duration::from_millis(10));
+            future::ready(())
+        }));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(TryLockError::WouldBlock))));
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[test]
+    fn try_lock_until_gives_up_at_the_deadline() {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        let deadline = Instant::now();
+        let mut timeout = Box::pin(mutex.try_lock_until(deadline, |at| {
+            assert_eq!(at, deadline);
+            future::ready(())
+        }));
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Err(TryLockError::WouldBlock))));
+        drop(guard);
+        assert_eq!(mutex.waiter_count(), 0);
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[test]
+    fn try_lock_for_acquires_a_free_lock() {
+        let mutex = Mutex::new(0);
+        let timeout = mutex.try_lock_for(Duration::from_secs(1), |_| future::pending());
+        let mut timeout = Box::pin(timeout);
+        assert!(matches!(poll(&mut timeout), Poll::Ready(Ok(_))));
+    }
+}
@@ -0,0 +1,82 @@
+//! `sync::Mutex` on tokio's multi-threaded runtime, where a guard held
+//! across an await moves between worker threads along with its task.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task;
+use tokio::time;
+
+use futures_mutex::sync::{Mutex, MutexBuilder};
+
+async fn counter(fair: bool) {
+    let mutex = Arc::new(MutexBuilder::new().fair(fair).build(0));
+    let handles = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            tokio::spawn(async move {
+                for _ in 0..500 {
+                    let mut guard = mutex.lock().await.unwrap();
+                    let value = *guard;
+                    task::yield_now().await;
+                    *guard = value + 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    assert_eq!(*mutex.try_lock().unwrap(), 8 * 500);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn counter_fair() {
+    counter(true).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn counter_unfair() {
+    counter(false).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn timed_out_waiters_leave_the_queue() {
+    let mutex = Arc::new(Mutex::new(0));
+    let guard = mutex.lock().await.unwrap();
+    let handles = (0..8)
+        .map(|_| {
+            let mutex = mutex.clone();
+            tokio::spawn(async move {
+                time::timeout(Duration::from_millis(10), mutex.lock())
+                    .await
+                    .is_err()
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert!(handle.await.unwrap());
+    }
+    assert_eq!(mutex.waiter_count(), 0);
+    drop(guard);
+    assert!(mutex.try_lock().is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn try_lock_for_with_tokio_sleep() {
+    let mutex = Arc::new(Mutex::new(0));
+    let guard = mutex.lock().await.unwrap();
+    let waiting = {
+        let mutex = mutex.clone();
+        tokio::spawn(async move {
+            *mutex
+                .try_lock_for(Duration::from_secs(10), time::sleep)
+                .await
+                .unwrap() += 1;
+        })
+    };
+    time::sleep(Duration::from_millis(10)).await;
+    drop(guard);
+    waiting.await.unwrap();
+    assert_eq!(*mutex.try_lock().unwrap(), 1);
+}
@@ -0,0 +1,123 @@
+//! `unsync::watch`: change notification, subscribers and closing.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_test::task::new_count_waker;
+
+use futures_mutex::unsync::watch;
+
+fn poll<F: Future>(future: &mut Pin<Box<F>>) -> Poll<F::Output> {
+    let (waker, _) = new_count_waker();
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn initial_value_counts_as_seen() {
+    let (tx, mut rx) = watch::channel(0);
+    assert!(!rx.has_changed());
+    assert_eq!(*rx.borrow(), 0);
+    let mut changed = Box::pin(rx.changed());
+    assert!(poll(&mut changed).is_pending());
+    drop(changed);
+    drop(tx);
+}
+
+#[test]
+fn send_wakes_every_receiver() {
+    let (tx, mut rx1) = watch::channel(0);
+    let mut rx2 = rx1.clone();
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut changed1 = Box::pin(rx1.changed());
+    let mut changed2 = Box::pin(rx2.changed());
+    assert!(changed1.as_mut().poll(&mut cx).is_pending());
+    assert!(changed2.as_mut().poll(&mut cx).is_pending());
+
+    let mut send = Box::pin(tx.send(1));
+    assert!(matches!(poll(&mut send), Poll::Ready(Ok(()))));
+    assert_eq!(count.get(), 2);
+    assert_eq!(changed1.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(changed2.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+    drop((changed1, changed2));
+    assert!(!rx1.has_changed());
+    assert_eq!(*rx2.borrow(), 1);
+}
+
+#[test]
+fn only_the_latest_value_is_kept() {
+    let (tx, mut rx) = watch::channel(0);
+    for i in 1..=3 {
+        let mut send = Box::pin(tx.send(i));
+        assert!(poll(&mut send).is_ready());
+    }
+    assert!(rx.has_changed());
+    assert_eq!(*rx.borrow_and_update(), 3);
+    assert!(!rx.has_changed());
+}
+
+#[test]
+fn subscriber_starts_at_the_current_value() {
+    let (tx, _rx) = watch::channel(0);
+    let mut send = Box::pin(tx.send(1));
+    assert!(poll(&mut send).is_ready());
+    let rx = tx.subscribe();
+    assert!(!rx.has_changed());
+    assert_eq!(*rx.borrow(), 1);
+}
+
+#[test]
+fn send_waits_for_read_guards() {
+    let (tx, rx) = watch::channel(0);
+    let guard = rx.borrow();
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut send = Box::pin(tx.send(1));
+    assert!(send.as_mut().poll(&mut cx).is_pending());
+    // New borrows are not held back by the queued send.
+    assert_eq!(*rx.borrow(), 0);
+
+    drop(guard);
+    assert_eq!(count.get(), 1);
+    assert!(matches!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    assert_eq!(*rx.borrow(), 1);
+}
+
+#[test]
+fn dropping_the_sender_closes_the_channel() {
+    let (tx, mut rx) = watch::channel(0);
+    let mut send = Box::pin(tx.send(1));
+    assert!(poll(&mut send).is_ready());
+    drop(send);
+    drop(tx);
+    // The unseen value is still delivered before the error.
+    let mut changed = Box::pin(rx.changed());
+    assert_eq!(poll(&mut changed), Poll::Ready(Ok(())));
+    drop(changed);
+    let mut changed = Box::pin(rx.changed());
+    assert!(matches!(poll(&mut changed), Poll::Ready(Err(_))));
+}
+
+#[test]
+fn dropping_the_sender_wakes_waiting_receivers() {
+    let (tx, mut rx) = watch::channel(0);
+    let (waker, count) = new_count_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut changed = Box::pin(rx.changed());
+    assert!(changed.as_mut().poll(&mut cx).is_pending());
+    drop(tx);
+    assert_eq!(count.get(), 1);
+    assert!(matches!(changed.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+}
+
+#[test]
+fn send_fails_without_receivers() {
+    let (tx, rx) = watch::channel(0);
+    drop(rx);
+    let mut send = Box::pin(tx.send(1));
+    match poll(&mut send) {
+        Poll::Ready(Err(err)) => assert_eq!(err.0, 1),
+        _ => panic!("a send without receivers must fail"),
+    }
+}